@@ -1,24 +1,139 @@
 use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Mutex;
 
+// Taille maximale d'une trame du protocole binaire
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+// Nombre maximal de lignes renvoyées par GREP / GREPRE
+const MAX_GREP_RESULTS: usize = 100;
+
+// Intervalle par défaut entre deux lignes de battement (--heartbeat <secondes>, 0 pour aucune)
+const DEFAULT_HEARTBEAT_SECS: u64 = 60;
+
+// Message du mode --framed : une trame = [longueur u32 big-endian][LogFrame bincode]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LogFrame {
+    seq: u64,
+    level: String,
+    tag: String,
+    message: String,
+}
+
+// Accusé de réception renvoyé pour chaque LogFrame, avec le même numéro de séquence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FrameAck {
+    seq: u64,
+    ok: bool,
+}
+
+// Lit une trame ; Ok(None) si le client a fermé la connexion proprement
+async fn read_frame<R, T>(reader: &mut R) -> Result<Option<T>, Box<dyn std::error::Error + Send + Sync>>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let mut len_bytes = [0u8; 4];
+    match reader.read_exact(&mut len_bytes).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(format!("trame trop grande ({} octets)", len).into());
+    }
+
+    let mut buffer = vec![0u8; len];
+    reader.read_exact(&mut buffer).await?;
+    Ok(Some(bincode::deserialize(&buffer)?))
+}
+
+async fn write_frame<W, T>(writer: &mut W, value: &T) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let data = bincode::serialize(value)?;
+    writer.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    writer.write_all(&data).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+// Destination des entrées : fichier (défaut), sortie standard (--stdout-only) ou les deux (--stdout)
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LogSink {
+    File,
+    Stdout,
+    Both,
+}
+
+impl LogSink {
+    fn to_file(self) -> bool {
+        self != LogSink::Stdout
+    }
+
+    fn to_stdout(self) -> bool {
+        self != LogSink::File
+    }
+}
+
+// Arrête la tâche associée quand son propriétaire disparaît (fin de `run`, arrêt du serveur)
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
 #[derive(Debug)]
 struct LogServer {
     log_file_path: String,
     client_count: Arc<Mutex<u32>>,
+    messages_logged: Arc<Mutex<u64>>, // Messages clients enregistrés depuis le démarrage
+    framed: bool,
+    heartbeat_interval: Option<Duration>,
+    sink: LogSink,
+    // Une entrée est écrite dans toutes ses destinations avant la suivante :
+    // fichier et stdout gardent le même ordre
+    write_lock: Arc<Mutex<()>>,
 }
 
 impl LogServer {
-    fn new(log_file_path: String) -> Self {
+    fn new(log_file_path: String, framed: bool, heartbeat_interval: Option<Duration>, sink: LogSink) -> Self {
         LogServer {
             log_file_path,
             client_count: Arc::new(Mutex::new(0)),
+            messages_logged: Arc::new(Mutex::new(0)),
+            framed,
+            heartbeat_interval,
+            sink,
+            write_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    // Copie partageant les compteurs, pour une tâche de fond
+    fn share(&self) -> Self {
+        LogServer {
+            log_file_path: self.log_file_path.clone(),
+            client_count: Arc::clone(&self.client_count),
+            messages_logged: Arc::clone(&self.messages_logged),
+            framed: self.framed,
+            heartbeat_interval: self.heartbeat_interval,
+            sink: self.sink,
+            write_lock: Arc::clone(&self.write_lock),
         }
     }
 
@@ -28,7 +143,9 @@ impl LogServer {
         }
         self.write_log("SERVER", "Serveur demarre").await?;
         println!("Serveur de logs initialise");
-        println!("Fichier de logs: {}", self.log_file_path);
+        if self.sink.to_file() {
+            println!("Fichier de logs: {}", self.log_file_path);
+        }
         Ok(())
     }
 
@@ -41,17 +158,64 @@ impl LogServer {
             message.trim()
         );
 
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.log_file_path)?;
+        let _guard = self.write_lock.lock().await;
+        self.write_entry(&log_entry, &mut std::io::stdout().lock())?;
+        Ok(())
+    }
+
+    // Écrit une entrée déjà formatée dans chaque destination du sink, `stdout`
+    // tenant lieu de sortie standard
+    fn write_entry<W: Write>(&self, log_entry: &str, stdout: &mut W) -> std::io::Result<()> {
+        if self.sink.to_file() {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.log_file_path)?;
 
-        file.write_all(log_entry.as_bytes())?;
-        file.flush()?;
+            file.write_all(log_entry.as_bytes())?;
+            file.flush()?;
+        }
+
+        if self.sink.to_stdout() {
+            stdout.write_all(log_entry.as_bytes())?;
+            stdout.flush()?;
+        }
 
         Ok(())
     }
 
+    // Message d'un client : enregistré et compté pour le battement
+    async fn log_message(&self, client_id: &str, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_log(client_id, message).await?;
+        *self.messages_logged.lock().await += 1;
+        Ok(())
+    }
+
+    // Ligne SERVER périodique (signe de vie dans le journal lui-même), suivie
+    // d'une synchronisation du fichier sur disque
+    async fn heartbeat(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await; // Le premier tick est immédiat
+        loop {
+            ticker.tick().await;
+            let clients = self.get_client_count().await;
+            let messages = *self.messages_logged.lock().await;
+            let line = format!("Battement: {} client(s) connecte(s), {} message(s) depuis le demarrage", clients, messages);
+            let result = match self.write_log("SERVER", &line).await {
+                Ok(()) if !self.sink.to_file() => Ok(()),
+                Ok(()) => OpenOptions::new()
+                    .append(true)
+                    .open(&self.log_file_path)
+                    .and_then(|file| file.sync_data())
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = result {
+                eprintln!("Erreur battement: {}", e);
+            }
+        }
+    }
+
     async fn increment_client_count(&self) -> u32 {
         let mut count = self.client_count.lock().await;
         *count += 1;
@@ -79,14 +243,40 @@ impl LogServer {
         let client_id = format!("CLIENT-{}", client_addr);
         let client_num = self.increment_client_count().await;
 
-        self.write_log(&client_id, &format!("Connexion client #{}", client_num)).await?;
+        let result = async {
+            self.write_log(&client_id, &format!("Connexion client #{}", client_num)).await?;
+            if self.framed {
+                self.handle_framed_client(stream, &client_id).await
+            } else {
+                self.handle_text_client(stream, &client_id, client_addr).await
+            }
+        }
+        .await
+        .map_err(|e| e.to_string()); // Box<dyn Error> n'est pas Send : pas gardé à travers un await
 
+        // Quelle que soit l'issue du traitement : sinon le compteur (et le
+        // battement qui le publie) garderait des clients fantômes
+        let remaining_clients = self.decrement_client_count().await;
+        self.write_log(&client_id, &format!("Deconnexion. Clients restants: {}", remaining_clients)).await?;
+
+        println!("Client {} deconnecte. Clients restants: {}", client_addr, remaining_clients);
+
+        result.map_err(Into::into)
+    }
+
+    // Mode texte : une ligne = un message
+    async fn handle_text_client(
+        &self,
+        stream: TcpStream,
+        client_id: &str,
+        client_addr: SocketAddr,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let (reader, mut writer) = stream.into_split();
         let reader = BufReader::new(reader);
         let mut lines = reader.lines();
 
         let welcome_msg = format!(
-            "Bienvenue sur le serveur de log - ID: {} - Clients connectes: {}\nTapez vos messages (quitter pour sortir)",
+            "Bienvenue sur le serveur de log - ID: {} - Clients connectes: {}\nTapez vos messages (quitter pour sortir, GREP/GREPRE <motif> pour chercher)",
             client_id, self.get_client_count().await
         );
         let _ = writer.write_all(welcome_msg.as_bytes()).await;
@@ -98,13 +288,37 @@ impl LogServer {
                         continue;
                     }
 
+                    // GREP <texte> / GREPRE <regex> : recherche dans le fichier de logs
+                    if let Some(pattern) = line.trim().strip_prefix("GREPRE ") {
+                        match Regex::new(pattern.trim()) {
+                            Ok(re) => {
+                                if let Err(e) = self.send_matches(&mut writer, |l| re.is_match(l)).await {
+                                    eprintln!("Erreur GREPRE pour {}: {}", client_addr, e);
+                                }
+                            }
+                            Err(e) => {
+                                // Le message de regex tient sur plusieurs lignes : le ramener à une seule
+                                let reason = e.to_string().split_whitespace().collect::<Vec<_>>().join(" ");
+                                let _ = writer.write_all(format!("Erreur: regex invalide ({})\n", reason).as_bytes()).await;
+                            }
+                        }
+                        continue;
+                    }
+                    if let Some(pattern) = line.trim().strip_prefix("GREP ") {
+                        let pattern = pattern.trim();
+                        if let Err(e) = self.send_matches(&mut writer, |l| l.contains(pattern)).await {
+                            eprintln!("Erreur GREP pour {}: {}", client_addr, e);
+                        }
+                        continue;
+                    }
+
                     match line.trim().to_lowercase().as_str() {
                         "quitter" => {
                             let _ = writer.write_all(b"Au revoir\n").await;
                             break;
                         }
                         _ => {
-                            self.write_log(&client_id, &line).await?;
+                            self.log_message(client_id, &line).await?;
                             let _ = writer.write_all(b"Message enregistre\n").await;
                         }
                     }
@@ -113,17 +327,76 @@ impl LogServer {
                     break;
                 }
                 Err(e) => {
-                    self.write_log(&client_id, &format!("Erreur lecture: {}", e)).await?;
+                    self.write_log(client_id, &format!("Erreur lecture: {}", e)).await?;
                     eprintln!("Erreur lecture client {}: {}", client_addr, e);
                     break;
                 }
             }
         }
 
-        let remaining_clients = self.decrement_client_count().await;
-        self.write_log(&client_id, &format!("Deconnexion. Clients restants: {}", remaining_clients)).await?;
+        Ok(())
+    }
 
-        println!("Client {} deconnecte. Clients restants: {}", client_addr, remaining_clients);
+    // Parcourt le fichier ligne par ligne (sans le charger) et renvoie au
+    // client les lignes retenues, au plus MAX_GREP_RESULTS
+    async fn send_matches<W, F>(&self, writer: &mut W, matches: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        W: AsyncWrite + Unpin,
+        F: Fn(&str) -> bool,
+    {
+        if !self.sink.to_file() {
+            writer.write_all(b"Erreur: journal non enregistre dans un fichier (--stdout-only)\n").await?;
+            return Ok(());
+        }
+
+        let file = fs::File::open(&self.log_file_path).await?;
+        let mut lines = BufReader::new(file).lines();
+        let mut count = 0;
+
+        while let Some(line) = lines.next_line().await? {
+            if !matches(&line) {
+                continue;
+            }
+            writer.write_all(line.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            count += 1;
+            if count >= MAX_GREP_RESULTS {
+                writer.write_all(format!("Limite de {} resultats atteinte\n", MAX_GREP_RESULTS).as_bytes()).await?;
+                return Ok(());
+            }
+        }
+
+        writer.write_all(format!("{} resultat(s)\n", count).as_bytes()).await?;
+        Ok(())
+    }
+
+    // Mode --framed : trames bincode { seq, level, tag, message }, chacune acquittée
+    async fn handle_framed_client(
+        &self,
+        stream: TcpStream,
+        client_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (mut reader, mut writer) = stream.into_split();
+
+        loop {
+            let frame: LogFrame = match read_frame(&mut reader).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(e) => {
+                    self.write_log(client_id, &format!("Trame invalide: {}", e)).await?;
+                    eprintln!("Trame invalide de {}: {}", client_id, e);
+                    break;
+                }
+            };
+
+            let entry = format!("[{}] [{}] {}", frame.level, frame.tag, frame.message);
+            let ok = self.log_message(client_id, &entry).await.is_ok();
+
+            if let Err(e) = write_frame(&mut writer, &FrameAck { seq: frame.seq, ok }).await {
+                eprintln!("Erreur envoi accuse de reception a {}: {}", client_id, e);
+                break;
+            }
+        }
 
         Ok(())
     }
@@ -133,18 +406,27 @@ impl LogServer {
 
         let listener = TcpListener::bind(bind_addr).await?;
         println!("Serveur en ecoute sur {}", bind_addr);
-        println!("Les logs sont enregistres dans: {}", self.log_file_path);
+        if self.framed {
+            println!("Mode trames binaires (--framed) actif");
+        }
+        match self.sink {
+            LogSink::File => println!("Les logs sont enregistres dans: {}", self.log_file_path),
+            LogSink::Stdout => println!("Les logs sont ecrits sur la sortie standard"),
+            LogSink::Both => println!("Les logs sont enregistres dans: {} et sur la sortie standard", self.log_file_path),
+        }
         println!("En attente de connexions clients...\n");
 
+        let _heartbeat = self.heartbeat_interval.map(|interval| {
+            let server = self.share();
+            AbortOnDrop(tokio::spawn(async move { server.heartbeat(interval).await }))
+        });
+
         loop {
             match listener.accept().await {
                 Ok((stream, client_addr)) => {
                     println!("Nouvelle connexion de: {}", client_addr);
 
-                    let server_clone = LogServer {
-                        log_file_path: self.log_file_path.clone(),
-                        client_count: Arc::clone(&self.client_count),
-                    };
+                    let server_clone = self.share();
 
                     tokio::spawn(async move {
                         if let Err(e) = server_clone.handle_client(stream, client_addr).await {
@@ -169,8 +451,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let bind_addr = "127.0.0.1:8080";
     let log_file_path = "logs/server.log".to_string();
+    // --framed : protocole binaire à trames préfixées par leur longueur
+    let framed = std::env::args().any(|arg| arg == "--framed");
+    // --heartbeat <secondes> : intervalle des lignes de battement (0 : désactivé)
+    let args: Vec<String> = std::env::args().collect();
+    let heartbeat_secs = args
+        .iter()
+        .position(|arg| arg == "--heartbeat")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_HEARTBEAT_SECS);
+    let heartbeat_interval = (heartbeat_secs > 0).then(|| Duration::from_secs(heartbeat_secs));
+    // --stdout : entrées aussi écrites sur la sortie standard ; --stdout-only : uniquement là
+    let sink = if args.iter().any(|arg| arg == "--stdout-only") {
+        LogSink::Stdout
+    } else if args.iter().any(|arg| arg == "--stdout") {
+        LogSink::Both
+    } else {
+        LogSink::File
+    };
 
-    let server = LogServer::new(log_file_path);
+    let server = LogServer::new(log_file_path, framed, heartbeat_interval, sink);
 
     let server_task = tokio::spawn(async move {
         if let Err(e) = server.run(bind_addr).await {
@@ -191,3 +492,155 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Serveur de logs arrete");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Chemin de journal propre au test, supprimé s'il existait déjà
+    fn test_log_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("journalisation-{}-{}.log", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[tokio::test]
+    async fn grep_returns_only_matching_lines() {
+        let server = LogServer::new(test_log_path("grep"), false, None, LogSink::File);
+        for message in ["disque plein", "connexion ok", "erreur disque 42", "fin"] {
+            server.write_log("CLIENT-test", message).await.unwrap();
+        }
+
+        let mut output = Vec::new();
+        server.send_matches(&mut output, |l| l.contains("disque")).await.unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("[CLIENT-test] disque plein"));
+        assert!(lines[1].ends_with("[CLIENT-test] erreur disque 42"));
+        assert_eq!(lines[2], "2 resultat(s)");
+
+        let re = Regex::new(r"\d+$").unwrap();
+        let mut output = Vec::new();
+        server.send_matches(&mut output, |l| re.is_match(l)).await.unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.lines().next().unwrap().ends_with("erreur disque 42"));
+        assert!(output.ends_with("1 resultat(s)\n"));
+    }
+
+    #[tokio::test]
+    async fn short_interval_writes_a_heartbeat_line() {
+        let path = test_log_path("heartbeat");
+        let server = LogServer::new(path.clone(), false, Some(Duration::from_millis(50)), LogSink::File);
+        server.log_message("CLIENT-test", "un").await.unwrap();
+        server.log_message("CLIENT-test", "deux").await.unwrap();
+
+        let shared = server.share();
+        let heartbeat = AbortOnDrop(tokio::spawn(async move { shared.heartbeat(Duration::from_millis(50)).await }));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        drop(heartbeat);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let beats: Vec<&str> = content.lines().filter(|l| l.contains("[SERVER] Battement")).collect();
+        assert!(!beats.is_empty());
+        assert!(beats[0].ends_with("0 client(s) connecte(s), 2 message(s) depuis le demarrage"));
+    }
+
+    #[tokio::test]
+    async fn client_count_is_released_when_handling_fails() {
+        // Un répertoire comme fichier de journal : toute écriture échoue
+        let server = LogServer::new(std::env::temp_dir().to_string_lossy().into_owned(), false, None, LogSink::File);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, addr) = listener.accept().await.unwrap();
+
+        assert!(server.handle_client(stream, addr).await.is_err());
+        assert_eq!(server.get_client_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn frames_round_trip_over_an_in_memory_stream() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let frames: Vec<LogFrame> = (1..=3)
+            .map(|seq| LogFrame {
+                seq,
+                level: "INFO".to_string(),
+                tag: "test".to_string(),
+                message: format!("message {}", seq),
+            })
+            .collect();
+
+        for frame in &frames {
+            write_frame(&mut client, frame).await.unwrap();
+        }
+        write_frame(&mut client, &FrameAck { seq: 9, ok: false }).await.unwrap();
+        drop(client);
+
+        for frame in &frames {
+            let received: LogFrame = read_frame(&mut server).await.unwrap().unwrap();
+            assert_eq!(&received, frame);
+        }
+        let ack: FrameAck = read_frame(&mut server).await.unwrap().unwrap();
+        assert_eq!(ack, FrameAck { seq: 9, ok: false });
+        // Fermeture propre entre deux trames
+        assert!(read_frame::<_, LogFrame>(&mut server).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn oversized_frame_header_is_rejected() {
+        let header = ((MAX_FRAME_SIZE + 1) as u32).to_be_bytes();
+        let mut reader: &[u8] = &header;
+        assert!(read_frame::<_, LogFrame>(&mut reader).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn framed_client_gets_an_ack_per_frame() {
+        let path = test_log_path("framed");
+        let server = LogServer::new(path.clone(), true, None, LogSink::File);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, addr) = listener.accept().await.unwrap();
+        let handler = tokio::spawn(async move { server.handle_client(stream, addr).await.map_err(|e| e.to_string()) });
+
+        for seq in [7, 8] {
+            let frame = LogFrame { seq, level: "WARN".to_string(), tag: "disque".to_string(), message: "plein".to_string() };
+            write_frame(&mut client, &frame).await.unwrap();
+            let ack: FrameAck = read_frame(&mut client).await.unwrap().unwrap();
+            assert_eq!(ack, FrameAck { seq, ok: true });
+        }
+        drop(client);
+        handler.await.unwrap().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().filter(|l| l.ends_with("[WARN] [disque] plein")).count(), 2);
+    }
+
+    #[test]
+    fn entries_reach_both_stdout_and_the_file() {
+        let path = test_log_path("both");
+        let server = LogServer::new(path.clone(), false, None, LogSink::Both);
+        let mut stdout = Vec::new();
+        server.write_entry("[t] [A] un\n", &mut stdout).unwrap();
+        server.write_entry("[t] [B] deux\n", &mut stdout).unwrap();
+
+        assert_eq!(String::from_utf8(stdout).unwrap(), "[t] [A] un\n[t] [B] deux\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[t] [A] un\n[t] [B] deux\n");
+    }
+
+    #[test]
+    fn stdout_only_sink_leaves_no_file() {
+        let path = test_log_path("stdout-only");
+        let server = LogServer::new(path.clone(), false, None, LogSink::Stdout);
+        let mut stdout = Vec::new();
+        server.write_entry("[t] [A] un\n", &mut stdout).unwrap();
+
+        assert_eq!(stdout, b"[t] [A] un\n");
+        assert!(!std::path::Path::new(&path).exists());
+
+        let server = LogServer::new(path.clone(), false, None, LogSink::File);
+        let mut stdout = Vec::new();
+        server.write_entry("[t] [A] un\n", &mut stdout).unwrap();
+        assert!(stdout.is_empty());
+        assert!(std::path::Path::new(&path).exists());
+    }
+}
@@ -1,25 +1,70 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Nombre d'essais de PIN avant blocage du compte pour la session
+const ESSAIS_PIN_MAX: u32 = 3;
 
 struct Compte {
     nom: String,
     solde: f64,
+    // Le PIN n'est jamais stocké en clair : seulement un sel et le hash (sel + PIN)
+    pin_sel: u64,
+    pin_hash: u64,
+    essais_echoues: u32,
+}
+
+impl Compte {
+    // Crée un compte et hashe son code PIN
+    fn new(nom: &str, solde: f64, pin: &str) -> Compte {
+        let pin_sel = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+            ^ nom.len() as u64;
+        Compte {
+            nom: String::from(nom),
+            solde,
+            pin_sel,
+            pin_hash: hasher_pin(pin_sel, pin),
+            essais_echoues: 0,
+        }
+    }
+
+    fn est_bloque(&self) -> bool {
+        self.essais_echoues >= ESSAIS_PIN_MAX
+    }
+
+    // Vérifie un PIN ; chaque échec est compté, une réussite remet le compteur à zéro
+    fn verifier_pin(&mut self, pin: &str) -> bool {
+        if self.est_bloque() {
+            return false;
+        }
+        if hasher_pin(self.pin_sel, pin) == self.pin_hash {
+            self.essais_echoues = 0;
+            true
+        } else {
+            self.essais_echoues += 1;
+            false
+        }
+    }
+}
+
+// Hash salé d'un code PIN
+fn hasher_pin(sel: u64, pin: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    sel.hash(&mut hasher);
+    pin.hash(&mut hasher);
+    hasher.finish()
 }
 
 fn main() {
-    let mut compte_principal = Compte {
-        nom: String::from("Kevin"),
-        solde: 1500.50,
-    };
+    let mut compte_principal = Compte::new("Kevin", 1500.50, "1234");
     
     let autres_comptes = [
-        Compte {
-            nom: String::from("Marie"),
-            solde: 850.75,
-        },
-        Compte {
-            nom: String::from("Pierre"), 
-            solde: 2200.00,
-        },
+        Compte::new("Marie", 850.75, "0000"),
+        Compte::new("Pierre", 2200.00, "4321"),
     ];
     
     println!("Banque TP1");
@@ -36,7 +81,9 @@ fn main() {
         println!("Veuillez saisir un numéro de votre choix:");
         
         let mut choix = String::new();
-        io::stdin().read_line(&mut choix);
+        if io::stdin().read_line(&mut choix).unwrap_or(0) == 0 {
+            break;
+        }
         
         let choix: usize = match choix.trim().parse() {
             Ok(num) => num,
@@ -49,7 +96,9 @@ fn main() {
         if choix == 1 {
             afficher_solde(&compte_principal);
         } else if choix == 2 {
-            faire_retrait(&mut compte_principal);
+            if demander_pin(&mut compte_principal) {
+                faire_retrait(&mut compte_principal);
+            }
         } else if choix == 3 {
             lister_comptes(&compte_principal, &autres_comptes);
         } else if choix == 4 {
@@ -66,6 +115,29 @@ fn afficher_solde(compte: &Compte) {
     println!("Solde actuel : {:.2}€", compte.solde);
 }
 
+// Fonction pour demander le code PIN avant une opération sensible
+fn demander_pin(compte: &mut Compte) -> bool {
+    if compte.est_bloque() {
+        println!("Compte bloqué : trop d'essais de PIN incorrects");
+        return false;
+    }
+
+    println!("Code PIN :");
+    let mut pin = String::new();
+    let _ = io::stdin().read_line(&mut pin);
+
+    if compte.verifier_pin(pin.trim()) {
+        return true;
+    }
+
+    if compte.est_bloque() {
+        println!("PIN incorrect. Compte bloqué pour cette session");
+    } else {
+        println!("PIN incorrect ({} essai(s) restant(s))", ESSAIS_PIN_MAX - compte.essais_echoues);
+    }
+    false
+}
+
 // Fonction pour faire un retrait sur un compte
 fn faire_retrait(compte: &mut Compte) {
     println!("Retrait");
@@ -73,7 +145,7 @@ fn faire_retrait(compte: &mut Compte) {
     println!("Montant à retirer :");
     
     let mut montant = String::new();
-    io::stdin().read_line(&mut montant);
+    let _ = io::stdin().read_line(&mut montant);
     
     let montant: f64 = match montant.trim().parse() {
         Ok(m) => m,
@@ -93,7 +165,7 @@ fn faire_retrait(compte: &mut Compte) {
         return;
     }
     
-    compte.solde = compte.solde - montant;
+    compte.solde -= montant;
     println!("Retrait de {:.2}€ effectué", montant);
     println!("Nouveau solde : {:.2}€", compte.solde);
 }
@@ -109,4 +181,32 @@ fn lister_comptes(compte_principal: &Compte, autres_comptes: &[Compte]) {
     for (i, compte) in autres_comptes.iter().enumerate() {
         println!("{}. {} - {:.2}€", i + 2, compte.nom, compte.solde);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn correct_pin_unlocks_the_account() {
+        let mut compte = Compte::new("Alice", 100.0, "1234");
+        assert_ne!(compte.pin_hash, hasher_pin(compte.pin_sel, "0000"));
+        assert!(!compte.verifier_pin("0000"));
+        assert_eq!(compte.essais_echoues, 1);
+        // Une réussite remet le compteur à zéro
+        assert!(compte.verifier_pin("1234"));
+        assert_eq!(compte.essais_echoues, 0);
+    }
+
+    #[test]
+    fn three_wrong_pins_lock_the_account() {
+        let mut compte = Compte::new("Bob", 50.0, "4321");
+        for _ in 0..ESSAIS_PIN_MAX {
+            assert!(!compte.est_bloque());
+            assert!(!compte.verifier_pin("0000"));
+        }
+        assert!(compte.est_bloque());
+        // Même le bon PIN est refusé une fois le compte bloqué
+        assert!(!compte.verifier_pin("4321"));
+    }
+}
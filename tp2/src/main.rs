@@ -1,16 +1,54 @@
-use std::fs::{File, OpenOptions, remove_file, metadata};
-use std::io::{self, Write, Read, BufRead, BufReader, stdin, stdout};
-use std::path::Path;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::{self, File, OpenOptions, remove_file};
+use std::io::{self, IsTerminal, Write, Read, BufRead, BufReader, Seek, SeekFrom, stdin, stdout};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Local, TimeZone};
+use filetime::FileTime;
+use fs2::FileExt;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use regex::{NoExpand, Regex};
+use sha2::{Digest, Sha256};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+const TRASH_DIR: &str = ".trash";
+// Fichier compagnon d'une entrée de la corbeille : son chemin d'origine
+const TRASH_ORIGIN_EXT: &str = "origine";
+const MANIFEST_FILE: &str = "MANIFEST.txt";
+// Un même événement répété dans ce délai n'est affiché qu'une fois
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
 
 #[derive(Debug)]
 struct FileManager {
     current_file: Option<String>,
+    hard_delete: bool,
+    // Confirmation automatique des écrasements/suppressions (--yes ou menu),
+    // valable uniquement pour cette exécution
+    assume_yes: bool,
+    // Presse-papiers de lignes, conservé entre deux choix du menu
+    clipboard: Option<Clipboard>,
+}
+
+// Lignes copiées ou coupées, en attente de collage
+#[derive(Debug)]
+struct Clipboard {
+    lines: Vec<String>,
+    // Pour une coupe : fichier et plage (numéros de 1 à n, inclus) à retirer au collage
+    cut_from: Option<(String, usize, usize)>,
 }
 
 impl FileManager {
-    fn new() -> Self {
+    fn new(hard_delete: bool, assume_yes: bool) -> Self {
         FileManager {
             current_file: None,
+            hard_delete,
+            assume_yes,
+            clipboard: None,
         }
     }
 
@@ -23,6 +61,26 @@ impl FileManager {
         println!("5. Supprimer un fichier");
         println!("6. Lister les fichiers du répertoire");
         println!("7. Informations sur le fichier courant");
+        println!("8. Corbeille (lister / restaurer / vider)");
+        println!("9. Afficher un fichier en hexadécimal");
+        println!("10. Concaténer des fichiers");
+        println!("11. Découper un fichier en morceaux");
+        println!("12. Joindre des morceaux");
+        println!("13. Confirmation automatique (actuellement: {})", if self.assume_yes { "oui" } else { "non" });
+        println!("14. Comparer avec la sauvegarde (.bak)");
+        println!("15. Toucher un fichier (créer vide / mettre à jour la date)");
+        println!("16. Copier / couper des lignes");
+        println!("17. Coller les lignes");
+        println!("18. Rechercher / remplacer dans plusieurs fichiers");
+        println!("19. Supprimer un répertoire");
+        println!("20. Fins de ligne et BOM (analyser / normaliser)");
+        println!("21. Lire avec coloration syntaxique");
+        println!("22. Liens symboliques (afficher / résoudre / créer)");
+        println!("23. Manifeste du répertoire (générer / vérifier)");
+        println!("24. Horodater un fichier");
+        println!("25. Remplacer une plage d'octets");
+        println!("26. Surveiller le répertoire");
+        println!("27. Compter les occurrences");
         println!("0. Quitter");
         
         if let Some(ref file) = self.current_file {
@@ -39,18 +97,11 @@ impl FileManager {
         // Vérifier si le fichier existe déjà
         if Path::new(&filename).exists() {
             println!("Le fichier {} existe déjà!", filename);
-            println!("Voulez-vous l'écraser ? (oui/non)");
-            let confirmation = self.get_input("");
-            
-            match confirmation.trim().to_lowercase().as_str() {
-                "oui" | "o" | "yes" | "y" => {
-                    println!("Le fichier existant sera écrasé.");
-                }
-                _ => {
-                    println!("Création annulée.");
-                    return;
-                }
+            if !self.confirm("Voulez-vous l'écraser ?") {
+                println!("Création annulée.");
+                return;
             }
+            println!("Le fichier existant sera écrasé.");
         }
 
         match File::create(&filename) {
@@ -91,15 +142,39 @@ impl FileManager {
     }
 
     fn read_file(&mut self) {
-        let filename = self.get_filename("Nom du fichier à lire");
+        let filename = self.get_existing_filename("Nom du fichier à lire");
+        
+        // Aperçu mis en forme des fichiers structurés, détecté par l'extension
+        let detected = PreviewFormat::from_extension(&filename);
+        let choice = self.get_input(&format!(
+            "Affichage (texte, json, csv ; vide pour {})",
+            detected.map_or("texte", PreviewFormat::name)
+        ));
+        let format = match choice.to_lowercase().as_str() {
+            "" => detected,
+            "json" => Some(PreviewFormat::Json),
+            "csv" => Some(PreviewFormat::Csv),
+            _ => None,
+        };
+        
+        if let Some(format) = format {
+            match fs::read_to_string(&filename).map_err(|e| e.to_string()).and_then(|content| format.pretty(&content)) {
+                Ok(pretty) => {
+                    println!("\n--- Contenu de {} ({}) ---", filename, format.name());
+                    println!("{}", pretty);
+                    self.current_file = Some(filename.clone());
+                    return;
+                }
+                Err(e) => println!("Attention: {} invalide ({}), affichage en texte brut", format.name(), e),
+            }
+        }
         
         match File::open(&filename) {
             Ok(file) => {
                 let reader = BufReader::new(file);
                 println!("\n--- Contenu de {} ---", filename);
                 
-                let mut line_number = 1;
-                for line in reader.lines() {
+                for (line_number, line) in (1..).zip(reader.lines()) {
                     match line {
                         Ok(content) => println!("{:3}: {}", line_number, content),
                         Err(e) => {
@@ -107,7 +182,6 @@ impl FileManager {
                             break;
                         }
                     }
-                    line_number += 1;
                 }
                 
                 self.current_file = Some(filename.clone());
@@ -116,6 +190,32 @@ impl FileManager {
         }
     }
 
+    // Langage deviné par l'extension ; texte brut si inconnu ou hors terminal
+    fn read_highlighted(&mut self) {
+        let filename = self.get_existing_filename("Nom du fichier à lire");
+        let content = match fs::read_to_string(&filename) {
+            Ok(content) => content,
+            Err(e) => {
+                println!("Erreur lors de la lecture: {}", e);
+                return;
+            }
+        };
+        self.current_file = Some(filename.clone());
+
+        let language = Path::new(&filename).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let highlighted = if stdout().is_terminal() { highlight(&content, language) } else { None };
+        match highlighted {
+            Some(highlighted) => {
+                println!("\n--- Contenu de {} ({}) ---", filename, language);
+                println!("{}", highlighted);
+            }
+            None => {
+                println!("\n--- Contenu de {} (sans coloration) ---", filename);
+                println!("{}", content);
+            }
+        }
+    }
+
     fn write_file(&mut self) {
         let filename = self.get_filename("Nom du fichier à écrire");
         
@@ -125,15 +225,25 @@ impl FileManager {
         
         let mode = self.get_input("Votre choix (1-2)");
         
-        let file_result = match mode.trim() {
-            "1" => File::create(&filename),
-            "2" => OpenOptions::new().create(true).append(true).open(&filename),
+        let mut options = OpenOptions::new();
+        match mode.trim() {
+            "1" => options.create(true).write(true),
+            "2" => options.create(true).append(true),
             _ => {
                 println!("Choix invalide!");
                 return;
             }
         };
 
+        // Verrouiller avant de tronquer, pour ne pas effacer le travail d'une autre instance
+        let file_result = options.open(&filename).and_then(|file| {
+            lock_exclusive(&file, &filename)?;
+            if mode.trim() == "1" {
+                file.set_len(0)?;
+            }
+            Ok(file)
+        });
+
         match file_result {
             Ok(mut file) => {
                 println!("Entrez le contenu (tapez 'EOF' sur une ligne vide pour terminer):");
@@ -155,27 +265,34 @@ impl FileManager {
                     }
                     Err(e) => println!("Erreur lors de l'écriture: {}", e),
                 }
+                let _ = FileExt::unlock(&file);
             }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => println!("Attention: {}", e),
             Err(e) => println!("Erreur lors de l'ouverture du fichier: {}", e),
         }
     }
 
     fn modify_file(&mut self) {
-        let filename = self.get_filename("Nom du fichier à modifier");
+        let filename = self.get_existing_filename("Nom du fichier à modifier");
         
-        // Lire le contenu existant
-        let mut content = String::new();
-        match File::open(&filename) {
-            Ok(mut file) => {
-                if let Err(e) = file.read_to_string(&mut content) {
-                    println!("Erreur lors de la lecture: {}", e);
-                    return;
-                }
-            }
+        // Verrou conservé pendant toute la lecture-modification-écriture
+        let mut file = match OpenOptions::new().read(true).write(true).open(&filename) {
+            Ok(file) => file,
             Err(e) => {
                 println!("Erreur lors de l'ouverture: {}", e);
                 return;
             }
+        };
+        if let Err(e) = lock_exclusive(&file, &filename) {
+            println!("Attention: {}", e);
+            return;
+        }
+
+        // Lire le contenu existant
+        let mut content = String::new();
+        if let Err(e) = file.read_to_string(&mut content) {
+            println!("Erreur lors de la lecture: {}", e);
+            return;
         }
 
         println!("\n--- Contenu actuel ---");
@@ -235,162 +352,2403 @@ impl FileManager {
             }
         }
 
-        // Écrire le contenu modifié
-        match File::create(&filename) {
-            Ok(mut file) => {
-                let new_content = new_lines.join("\n") + "\n";
-                if let Err(e) = file.write_all(new_content.as_bytes()) {
-                    println!("Erreur lors de l'écriture: {}", e);
-                } else {
-                    println!("Fichier modifié avec succès!");
-                    self.current_file = Some(filename.clone());
-                }
+        // Sauvegarde du contenu d'origine, pour pouvoir comparer ensuite
+        if let Err(e) = fs::write(backup_path(&filename), &content) {
+            println!("Attention: sauvegarde impossible ({}), modification annulée", e);
+            let _ = FileExt::unlock(&file);
+            return;
+        }
+
+        // Écrire le contenu modifié dans le fichier toujours verrouillé
+        let new_content = new_lines.join("\n") + "\n";
+        let result = file
+            .set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(new_content.as_bytes()));
+        match result {
+            Ok(_) => {
+                println!("Fichier modifié avec succès!");
+                self.current_file = Some(filename.clone());
             }
-            Err(e) => println!("Erreur lors de la création du fichier: {}", e),
+            Err(e) => println!("Erreur lors de l'écriture: {}", e),
         }
+        let _ = FileExt::unlock(&file);
     }
 
     fn delete_file(&mut self) {
-        let filename = self.get_filename("Nom du fichier à supprimer");
-        
-        if !Path::new(&filename).exists() {
-            println!("Le fichier {} n'existe pas!", filename);
-            return;
+        let filename = self.get_existing_filename("Nom du fichier à supprimer");
+        self.delete_path(filename);
+    }
+
+    // Suppression commune au menu et à la sous-commande `delete` ; renvoie
+    // false si le fichier n'a pas été supprimé
+    fn delete_path(&mut self, filename: String) -> bool {
+        match fs::symlink_metadata(&filename) {
+            Ok(meta) if meta.is_dir() => {
+                println!("{} est un répertoire (option 19 pour un répertoire)", filename);
+                return false;
+            }
+            Ok(_) => {}
+            Err(_) => {
+                println!("Le fichier {} n'existe pas!", filename);
+                return false;
+            }
         }
 
-        println!("Êtes-vous sûr de vouloir supprimer {} ? (oui/non)", filename);
-        let confirmation = self.get_input("");
-        
-        match confirmation.trim().to_lowercase().as_str() {
-            "oui" | "o" | "yes" | "y" => {
-                match remove_file(&filename) {
-                    Ok(_) => {
-                        println!("Fichier {} supprimé avec succès!", filename);
-                        if let Some(ref current) = self.current_file {
-                            if current == &filename {
-                                self.current_file = None;
-                            }
-                        }
-                    }
-                    Err(e) => println!("Erreur lors de la suppression: {}", e),
+        if !self.confirm(&format!("Êtes-vous sûr de vouloir supprimer {} ?", filename)) {
+            println!("Suppression annulée.");
+            return false;
+        }
+
+        let result = if self.hard_delete {
+            remove_file(&filename)
+        } else {
+            move_to_trash(Path::new(TRASH_DIR), Path::new(&filename)).map(|_| ())
+        };
+
+        match result {
+            Ok(_) => {
+                if self.hard_delete {
+                    println!("Fichier {} supprimé avec succès!", filename);
+                } else {
+                    println!("Fichier {} déplacé dans la corbeille ({})", filename, TRASH_DIR);
                 }
+                if self.current_file.as_ref() == Some(&filename) {
+                    self.current_file = None;
+                }
+                true
+            }
+            Err(e) => {
+                println!("Erreur lors de la suppression: {}", e);
+                false
             }
-            _ => println!("Suppression annulée."),
         }
     }
 
-    fn list_files(&self) {
-        println!("\n--- Fichiers du répertoire courant ---");
-        
-        match std::fs::read_dir(".") {
-            Ok(entries) => {
-                let mut files = Vec::new();
-                let mut dirs = Vec::new();
-                
-                for entry in entries {
-                    if let Ok(entry) = entry {
-                        let path = entry.path();
-                        let name = path.file_name().unwrap().to_string_lossy().to_string();
-                        
-                        if path.is_dir() {
-                            dirs.push(name);
-                        } else {
-                            files.push(name);
-                        }
+    fn manage_trash(&mut self) {
+        let entries = match list_trash(Path::new(TRASH_DIR)) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("Erreur lors de la lecture de la corbeille: {}", e);
+                return;
+            }
+        };
+
+        println!("\n--- Corbeille ---");
+        if entries.is_empty() {
+            println!("La corbeille est vide.");
+            return;
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            let name = entry.file_name().unwrap().to_string_lossy();
+            println!("{:3}. {} (origine: {})", i + 1, name, original_path(entry).display());
+        }
+
+        println!("\n1. Restaurer un fichier");
+        println!("2. Vider la corbeille définitivement");
+        println!("0. Retour");
+
+        match self.get_input("Votre choix (0-2)").as_str() {
+            "1" => {
+                let num = self.get_input("Numéro du fichier à restaurer");
+                let entry = match num.parse::<usize>() {
+                    Ok(n) if n > 0 && n <= entries.len() => &entries[n - 1],
+                    _ => {
+                        println!("Numéro invalide!");
+                        return;
                     }
+                };
+
+                let target = original_path(entry);
+                if target.exists() {
+                    println!("Le fichier {} existe déjà! Restauration annulée.", target.display());
+                    return;
                 }
-                
-                dirs.sort();
-                files.sort();
-                
-                if !dirs.is_empty() {
-                    println!("\nRépertoires:");
-                    for dir in dirs {
-                        println!("  [DIR]  {}", dir);
-                    }
+
+                match restore_from_trash(entry, &target) {
+                    Ok(_) => println!("Fichier {} restauré avec succès!", target.display()),
+                    Err(e) => println!("Erreur lors de la restauration: {}", e),
                 }
-                
-                if !files.is_empty() {
-                    println!("\nFichiers:");
-                    for file in files {
-                        println!("  [FILE] {}", file);
-                    }
+            }
+            "2" => {
+                if !self.confirm(&format!("Supprimer définitivement {} fichier(s) ?", entries.len())) {
+                    println!("Vidage annulé.");
+                    return;
+                }
+                match fs::remove_dir_all(TRASH_DIR) {
+                    Ok(_) => println!("Corbeille vidée."),
+                    Err(e) => println!("Erreur lors du vidage de la corbeille: {}", e),
                 }
             }
-            Err(e) => println!("Erreur lors de la lecture du répertoire: {}", e),
+            _ => {}
         }
     }
 
-    fn show_file_info(&self) {
-        let filename = match &self.current_file {
-            Some(file) => file.clone(),
-            None => self.get_filename("Nom du fichier pour les informations"),
+    fn hexdump_file(&mut self) {
+        let filename = self.get_existing_filename("Nom du fichier à afficher");
+
+        let mut file = match File::open(&filename) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Erreur lors de l'ouverture du fichier: {}", e);
+                return;
+            }
         };
 
-        match metadata(&filename) {
-            Ok(meta) => {
-                println!("\n--- Informations sur {} ---", filename);
-                println!("Taille: {} octets", meta.len());
-                println!("Lecture seule: {}", meta.permissions().readonly());
-                println!("Type: {}", if meta.is_dir() { "Répertoire" } else { "Fichier" });
-                
-                if let Ok(modified) = meta.modified() {
-                    println!("Dernière modification: {:?}", modified);
+        // Plage d'octets à afficher, pour éviter de vider un énorme fichier
+        let start = self.get_input("Offset de départ (défaut 0)").parse::<u64>().unwrap_or(0);
+        let length = self.get_input("Nombre d'octets (défaut 256)").parse::<u64>().unwrap_or(256);
+
+        let mut bytes = Vec::new();
+        let result = file
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| file.take(length).read_to_end(&mut bytes));
+        if let Err(e) = result {
+            println!("Erreur lors de la lecture: {}", e);
+            return;
+        }
+
+        println!("\n--- Hexdump de {} ---", filename);
+        if bytes.is_empty() {
+            println!("(aucun octet dans cette plage)");
+        }
+        for (i, chunk) in bytes.chunks(16).enumerate() {
+            println!("{}", hexdump_line(start as usize + i * 16, chunk));
+        }
+
+        self.current_file = Some(filename);
+    }
+
+    fn concat_files(&mut self) {
+        let inputs: Vec<String> = self
+            .get_input("Fichiers à concaténer (séparés par des espaces)")
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .collect();
+        if inputs.is_empty() {
+            println!("Aucun fichier indiqué!");
+            return;
+        }
+
+        let output = self.get_filename("Fichier de sortie");
+        if inputs.contains(&output) {
+            println!("Le fichier de sortie ne peut pas être aussi une entrée!");
+            return;
+        }
+
+        let separator = self.get_input("Ligne de séparation (vide pour aucune)");
+        let separator = if separator.is_empty() { None } else { Some(separator.as_str()) };
+
+        match concat_files(&inputs, &output, separator) {
+            Ok(total) => {
+                println!("{} fichier(s) concaténé(s) dans {} ({} octets)", inputs.len(), output, total);
+                self.current_file = Some(output.clone());
+            }
+            Err(e) => println!("Erreur lors de la concaténation: {}", e),
+        }
+    }
+
+    fn split_file(&mut self) {
+        let filename = self.get_existing_filename("Fichier à découper");
+        let size = self.get_input("Taille des morceaux (octets, ou suffixe K pour KiB)");
+        let Some(chunk_size) = parse_chunk_size(&size) else {
+            println!("Taille invalide!");
+            return;
+        };
+
+        match split_file(&filename, chunk_size) {
+            Ok(parts) => {
+                println!("{} découpé en {} morceau(x) de {} octets max", filename, parts, chunk_size);
+                // Empreinte conservée pour vérifier la reconstitution
+                match sha256_file(Path::new(&filename)) {
+                    Ok(hash) => match fs::write(format!("{}.sha256", filename), &hash) {
+                        Ok(_) => println!("SHA-256: {}", hash),
+                        Err(e) => println!("Erreur lors de l'écriture de l'empreinte: {}", e),
+                    },
+                    Err(e) => println!("Erreur lors du calcul de l'empreinte: {}", e),
                 }
             }
-            Err(e) => println!("Erreur lors de la récupération des métadonnées: {}", e),
+            Err(e) => println!("Erreur lors du découpage: {}", e),
         }
     }
 
-    fn get_filename(&self, prompt: &str) -> String {
-        self.get_input(prompt)
+    fn join_parts(&mut self) {
+        let base = self.get_filename("Nom du fichier d'origine (sans .partNNN)");
+        let output = self.get_input("Fichier de sortie (vide pour le nom d'origine)");
+        let output = if output.is_empty() { base.clone() } else { output };
+
+        let total = match join_parts(&base, &output) {
+            Ok(total) => total,
+            Err(e) => {
+                println!("Erreur lors de la reconstitution: {}", e);
+                return;
+            }
+        };
+        println!("{} reconstitué ({} octets)", output, total);
+        self.current_file = Some(output.clone());
+
+        let hash_path = format!("{}.sha256", base);
+        if !Path::new(&hash_path).is_file() {
+            return;
+        }
+        let answer = self.get_input("Vérifier l'intégrité avec SHA-256 ? (o/n)");
+        if !answer.eq_ignore_ascii_case("o") {
+            return;
+        }
+        match (fs::read_to_string(&hash_path), sha256_file(Path::new(&output))) {
+            (Ok(expected), Ok(actual)) if expected.trim() == actual => println!("Intégrité vérifiée"),
+            (Ok(_), Ok(_)) => println!("Attention: l'empreinte ne correspond pas, fichier corrompu!"),
+            (Err(e), _) | (_, Err(e)) => println!("Erreur lors de la vérification: {}", e),
+        }
     }
 
-    fn get_input(&self, prompt: &str) -> String {
-        if !prompt.is_empty() {
-            print!("{}: ", prompt);
-            stdout().flush().unwrap();
+    // Suppression récursive définitive (pas de corbeille) : la confirmation
+    // demande de retaper le nom, même avec la confirmation automatique
+    fn delete_directory(&mut self) {
+        let dirname = self.get_existing_filename("Répertoire à supprimer");
+        let path = Path::new(&dirname);
+
+        match fs::symlink_metadata(path) {
+            Ok(meta) if meta.is_dir() => {}
+            Ok(_) => {
+                println!("{} n'est pas un répertoire (option 5 pour un fichier)", dirname);
+                return;
+            }
+            Err(e) => {
+                println!("Erreur: {}", e);
+                return;
+            }
+        }
+
+        if is_protected_dir(path) {
+            println!("Refusé: {} est le répertoire de travail ou l'un de ses parents", dirname);
+            return;
+        }
+
+        let (files, dirs) = match count_tree(path) {
+            Ok(counts) => counts,
+            Err(e) => {
+                println!("Erreur lors du parcours de {}: {}", dirname, e);
+                return;
+            }
+        };
+        println!("{} sera supprimé définitivement avec {} fichier(s) et {} sous-répertoire(s).", dirname, files, dirs);
+        let confirmation = self.get_input("Retapez le nom du répertoire pour confirmer");
+
+        match delete_tree(path, &confirmation) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => println!("Suppression annulée."),
+            Ok(()) => {
+                println!("Répertoire {} supprimé", dirname);
+                // Le fichier courant a pu disparaître avec le répertoire
+                if self.current_file.as_ref().is_some_and(|file| !Path::new(file).exists()) {
+                    self.current_file = None;
+                }
+            }
+            Err(e) => println!("Erreur lors de la suppression: {}", e),
         }
-        
-        let mut input = String::new();
-        stdin().read_line(&mut input).expect("Erreur lors de la lecture");
-        input.trim().to_string()
     }
 
-    fn run(&mut self) {
-        loop {
-            self.display_menu();
-            
-            let mut input = String::new();
-            if stdin().read_line(&mut input).is_err() {
-                println!("Erreur lors de la lecture de l'entrée.");
-                continue;
+    fn line_endings(&mut self) {
+        let filename = match &self.current_file {
+            Some(file) => file.clone(),
+            None => self.get_existing_filename("Fichier à analyser"),
+        };
+        let bytes = match fs::read(&filename) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Erreur lors de la lecture: {}", e);
+                return;
             }
+        };
 
-            match input.trim() {
-                "1" => self.create_file(),
-                "2" => self.read_file(),
-                "3" => self.write_file(),
-                "4" => self.modify_file(),
-                "5" => self.delete_file(),
-                "6" => self.list_files(),
-                "7" => self.show_file_info(),
-                "0" => {
-                    println!("Au revoir!");
-                    break;
+        let report = analyze_line_endings(&bytes);
+        println!("\n--- Fins de ligne de {} ---", filename);
+        println!("BOM: {}", report.bom.map_or("aucun", Bom::name));
+        if matches!(report.bom, Some(Bom::Utf16Le | Bom::Utf16Be)) {
+            println!("Fichier UTF-16 : analyse des fins de ligne non prise en charge");
+            return;
+        }
+        println!("Fins de ligne: {} ({} LF, {} CRLF)", report.style(), report.lf, report.crlf);
+        println!("Saut de ligne final: {}", if report.trailing_newline { "oui" } else { "non" });
+
+        println!("\n1. Convertir en LF");
+        println!("2. Convertir en CRLF");
+        println!("0. Retour");
+        let target = match self.get_input("Votre choix (0-2)").as_str() {
+            "1" => LineEnding::Lf,
+            "2" => LineEnding::Crlf,
+            _ => return,
+        };
+
+        let normalized = normalize_line_endings(&bytes, target);
+        if normalized == bytes {
+            println!("Rien à convertir.");
+            return;
+        }
+        if let Err(e) = fs::write(backup_path(&filename), &bytes) {
+            println!("Attention: sauvegarde impossible ({}), conversion annulée", e);
+            return;
+        }
+        match fs::write(&filename, normalized) {
+            Ok(()) => println!("{} converti (original dans {})", filename, backup_path(&filename)),
+            Err(e) => println!("Erreur lors de l'écriture: {}", e),
+        }
+    }
+
+    fn symlinks(&mut self) {
+        let path = self.get_input("Chemin à examiner");
+        println!("{}", describe_symlink(&path));
+
+        println!("\n1. Résoudre le chemin complet");
+        if cfg!(unix) {
+            println!("2. Créer un lien symbolique vers ce chemin");
+        }
+        println!("0. Retour");
+        match self.get_input("Votre choix").as_str() {
+            "1" => println!("{}", resolve_symlink(&path)),
+            #[cfg(unix)]
+            "2" => {
+                let link = self.get_filename("Nom du lien à créer");
+                // symlink_metadata : un lien cassé existant compte aussi
+                if fs::symlink_metadata(&link).is_ok() {
+                    println!("{} existe déjà!", link);
+                    return;
+                }
+                match std::os::unix::fs::symlink(&path, &link) {
+                    Ok(()) => println!("Lien {} -> {} créé", link, path),
+                    Err(e) => println!("Erreur lors de la création du lien: {}", e),
                 }
-                _ => println!("Choix invalide! Veuillez choisir entre 0 et 7."),
             }
+            _ => {}
+        }
+    }
 
-            // Pause pour permettre à l'utilisateur de lire les résultats
-            println!("\nAppuyez sur Entrée pour continuer...");
-            let mut pause = String::new();
-            stdin().read_line(&mut pause).unwrap();
+    fn manifest(&mut self) {
+        println!("\n1. Générer {} (chemin, taille et SHA-256 de chaque fichier)", MANIFEST_FILE);
+        println!("2. Vérifier le répertoire par rapport à {}", MANIFEST_FILE);
+        println!("0. Retour");
+        match self.get_input("Votre choix (0-2)").as_str() {
+            "1" => {
+                if Path::new(MANIFEST_FILE).exists()
+                    && !self.confirm(&format!("{} existe déjà. L'écraser ?", MANIFEST_FILE)) {
+                    println!("Génération annulée.");
+                    return;
+                }
+                match build_manifest(Path::new(".")).and_then(|entries| {
+                    write_manifest(Path::new(MANIFEST_FILE), &entries)?;
+                    Ok(entries.len())
+                }) {
+                    Ok(count) => println!("{} écrit ({} fichier(s))", MANIFEST_FILE, count),
+                    Err(e) => println!("Erreur lors de la génération: {}", e),
+                }
+            }
+            "2" => match verify_manifest(Path::new("."), Path::new(MANIFEST_FILE)) {
+                Ok(diff) if diff.is_empty() => println!("Aucune différence avec {}", MANIFEST_FILE),
+                Ok(diff) => {
+                    for path in &diff.added {
+                        println!("  + {} (ajouté)", path);
+                    }
+                    for path in &diff.removed {
+                        println!("  - {} (supprimé)", path);
+                    }
+                    for path in &diff.changed {
+                        println!("  ~ {} (modifié)", path);
+                    }
+                }
+                Err(e) => println!("Erreur lors de la vérification: {}", e),
+            },
+            _ => {}
         }
     }
-}
 
-fn main() {
-    let mut file_manager = FileManager::new();
-    file_manager.run();
-}
\ No newline at end of file
+    fn patch_bytes(&mut self) {
+        let filename = self.get_existing_filename("Fichier à modifier");
+        let offset = self.get_input("Offset de départ").parse::<u64>();
+        let length = self.get_input("Nombre d'octets à remplacer").parse::<u64>();
+        let (Ok(offset), Ok(length)) = (offset, length) else {
+            println!("Offset ou longueur invalide!");
+            return;
+        };
+
+        println!("1. Octets en hexadécimal (ex: 0a ff 00)");
+        println!("2. Texte");
+        let replacement = match self.get_input("Format (1-2)").as_str() {
+            "1" => match parse_hex(&self.get_input("Octets")) {
+                Some(bytes) => bytes,
+                None => {
+                    println!("Hexadécimal invalide!");
+                    return;
+                }
+            },
+            "2" => self.get_input("Texte").into_bytes(),
+            _ => {
+                println!("Choix invalide!");
+                return;
+            }
+        };
+
+        match patch_range(Path::new(&filename), offset, length, &replacement) {
+            Ok(()) => {
+                println!("{} octet(s) remplacé(s) par {} octet(s) à l'offset {}", length, replacement.len(), offset);
+                self.current_file = Some(filename);
+            }
+            Err(e) => println!("Erreur: {}", e),
+        }
+    }
+
+    fn watch_directory(&mut self) {
+        // Dernier affichage de chaque ligne, pour écarter les doublons rapprochés
+        let mut last_seen: HashMap<String, Instant> = HashMap::new();
+        let watcher = notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+            Ok(event) => {
+                let now = Instant::now();
+                last_seen.retain(|_, seen| now.duration_since(*seen) < WATCH_DEBOUNCE);
+                for line in format_event(&event) {
+                    if let Entry::Vacant(entry) = last_seen.entry(line) {
+                        println!("{}", entry.key());
+                        entry.insert(now);
+                    }
+                }
+            }
+            Err(e) => println!("Erreur de surveillance: {}", e),
+        });
+        let mut watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                println!("Surveillance impossible: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = watcher.watch(Path::new("."), RecursiveMode::NonRecursive) {
+            println!("Surveillance impossible: {}", e);
+            return;
+        }
+
+        println!("Surveillance du répertoire courant (Entrée pour arrêter)...");
+        let mut line = String::new();
+        let _ = stdin().read_line(&mut line);
+        println!("Surveillance arrêtée");
+    }
+
+    fn count_occurrences(&mut self) {
+        println!("1. Texte littéral");
+        println!("2. Expression régulière");
+        let literal = match self.get_input("Votre choix (1-2)").as_str() {
+            "1" => true,
+            "2" => false,
+            _ => {
+                println!("Choix invalide!");
+                return;
+            }
+        };
+        let search = self.get_input("Rechercher");
+        if search.is_empty() {
+            println!("Motif vide!");
+            return;
+        }
+        let pattern = match Regex::new(&if literal { regex::escape(&search) } else { search }) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                println!("Expression invalide: {}", e);
+                return;
+            }
+        };
+
+        let counts = match count_occurrences(Path::new("."), &pattern) {
+            Ok(counts) => counts,
+            Err(e) => {
+                println!("Erreur lors de la lecture du répertoire: {}", e);
+                return;
+            }
+        };
+        let mut counts: Vec<(PathBuf, usize)> = counts.into_iter().collect();
+        counts.sort();
+        for (path, count) in &counts {
+            let shown = path.strip_prefix(".").unwrap_or(path);
+            println!("{:>6}  {}", count, shown.display());
+        }
+        let total: usize = counts.iter().map(|(_, count)| count).sum();
+        println!("Total: {} occurrence(s) dans {} fichier(s)", total, counts.len());
+    }
+
+    fn timestamp_file(&mut self) {
+        let filename = match &self.current_file {
+            Some(file) => file.clone(),
+            None => self.get_filename("Fichier à horodater"),
+        };
+        let note = self.get_input("Note (optionnelle)");
+        let line = timestamp_line(&Local::now(), &note);
+        match append_line(Path::new(&filename), &line) {
+            Ok(()) => println!("Ajouté à {}: {}", filename, line),
+            Err(e) => println!("Erreur lors de l'écriture: {}", e),
+        }
+    }
+
+    fn touch_file(&mut self) {
+        let filename = self.get_filename("Fichier à toucher");
+        match touch_file(Path::new(&filename)) {
+            Ok(true) => println!("Fichier {} créé (vide)", filename),
+            Ok(false) => println!("Date de modification de {} mise à jour", filename),
+            Err(e) => println!("Erreur: {}", e),
+        }
+    }
+
+    fn copy_lines(&mut self) {
+        let filename = self.get_existing_filename("Fichier source");
+        let lines = match read_lines(&filename) {
+            Ok(lines) => lines,
+            Err(e) => {
+                println!("Erreur lors de la lecture: {}", e);
+                return;
+            }
+        };
+
+        println!("1. Copier");
+        println!("2. Couper (les lignes seront retirées au collage)");
+        let cut = match self.get_input("Votre choix (1-2)").as_str() {
+            "1" => false,
+            "2" => true,
+            _ => {
+                println!("Choix invalide!");
+                return;
+            }
+        };
+
+        let start = self.get_input("Première ligne").parse::<usize>();
+        let end = self.get_input("Dernière ligne").parse::<usize>();
+        let (start, end) = match (start, end) {
+            (Ok(start), Ok(end)) if start > 0 && start <= end && end <= lines.len() => (start, end),
+            _ => {
+                println!("Plage invalide! Le fichier compte {} ligne(s).", lines.len());
+                return;
+            }
+        };
+
+        self.clipboard = Some(Clipboard {
+            lines: lines[start - 1..end].to_vec(),
+            cut_from: cut.then(|| (filename.clone(), start, end)),
+        });
+        println!(
+            "{} ligne(s) {} depuis {}",
+            end - start + 1,
+            if cut { "coupée(s)" } else { "copiée(s)" },
+            filename
+        );
+    }
+
+    fn paste_lines(&mut self) {
+        let Some(clipboard) = &self.clipboard else {
+            println!("Le presse-papiers est vide.");
+            return;
+        };
+
+        let target = self.get_filename("Fichier de destination");
+        let position = match self.get_input("Coller avant la ligne n° (1 = début)").parse::<usize>() {
+            Ok(position) => position,
+            Err(_) => {
+                println!("Position invalide!");
+                return;
+            }
+        };
+
+        match paste_clipboard(clipboard, &target, position) {
+            Ok(_) => {
+                println!("{} ligne(s) collée(s) dans {}", clipboard.lines.len(), target);
+                self.current_file = Some(target);
+                // Une coupe ne se colle qu'une fois : les lignes d'origine n'existent plus
+                if clipboard.cut_from.is_some() {
+                    self.clipboard = None;
+                }
+            }
+            Err(e) => println!("Erreur lors du collage: {}", e),
+        }
+    }
+
+    fn batch_replace(&mut self) {
+        let filter = self.get_input("Fichiers concernés (ex: *.txt, vide pour tous)");
+        let filter = if filter.is_empty() { "*".to_string() } else { filter };
+
+        println!("1. Texte littéral");
+        println!("2. Expression régulière ($1... utilisables dans le remplacement)");
+        let literal = match self.get_input("Votre choix (1-2)").as_str() {
+            "1" => true,
+            "2" => false,
+            _ => {
+                println!("Choix invalide!");
+                return;
+            }
+        };
+
+        let search = self.get_input("Rechercher");
+        if search.is_empty() {
+            println!("Motif vide!");
+            return;
+        }
+        let pattern = match Regex::new(&if literal { regex::escape(&search) } else { search }) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                println!("Expression invalide: {}", e);
+                return;
+            }
+        };
+        let replacement = self.get_input("Remplacer par");
+
+        let mut files: Vec<String> = match fs::read_dir(".") {
+            Ok(entries) => entries
+                .flatten()
+                .filter(|entry| entry.path().is_file())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                // Les sauvegardes ne sont jamais modifiées
+                .filter(|name| !name.ends_with(".bak") && wildcard_match(&filter, name))
+                .collect(),
+            Err(e) => {
+                println!("Erreur lors de la lecture du répertoire: {}", e);
+                return;
+            }
+        };
+        files.sort();
+
+        // Simulation d'abord : rien n'est écrit tant que l'utilisateur n'a pas confirmé
+        let mut planned = Vec::new();
+        for file in &files {
+            match replace_in_file(file, &pattern, &replacement, literal, true) {
+                Ok(0) => {}
+                Ok(count) => {
+                    println!("{}: {} remplacement(s)", file, count);
+                    planned.push(file);
+                }
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {} // Fichier binaire
+                Err(e) => println!("{}: {}", file, e),
+            }
+        }
+        if planned.is_empty() {
+            println!("Aucune occurrence dans les fichiers {}", filter);
+            return;
+        }
+
+        println!("1. Simulation uniquement");
+        println!("2. Appliquer");
+        if self.get_input("Votre choix (1-2)") != "2" {
+            println!("Simulation terminée, aucun fichier modifié.");
+            return;
+        }
+        if !self.confirm(&format!("Modifier {} fichier(s) (sauvegarde .bak de chacun) ?", planned.len())) {
+            println!("Remplacement annulé.");
+            return;
+        }
+
+        for file in planned {
+            match replace_in_file(file, &pattern, &replacement, literal, false) {
+                Ok(count) => println!("{}: {} remplacement(s) effectué(s)", file, count),
+                Err(e) => println!("{}: erreur ({})", file, e),
+            }
+        }
+    }
+
+    fn compare_with_backup(&mut self) {
+        let filename = match self.current_file.clone() {
+            Some(file) => file,
+            None => self.get_existing_filename("Fichier à comparer"),
+        };
+        let backup = backup_path(&filename);
+
+        if !Path::new(&backup).is_file() {
+            println!("Aucune sauvegarde pour {} ({} n'existe pas)", filename, backup);
+            return;
+        }
+
+        let (current, saved) = match (fs::read_to_string(&filename), fs::read_to_string(&backup)) {
+            (Ok(current), Ok(saved)) => (current, saved),
+            (Err(e), _) | (_, Err(e)) => {
+                println!("Erreur lors de la lecture: {}", e);
+                return;
+            }
+        };
+
+        if current == saved {
+            println!("{} est identique à sa sauvegarde", filename);
+            return;
+        }
+
+        println!("\n--- {} (sauvegarde)", backup);
+        println!("+++ {} (actuel)", filename);
+        let saved_lines: Vec<&str> = saved.lines().collect();
+        let current_lines: Vec<&str> = current.lines().collect();
+        for line in diff_lines(&saved_lines, &current_lines) {
+            match line {
+                DiffLine::Same(text) => println!("  {}", text),
+                DiffLine::Removed(text) => println!("- {}", text),
+                DiffLine::Added(text) => println!("+ {}", text),
+            }
+        }
+    }
+
+    fn list_files(&self) {
+        println!("\n--- Fichiers du répertoire courant ---");
+        
+        match std::fs::read_dir(".") {
+            Ok(entries) => {
+                let mut files = Vec::new();
+                let mut dirs = Vec::new();
+                
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    let name = path.file_name().unwrap().to_string_lossy().to_string();
+                    
+                    if path.is_dir() {
+                        dirs.push(name);
+                    } else {
+                        files.push(name);
+                    }
+                }
+                
+                dirs.sort();
+                files.sort();
+                
+                if !dirs.is_empty() {
+                    println!("\nRépertoires:");
+                    for dir in dirs {
+                        println!("  [DIR]  {}", dir);
+                    }
+                }
+                
+                if !files.is_empty() {
+                    println!("\nFichiers:");
+                    for file in files {
+                        println!("  [FILE] {}", file);
+                    }
+                }
+            }
+            Err(e) => println!("Erreur lors de la lecture du répertoire: {}", e),
+        }
+    }
+
+    fn show_file_info(&self) {
+        let filename = match &self.current_file {
+            Some(file) => file.clone(),
+            None => self.get_existing_filename("Nom du fichier pour les informations"),
+        };
+
+        // symlink_metadata : un lien est décrit lui-même, sans suivre sa cible
+        match fs::symlink_metadata(&filename) {
+            Ok(meta) => {
+                println!("\n--- Informations sur {} ---", filename);
+                println!("Taille: {} octets", meta.len());
+                println!("Lecture seule: {}", meta.permissions().readonly());
+                if meta.is_symlink() {
+                    match fs::read_link(&filename) {
+                        Ok(target) => println!("Type: Lien symbolique -> {}", target.display()),
+                        Err(_) => println!("Type: Lien symbolique"),
+                    }
+                } else {
+                    println!("Type: {}", if meta.is_dir() { "Répertoire" } else { "Fichier" });
+                }
+                
+                if let Ok(modified) = meta.modified() {
+                    println!("Dernière modification: {:?}", modified);
+                }
+            }
+            Err(e) => println!("Erreur lors de la récupération des métadonnées: {}", e),
+        }
+    }
+
+    fn get_filename(&self, prompt: &str) -> String {
+        self.get_input(prompt)
+    }
+
+    // Comme get_filename, mais propose les noms les plus proches en cas de faute de frappe
+    fn get_existing_filename(&self, prompt: &str) -> String {
+        let filename = self.get_filename(prompt);
+        if filename.is_empty() || Path::new(&filename).exists() {
+            return filename;
+        }
+
+        let candidates: Vec<String> = match fs::read_dir(".") {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect(),
+            Err(_) => return filename,
+        };
+
+        let suggestions = suggest_filenames(&filename, &candidates);
+        if suggestions.is_empty() {
+            return filename;
+        }
+
+        println!("{} n'existe pas. Voulez-vous dire:", filename);
+        for (i, suggestion) in suggestions.iter().enumerate() {
+            println!("  {}. {}", i + 1, suggestion);
+        }
+        match self.get_input("Numéro de la suggestion (vide pour garder le nom saisi)").parse::<usize>() {
+            Ok(n) if n > 0 && n <= suggestions.len() => suggestions[n - 1].clone(),
+            _ => filename,
+        }
+    }
+
+    // Demande oui/non ; avec la confirmation automatique, répond oui sans lire l'entrée
+    fn confirm(&self, question: &str) -> bool {
+        if self.assume_yes {
+            println!("{} (oui/non) oui [confirmation automatique]", question);
+            return true;
+        }
+        println!("{} (oui/non)", question);
+        let confirmation = self.get_input("");
+        matches!(confirmation.trim().to_lowercase().as_str(), "oui" | "o" | "yes" | "y")
+    }
+
+    fn toggle_assume_yes(&mut self) {
+        self.assume_yes = !self.assume_yes;
+        if self.assume_yes {
+            println!("Confirmation automatique activée (pour cette session uniquement)");
+        } else {
+            println!("Confirmation automatique désactivée");
+        }
+    }
+
+    fn get_input(&self, prompt: &str) -> String {
+        if !prompt.is_empty() {
+            print!("{}: ", prompt);
+            stdout().flush().unwrap();
+        }
+        
+        let mut input = String::new();
+        stdin().read_line(&mut input).expect("Erreur lors de la lecture");
+        input.trim().to_string()
+    }
+
+    fn run(&mut self) {
+        loop {
+            self.display_menu();
+            
+            let mut input = String::new();
+            if stdin().read_line(&mut input).is_err() {
+                println!("Erreur lors de la lecture de l'entrée.");
+                continue;
+            }
+
+            match input.trim() {
+                "1" => self.create_file(),
+                "2" => self.read_file(),
+                "3" => self.write_file(),
+                "4" => self.modify_file(),
+                "5" => self.delete_file(),
+                "6" => self.list_files(),
+                "7" => self.show_file_info(),
+                "8" => self.manage_trash(),
+                "9" => self.hexdump_file(),
+                "10" => self.concat_files(),
+                "11" => self.split_file(),
+                "12" => self.join_parts(),
+                "13" => self.toggle_assume_yes(),
+                "14" => self.compare_with_backup(),
+                "15" => self.touch_file(),
+                "16" => self.copy_lines(),
+                "17" => self.paste_lines(),
+                "18" => self.batch_replace(),
+                "19" => self.delete_directory(),
+                "20" => self.line_endings(),
+                "21" => self.read_highlighted(),
+                "22" => self.symlinks(),
+                "23" => self.manifest(),
+                "24" => self.timestamp_file(),
+                "25" => self.patch_bytes(),
+                "26" => self.watch_directory(),
+                "27" => self.count_occurrences(),
+                "0" => {
+                    println!("Au revoir!");
+                    break;
+                }
+                _ => println!("Choix invalide! Veuillez choisir entre 0 et 27."),
+            }
+
+            // Pause pour permettre à l'utilisateur de lire les résultats
+            println!("\nAppuyez sur Entrée pour continuer...");
+            let mut pause = String::new();
+            stdin().read_line(&mut pause).unwrap();
+        }
+    }
+}
+
+// Nombre maximal de suggestions proposées
+const MAX_SUGGESTIONS: usize = 3;
+
+// Noms proches de `target` : ceux qui commencent par lui, puis ceux à faible
+// distance d'édition, du plus proche au plus éloigné
+fn suggest_filenames(target: &str, candidates: &[String]) -> Vec<String> {
+    let max_distance = (target.chars().count() / 3).clamp(1, 3);
+
+    let mut scored: Vec<(usize, &String)> = candidates
+        .iter()
+        .filter(|candidate| candidate.as_str() != target)
+        .filter_map(|candidate| {
+            if candidate.starts_with(target) {
+                return Some((0, candidate));
+            }
+            let distance = levenshtein(target, candidate);
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .collect();
+
+    scored.sort();
+    scored
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.clone())
+        .collect()
+}
+
+// Distance de Levenshtein (insertions, suppressions, substitutions)
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+// Formate une ligne de hexdump : offset, 16 octets en hexa, gouttière ASCII
+fn hexdump_line(offset: usize, bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    for i in 0..16 {
+        match bytes.get(i) {
+            Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+            None => hex.push_str("   "),
+        }
+        if i == 7 {
+            hex.push(' ');
+        }
+    }
+
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+        .collect();
+
+    format!("{:08x}  {} |{}|", offset, hex, ascii)
+}
+
+// Concatène les fichiers dans `output`, dans l'ordre (comme `cat a b > out`).
+// Toutes les entrées sont vérifiées avant d'écrire quoi que ce soit.
+fn concat_files(inputs: &[String], output: &str, separator: Option<&str>) -> io::Result<u64> {
+    for input in inputs {
+        if !Path::new(input).is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("le fichier {} n'existe pas", input),
+            ));
+        }
+    }
+
+    let mut out = File::create(output)?;
+    let mut total = 0;
+    for (i, input) in inputs.iter().enumerate() {
+        if i > 0 && let Some(separator) = separator {
+            writeln!(out, "{}", separator)?;
+            total += separator.len() as u64 + 1;
+        }
+        // io::copy lit par blocs : le fichier n'est jamais chargé en entier
+        total += io::copy(&mut File::open(input)?, &mut out)?;
+    }
+    out.flush()?;
+    Ok(total)
+}
+
+// Comme `touch` : crée un fichier vide s'il n'existe pas (Ok(true)), sinon
+// met sa date de modification à maintenant sans toucher au contenu (Ok(false))
+fn touch_file(path: &Path) -> io::Result<bool> {
+    if path.exists() {
+        filetime::set_file_mtime(path, FileTime::now())?;
+        Ok(false)
+    } else {
+        File::create(path)?;
+        Ok(true)
+    }
+}
+
+// Une ligne par chemin concerné ("[CREATED] fichier.txt"), aucune pour les
+// événements sans intérêt ici (accès, métadonnées seules)
+fn format_event(event: &Event) -> Vec<String> {
+    let label = match event.kind {
+        EventKind::Create(_) => "CREATED",
+        EventKind::Modify(ModifyKind::Name(_)) => "RENAMED",
+        EventKind::Modify(ModifyKind::Metadata(_)) => return Vec::new(),
+        EventKind::Modify(_) => "MODIFIED",
+        EventKind::Remove(_) => "DELETED",
+        _ => return Vec::new(),
+    };
+    let cwd = std::env::current_dir().unwrap_or_default();
+    event
+        .paths
+        .iter()
+        .map(|path| {
+            let shown = path.strip_prefix(&cwd).or_else(|_| path.strip_prefix(".")).unwrap_or(path);
+            format!("[{}] {}", label, shown.display())
+        })
+        .collect()
+}
+
+// Nombre d'occurrences (et non de lignes) de `pattern` dans chaque fichier
+// texte de `dir`, sans les fichiers qui n'en contiennent aucune. Les fichiers
+// binaires ou non UTF-8 sont ignorés, comme les correspondances vides (`x*`).
+fn count_occurrences(dir: &Path, pattern: &Regex) -> io::Result<HashMap<PathBuf, usize>> {
+    let mut counts = HashMap::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        // Un octet nul trahit un fichier binaire, même s'il est de l'UTF-8 valide
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        if content.contains('\0') {
+            continue;
+        }
+        let count = pattern.find_iter(&content).filter(|m| !m.is_empty()).count();
+        if count > 0 {
+            counts.insert(path, count);
+        }
+    }
+    Ok(counts)
+}
+
+// Octets saisis en hexadécimal, espaces ignorées ("0a ff00" -> [0x0a, 0xff, 0x00])
+fn parse_hex(input: &str) -> Option<Vec<u8>> {
+    let digits: Vec<char> = input.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(&pair.iter().collect::<String>(), 16).ok())
+        .collect()
+}
+
+// Remplace `length` octets à partir de `offset` : écriture sur place si la
+// taille ne change pas, sinon la fin du fichier est relue puis décalée
+fn patch_range(path: &Path, offset: u64, length: u64, replacement: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    lock_exclusive(&file, &path.display().to_string())?;
+
+    let size = file.metadata()?.len();
+    let end = offset.checked_add(length).filter(|&end| end <= size).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("plage {}..{} hors du fichier ({} octets)", offset, offset.saturating_add(length), size),
+        )
+    })?;
+
+    if replacement.len() as u64 == length {
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(replacement)?;
+    } else {
+        let mut tail = Vec::new();
+        file.seek(SeekFrom::Start(end))?;
+        file.read_to_end(&mut tail)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(replacement)?;
+        file.write_all(&tail)?;
+        file.set_len(offset + replacement.len() as u64 + tail.len() as u64)?;
+    }
+    FileExt::unlock(&file)
+}
+
+fn timestamp_line<Tz: TimeZone>(now: &DateTime<Tz>, note: &str) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let stamp = now.format("%Y-%m-%d %H:%M:%S").to_string();
+    let note = note.trim();
+    if note.is_empty() { stamp } else { format!("{} {}", stamp, note) }
+}
+
+fn append_line(path: &Path, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PreviewFormat {
+    Json,
+    Csv,
+}
+
+impl PreviewFormat {
+    fn from_extension(filename: &str) -> Option<Self> {
+        let extension = Path::new(filename).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(PreviewFormat::Json),
+            "csv" => Some(PreviewFormat::Csv),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PreviewFormat::Json => "json",
+            PreviewFormat::Csv => "csv",
+        }
+    }
+
+    fn pretty(self, content: &str) -> Result<String, String> {
+        match self {
+            PreviewFormat::Json => pretty_json(content).map_err(|e| e.to_string()),
+            PreviewFormat::Csv => pretty_csv(content).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+// JSON réindenté (deux espaces par niveau)
+fn pretty_json(content: &str) -> serde_json::Result<String> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    serde_json::to_string_pretty(&value)
+}
+
+// CSV aligné en colonnes, la première ligne (en-têtes) séparée du reste ;
+// les lignes plus courtes que les autres sont complétées par du vide
+fn pretty_csv(content: &str) -> csv::Result<String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let rows: Vec<Vec<String>> = reader
+        .records()
+        .map(|record| record.map(|r| r.iter().map(String::from).collect()))
+        .collect::<csv::Result<_>>()?;
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let format_row = |row: &Vec<String>| {
+        widths
+            .iter()
+            .enumerate()
+            .map(|(i, &width)| format!("{:<width$}", row.get(i).map_or("", String::as_str), width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    for (i, row) in rows.iter().enumerate() {
+        lines.push(format_row(row));
+        if i == 0 && rows.len() > 1 {
+            lines.push(widths.iter().map(|&w| "-".repeat(w)).collect::<Vec<_>>().join("-+-"));
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+// Lignes d'un fichier ; un fichier absent est vu comme vide
+fn read_lines(filename: &str) -> io::Result<Vec<String>> {
+    match fs::read_to_string(filename) {
+        Ok(content) => Ok(content.lines().map(String::from).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_lines(filename: &str, lines: &[String]) -> io::Result<()> {
+    if lines.is_empty() {
+        return fs::write(filename, "");
+    }
+    fs::write(filename, lines.join("\n") + "\n")
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// Insère les lignes du presse-papiers dans `target` avant la ligne `position`
+// (1 = début, nombre de lignes + 1 = fin). Pour une coupe, retire ensuite la
+// plage d'origine, y compris quand source et destination sont le même fichier.
+// Rien n'est écrit si la source a changé depuis la coupe.
+fn paste_clipboard(clipboard: &Clipboard, target: &str, position: usize) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+
+    let mut target_lines = read_lines(target)?;
+    if position == 0 || position > target_lines.len() + 1 {
+        return Err(invalid("position hors du fichier"));
+    }
+
+    let Some((source, start, end)) = &clipboard.cut_from else {
+        target_lines.splice(position - 1..position - 1, clipboard.lines.iter().cloned());
+        return write_lines(target, &target_lines);
+    };
+
+    let mut source_lines = read_lines(source)?;
+    if source_lines.get(start - 1..*end) != Some(clipboard.lines.as_slice()) {
+        return Err(invalid("la source a été modifiée depuis la coupe"));
+    }
+
+    if same_file(source, target) {
+        if position > *start && position <= *end {
+            return Err(invalid("impossible de coller à l'intérieur de la plage coupée"));
+        }
+        // Retirer d'abord, puis insérer à la position décalée si elle suivait la plage
+        source_lines.drain(start - 1..*end);
+        let at = if position > *end { position - 1 - (end - start + 1) } else { position - 1 };
+        source_lines.splice(at..at, clipboard.lines.iter().cloned());
+        return write_lines(source, &source_lines);
+    }
+
+    target_lines.splice(position - 1..position - 1, clipboard.lines.iter().cloned());
+    write_lines(target, &target_lines)?;
+    source_lines.drain(start - 1..*end);
+    write_lines(source, &source_lines)
+}
+
+// Remplace toutes les occurrences de `pattern` ; `literal` empêche
+// l'interprétation de $1, $nom... dans le remplacement. Renvoie le nouveau
+// texte et le nombre de remplacements.
+fn replace_all(content: &str, pattern: &Regex, replacement: &str, literal: bool) -> (String, usize) {
+    let count = pattern.find_iter(content).count();
+    if count == 0 {
+        return (content.to_string(), 0);
+    }
+    let replaced = if literal {
+        pattern.replace_all(content, NoExpand(replacement))
+    } else {
+        pattern.replace_all(content, replacement)
+    };
+    (replaced.into_owned(), count)
+}
+
+// Remplacement dans un fichier texte, après sauvegarde dans <fichier>.bak.
+// Avec `dry_run`, se contente de compter. Un fichier non UTF-8 donne InvalidData.
+fn replace_in_file(filename: &str, pattern: &Regex, replacement: &str, literal: bool, dry_run: bool) -> io::Result<usize> {
+    let content = fs::read_to_string(filename)?;
+    let (replaced, count) = replace_all(&content, pattern, replacement, literal);
+    if dry_run || count == 0 {
+        return Ok(count);
+    }
+    fs::write(backup_path(filename), &content)?;
+    fs::write(filename, replaced)?;
+    Ok(count)
+}
+
+// Filtre de noms à la manière du shell : `*` pour toute suite de caractères, `?` pour un seul
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // matches[j] : le préfixe du motif déjà lu reconnaît name[..j]
+    let mut matches = vec![false; name.len() + 1];
+    matches[0] = true;
+    for p in pattern {
+        let mut next = vec![false; name.len() + 1];
+        for j in 0..=name.len() {
+            next[j] = match p {
+                '*' => matches[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matches[j - 1],
+                c => j > 0 && matches[j - 1] && name[j - 1] == c,
+            };
+        }
+        matches = next;
+    }
+    matches[name.len()]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bom {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Bom {
+    fn name(self) -> &'static str {
+        match self {
+            Bom::Utf8 => "UTF-8",
+            Bom::Utf16Le => "UTF-16 LE",
+            Bom::Utf16Be => "UTF-16 BE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+// Résultat de l'analyse d'un fichier (les fins de ligne ne sont pas comptées en UTF-16)
+#[derive(Debug, PartialEq)]
+struct LineEndingReport {
+    lf: usize,
+    crlf: usize,
+    bom: Option<Bom>,
+    trailing_newline: bool,
+}
+
+impl LineEndingReport {
+    fn style(&self) -> &'static str {
+        match (self.lf, self.crlf) {
+            (0, 0) => "aucune",
+            (_, 0) => "LF",
+            (0, _) => "CRLF",
+            _ => "mixte",
+        }
+    }
+}
+
+fn analyze_line_endings(bytes: &[u8]) -> LineEndingReport {
+    let bom = if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        Some(Bom::Utf8)
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+        Some(Bom::Utf16Le)
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+        Some(Bom::Utf16Be)
+    } else {
+        None
+    };
+    let mut report = LineEndingReport { lf: 0, crlf: 0, bom, trailing_newline: false };
+    if matches!(bom, Some(Bom::Utf16Le | Bom::Utf16Be)) {
+        return report;
+    }
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        if byte == b'\n' {
+            if i > 0 && bytes[i - 1] == b'\r' {
+                report.crlf += 1;
+            } else {
+                report.lf += 1;
+            }
+        }
+    }
+    report.trailing_newline = bytes.ends_with(b"\n");
+    report
+}
+
+// Texte colorié pour le terminal (séquences ANSI 24 bits), `language` étant une
+// extension ou un nom de langage ; None si le langage est inconnu
+fn highlight(content: &str, language: &str) -> Option<String> {
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let syntax = syntaxes
+        .find_syntax_by_extension(language)
+        .or_else(|| syntaxes.find_syntax_by_token(language))?;
+    let themes = ThemeSet::load_defaults();
+    let mut highlighter = HighlightLines::new(syntax, &themes.themes["base16-ocean.dark"]);
+
+    let mut output = String::new();
+    for line in LinesWithEndings::from(content) {
+        let ranges = highlighter.highlight_line(line, &syntaxes).ok()?;
+        output.push_str(&as_24_bit_terminal_escaped(&ranges, false));
+    }
+    // Rétablir les couleurs du terminal
+    output.push_str("\x1b[0m");
+    Some(output)
+}
+
+// Toutes les fins de ligne converties vers `target` ; les \r isolés sont conservés
+fn normalize_line_endings(bytes: &[u8], target: LineEnding) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(bytes.len());
+    for (i, &byte) in bytes.iter().enumerate() {
+        if byte == b'\r' && bytes.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        if byte == b'\n' && target == LineEnding::Crlf {
+            normalized.push(b'\r');
+        }
+        normalized.push(byte);
+    }
+    normalized
+}
+
+// Sauvegarde créée avant d'écrire (modify_file, remplacement par lots) : "notes.txt" -> "notes.txt.bak"
+fn backup_path(filename: &str) -> String {
+    format!("{}.bak", filename)
+}
+
+#[derive(Debug)]
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// Diff ligne à ligne par plus longue sous-séquence commune : les lignes
+// communes sont conservées, le reste est marqué supprimé (old) ou ajouté (new)
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffLine<'a>> {
+    // lcs[i][j] : longueur de la PLSC de old[i..] et new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            result.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            result.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            result.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    result.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    result.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+    result
+}
+
+// "4096" -> 4096 octets, "4K" ou "4KiB" -> 4096 octets ; zéro est refusé
+fn parse_chunk_size(input: &str) -> Option<u64> {
+    let input = input.trim();
+    let upper = input.to_ascii_uppercase();
+    let (digits, multiplier) = match upper.strip_suffix("KIB").or_else(|| upper.strip_suffix('K')) {
+        Some(digits) => (digits.trim(), 1024),
+        None => (input, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|&n| n > 0)
+}
+
+// Nom du n-ième morceau : "fichier.part001"
+fn part_name(base: &str, index: usize) -> String {
+    format!("{}.part{:03}", base, index)
+}
+
+// Découpe `filename` en morceaux d'au plus `chunk_size` octets, par blocs,
+// sans charger le fichier ; renvoie le nombre de morceaux créés. Les morceaux
+// restants d'un découpage précédent plus fin sont supprimés, sans quoi la
+// reconstitution les concaténerait à la suite
+fn split_file(filename: &str, chunk_size: u64) -> io::Result<usize> {
+    let mut input = File::open(filename)?;
+    let total = input.metadata()?.len();
+    let mut parts = 0;
+
+    // Un fichier vide donne tout de même un morceau (vide)
+    loop {
+        parts += 1;
+        let mut part = File::create(part_name(filename, parts))?;
+        let written = io::copy(&mut (&mut input).take(chunk_size), &mut part)?;
+        if written < chunk_size || parts as u64 * chunk_size >= total {
+            break;
+        }
+    }
+
+    let mut stale = parts + 1;
+    while Path::new(&part_name(filename, stale)).is_file() {
+        remove_file(part_name(filename, stale))?;
+        stale += 1;
+    }
+    Ok(parts)
+}
+
+// Reconstitue `output` à partir de base.part001, base.part002... dans l'ordre
+// numérique ; s'arrête au premier numéro manquant
+fn join_parts(base: &str, output: &str) -> io::Result<u64> {
+    if !Path::new(&part_name(base, 1)).is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("aucun morceau {} trouvé", part_name(base, 1)),
+        ));
+    }
+
+    let mut out = File::create(output)?;
+    let mut total = 0;
+    let mut index = 1;
+    while let Ok(mut part) = File::open(part_name(base, index)) {
+        total += io::copy(&mut part, &mut out)?;
+        index += 1;
+    }
+    out.flush()?;
+    Ok(total)
+}
+
+// Empreinte SHA-256 d'un fichier en hexadécimal, calculée par blocs
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// Une ligne du manifeste : "<sha256> <taille> <chemin relatif>"
+#[derive(Debug, Clone, PartialEq)]
+struct ManifestEntry {
+    path: String,
+    size: u64,
+    hash: String,
+}
+
+// Écarts entre le manifeste et le répertoire, chemins triés
+#[derive(Debug, Default, PartialEq)]
+struct ManifestDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<String>,
+}
+
+impl ManifestDiff {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+// Tous les fichiers sous `root`, triés par chemin (séparateur '/'). Les liens
+// symboliques ne sont pas suivis ; le manifeste et la corbeille sont ignorés.
+fn build_manifest(root: &Path) -> io::Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+    collect_manifest(root, "", &mut entries)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+fn collect_manifest(dir: &Path, prefix: &str, entries: &mut Vec<ManifestEntry>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let relative = if prefix.is_empty() { name.clone() } else { format!("{}/{}", prefix, name) };
+        if relative == MANIFEST_FILE || relative == TRASH_DIR {
+            continue;
+        }
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_manifest(&entry.path(), &relative, entries)?;
+        } else if file_type.is_file() {
+            entries.push(ManifestEntry {
+                size: entry.metadata()?.len(),
+                hash: sha256_file(&entry.path())?,
+                path: relative,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn write_manifest(path: &Path, entries: &[ManifestEntry]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    for entry in entries {
+        writeln!(file, "{} {} {}", entry.hash, entry.size, entry.path)?;
+    }
+    Ok(())
+}
+
+fn read_manifest(path: &Path) -> io::Result<Vec<ManifestEntry>> {
+    let content = fs::read_to_string(path)?;
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            // Le chemin, en dernier, peut contenir des espaces
+            let mut fields = line.splitn(3, ' ');
+            match (fields.next(), fields.next().and_then(|size| size.parse().ok()), fields.next()) {
+                (Some(hash), Some(size), Some(path)) => Ok(ManifestEntry {
+                    path: path.to_string(),
+                    size,
+                    hash: hash.to_string(),
+                }),
+                _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("ligne de manifeste invalide: {}", line))),
+            }
+        })
+        .collect()
+}
+
+// Recalcule les empreintes sous `root` et les compare au manifeste
+fn verify_manifest(root: &Path, manifest: &Path) -> io::Result<ManifestDiff> {
+    let expected = read_manifest(manifest)?;
+    let current = build_manifest(root)?;
+    let expected_by_path: HashMap<&str, &ManifestEntry> = expected.iter().map(|e| (e.path.as_str(), e)).collect();
+    let current_by_path: HashMap<&str, &ManifestEntry> = current.iter().map(|e| (e.path.as_str(), e)).collect();
+
+    let mut diff = ManifestDiff::default();
+    for entry in &current {
+        match expected_by_path.get(entry.path.as_str()) {
+            None => diff.added.push(entry.path.clone()),
+            Some(old) if old.hash != entry.hash || old.size != entry.size => diff.changed.push(entry.path.clone()),
+            Some(_) => {}
+        }
+    }
+    for entry in &expected {
+        if !current_by_path.contains_key(entry.path.as_str()) {
+            diff.removed.push(entry.path.clone());
+        }
+    }
+    diff.removed.sort();
+    Ok(diff)
+}
+
+/// Pose un verrou exclusif consultatif sans attendre ; échoue avec `WouldBlock`
+/// si une autre instance modifie déjà le fichier
+fn lock_exclusive(file: &File, filename: &str) -> io::Result<()> {
+    file.try_lock_exclusive().map_err(|e| {
+        if e.kind() == io::ErrorKind::WouldBlock {
+            io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{} est en cours de modification par une autre instance", filename),
+            )
+        } else {
+            e
+        }
+    })
+}
+
+// Déplace un fichier dans la corbeille `trash` sous un nom horodaté
+// ("<secondes>_<nom>") ; son chemin absolu d'origine est noté à côté, dans
+// "<entrée>.origine". Les répertoires sont refusés.
+fn move_to_trash(trash: &Path, path: &Path) -> io::Result<PathBuf> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} est un répertoire", path.display()),
+        ));
+    }
+    // Chemin absolu du répertoire parent (le fichier lui-même peut être un lien)
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(trash)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "nom de fichier invalide"))?
+        .to_string_lossy();
+
+    let origin = fs::canonicalize(parent)?.join(name.as_ref());
+
+    let mut destination = trash.join(format!("{}_{}", timestamp, name));
+    let mut suffix = 1;
+    while destination.exists() {
+        destination = trash.join(format!("{}-{}_{}", timestamp, suffix, name));
+        suffix += 1;
+    }
+
+    fs::write(origin_file(&destination), origin.to_string_lossy().as_bytes())?;
+    if let Err(e) = move_file(path, &destination) {
+        let _ = remove_file(origin_file(&destination));
+        return Err(e);
+    }
+    Ok(destination)
+}
+
+fn origin_file(entry: &Path) -> PathBuf {
+    let mut name = entry.as_os_str().to_owned();
+    name.push(".");
+    name.push(TRASH_ORIGIN_EXT);
+    PathBuf::from(name)
+}
+
+// `.`, `..`, le répertoire de travail ou l'un de ses parents
+fn is_protected_dir(path: &Path) -> bool {
+    match (fs::canonicalize(path), std::env::current_dir()) {
+        (Ok(target), Ok(cwd)) => cwd.starts_with(&target),
+        // Dans le doute, refuser
+        _ => true,
+    }
+}
+
+// Cible directe d'un lien symbolique, sans la suivre plus loin
+fn describe_symlink(path: &str) -> String {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_symlink() => match fs::read_link(path) {
+            Ok(target) => format!("{} est un lien symbolique vers {}", path, target.display()),
+            Err(e) => format!("{} est un lien symbolique illisible: {}", path, e),
+        },
+        Ok(_) => format!("{} n'est pas un lien symbolique", path),
+        Err(e) => format!("{}: {}", path, e),
+    }
+}
+
+// Chemin complet : suit tous les liens et les composants relatifs ; échoue si
+// la cible n'existe pas
+fn resolve_symlink(path: &str) -> String {
+    match fs::canonicalize(path) {
+        Ok(resolved) => format!("{} -> {}", path, resolved.display()),
+        Err(e) => format!("Résolution impossible (lien cassé ?): {}", e),
+    }
+}
+
+// Supprime `path` et tout son contenu si `confirmation` reprend exactement son
+// nom ; refuse les fichiers, les liens et les répertoires protégés
+fn delete_tree(path: &Path, confirmation: &str) -> io::Result<()> {
+    if !fs::symlink_metadata(path)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} n'est pas un répertoire", path.display()),
+        ));
+    }
+    if is_protected_dir(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} est le répertoire de travail ou l'un de ses parents", path.display()),
+        ));
+    }
+    if Path::new(confirmation) != path {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "confirmation incorrecte"));
+    }
+    fs::remove_dir_all(path)
+}
+
+// Nombre de fichiers et de sous-répertoires sous `path`, sans suivre les liens
+fn count_tree(path: &Path) -> io::Result<(usize, usize)> {
+    let mut files = 0;
+    let mut dirs = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let (sub_files, sub_dirs) = count_tree(&entry.path())?;
+            files += sub_files;
+            dirs += sub_dirs + 1;
+        } else {
+            files += 1;
+        }
+    }
+    Ok((files, dirs))
+}
+
+// Remet un fichier de la corbeille à l'emplacement demandé, en recréant
+// au besoin son répertoire
+fn restore_from_trash(entry: &Path, target: &Path) -> io::Result<()> {
+    if let Some(parent) = target.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    move_file(entry, target)?;
+    let _ = remove_file(origin_file(entry));
+    Ok(())
+}
+
+// Liste le contenu de la corbeille, du plus ancien au plus récent
+fn list_trash(trash: &Path) -> io::Result<Vec<PathBuf>> {
+    if !trash.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(trash)? {
+        let path = entry?.path();
+        let is_origin = path.extension().is_some_and(|ext| ext == TRASH_ORIGIN_EXT);
+        if !is_origin && !fs::symlink_metadata(&path)?.is_dir() {
+            entries.push(path);
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+// Chemin d'origine noté à la mise à la corbeille ; à défaut (entrée plus
+// ancienne), le nom sans préfixe horodaté, dans le répertoire courant
+fn original_path(entry: &Path) -> PathBuf {
+    if let Ok(origin) = fs::read_to_string(origin_file(entry)) {
+        return PathBuf::from(origin);
+    }
+    let name = entry.file_name().unwrap().to_string_lossy().to_string();
+    match name.split_once('_') {
+        Some((_, original)) => PathBuf::from(original),
+        None => PathBuf::from(name),
+    }
+}
+
+// rename, avec repli sur copie + suppression si la source est sur un autre disque
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        remove_file(from)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    // --hard-delete : suppression définitive, sans passer par la corbeille
+    let hard_delete = std::env::args().any(|arg| arg == "--hard-delete");
+    // --yes : confirme automatiquement écrasements et suppressions (scripts)
+    let assume_yes = std::env::args().any(|arg| arg == "--yes");
+
+    let mut file_manager = FileManager::new(hard_delete, assume_yes);
+
+    // Sous-commande non interactive : tp2 [--yes] [--hard-delete] delete <fichier>
+    let commands: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    match commands.as_slice() {
+        [] => file_manager.run(),
+        [command, filename] if command == "delete" => {
+            if !file_manager.delete_path(filename.clone()) {
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("Usage: tp2 [--yes] [--hard-delete] [delete <fichier>]");
+            std::process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Répertoire de travail propre à un test, recréé à vide
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tp2-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn trashed_file_is_restored_to_its_original_path() {
+        let dir = test_dir("trash");
+        let trash = dir.join(".trash");
+        let file = dir.join("sub").join("a.txt");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "contenu").unwrap();
+
+        let entry = move_to_trash(&trash, &file).unwrap();
+        assert!(!file.exists());
+        assert_eq!(list_trash(&trash).unwrap(), vec![entry.clone()]);
+
+        // Le sous-répertoire a disparu entre-temps : il doit être recréé
+        fs::remove_dir(dir.join("sub")).unwrap();
+        let target = original_path(&entry);
+        assert_eq!(target, fs::canonicalize(&dir).unwrap().join("sub").join("a.txt"));
+        restore_from_trash(&entry, &target).unwrap();
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "contenu");
+        assert!(list_trash(&trash).unwrap().is_empty());
+        assert_eq!(fs::read_dir(&trash).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn split_then_join_round_trips_despite_stale_parts() {
+        let dir = test_dir("split");
+        let file = dir.join("data.bin");
+        let base = file.to_str().unwrap();
+        let content: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&file, &content).unwrap();
+
+        // Un premier découpage fin laisse des morceaux .part004 et au-delà
+        assert_eq!(split_file(base, 100).unwrap(), 25);
+        assert_eq!(split_file(base, 1000).unwrap(), 3);
+        assert!(!Path::new(&part_name(base, 4)).exists());
+
+        let output = dir.join("joined.bin");
+        let total = join_parts(base, output.to_str().unwrap()).unwrap();
+        assert_eq!(total, content.len() as u64);
+        assert_eq!(fs::read(&output).unwrap(), content);
+        assert_eq!(sha256_file(&output).unwrap(), sha256_file(&file).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn directories_are_refused_by_the_trash() {
+        let dir = test_dir("trash-dir");
+        let trash = dir.join(".trash");
+        let sub = dir.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+
+        let err = move_to_trash(&trash, &sub).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(sub.is_dir());
+        assert!(list_trash(&trash).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hexdump_line_formats_offset_hex_and_ascii() {
+        let bytes: Vec<u8> = b"Hello, world!\n\x00\xff".to_vec();
+        assert_eq!(
+            hexdump_line(0x10, &bytes),
+            "00000010  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|"
+        );
+    }
+
+    #[test]
+    fn hexdump_line_pads_a_short_last_line() {
+        assert_eq!(
+            hexdump_line(0x20, b"ab"),
+            format!("00000020  61 62 {}  |ab|", " ".repeat(3 * 14))
+        );
+    }
+
+    #[test]
+    fn concat_files_joins_inputs_in_order() {
+        let dir = test_dir("concat");
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        fs::write(&a, "premier\n").unwrap();
+        fs::write(&b, "second\n").unwrap();
+        let inputs = [a.to_string_lossy().to_string(), b.to_string_lossy().to_string()];
+        let output = dir.join("out.txt");
+
+        let total = concat_files(&inputs, output.to_str().unwrap(), None).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "premier\nsecond\n");
+        assert_eq!(total, 15);
+
+        concat_files(&inputs, output.to_str().unwrap(), Some("---")).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "premier\n---\nsecond\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concat_files_writes_nothing_when_an_input_is_missing() {
+        let dir = test_dir("concat-missing");
+        let a = dir.join("a.txt");
+        fs::write(&a, "premier\n").unwrap();
+        let inputs = [a.to_string_lossy().to_string(), dir.join("absent.txt").to_string_lossy().to_string()];
+        let output = dir.join("out.txt");
+
+        let err = concat_files(&inputs, output.to_str().unwrap(), None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(!output.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn one_character_typo_is_suggested() {
+        let candidates: Vec<String> = ["notes.txt", "readme.md", "note.txt", "main.rs"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(suggest_filenames("notez.txt", &candidates), vec!["note.txt", "notes.txt"]);
+        assert_eq!(suggest_filenames("rea", &candidates), vec!["readme.md"]);
+        assert!(suggest_filenames("zzzzzz", &candidates).is_empty());
+    }
+
+    #[test]
+    fn levenshtein_counts_edits() {
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("chat", "chat"), 0);
+        assert_eq!(levenshtein("chat", "chats"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("été", "ete"), 2);
+    }
+
+    #[test]
+    fn second_lock_on_the_same_file_is_refused() {
+        let dir = test_dir("lock");
+        let path = dir.join("a.txt");
+        fs::write(&path, "contenu").unwrap();
+
+        let first = File::open(&path).unwrap();
+        lock_exclusive(&first, "a.txt").unwrap();
+        let second = File::open(&path).unwrap();
+        let err = lock_exclusive(&second, "a.txt").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(err.to_string().contains("autre instance"));
+
+        // Une fois le premier verrou relâché, le fichier redevient disponible
+        FileExt::unlock(&first).unwrap();
+        lock_exclusive(&second, "a.txt").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Rendu des lignes du diff tel qu'affiché par la comparaison
+    fn render_diff(diff: &[DiffLine]) -> Vec<String> {
+        diff.iter()
+            .map(|line| match line {
+                DiffLine::Same(text) => format!("  {}", text),
+                DiffLine::Removed(text) => format!("- {}", text),
+                DiffLine::Added(text) => format!("+ {}", text),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn modified_file_is_diffed_against_its_backup() {
+        let dir = test_dir("backup");
+        let file = dir.join("notes.txt");
+        let filename = file.to_str().unwrap();
+        fs::write(&file, "un\ndeux\ntrois\n").unwrap();
+
+        // La modification crée la sauvegarde notes.txt.bak
+        let pattern = Regex::new("deux").unwrap();
+        assert_eq!(replace_in_file(filename, &pattern, "DEUX", true, false).unwrap(), 1);
+        let saved = fs::read_to_string(backup_path(filename)).unwrap();
+        let current = fs::read_to_string(&file).unwrap();
+
+        let saved_lines: Vec<&str> = saved.lines().collect();
+        let current_lines: Vec<&str> = current.lines().collect();
+        assert_eq!(
+            render_diff(&diff_lines(&saved_lines, &current_lines)),
+            vec!["  un", "- deux", "+ DEUX", "  trois"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diff_of_identical_lines_has_no_changes() {
+        let lines = ["a", "b"];
+        assert_eq!(render_diff(&diff_lines(&lines, &lines)), vec!["  a", "  b"]);
+        assert_eq!(render_diff(&diff_lines(&[], &["a"])), vec!["+ a"]);
+        assert_eq!(render_diff(&diff_lines(&["a"], &[])), vec!["- a"]);
+    }
+
+    #[test]
+    fn touch_creates_missing_files_and_advances_mtime() {
+        let dir = test_dir("touch");
+        let path = dir.join("vide.txt");
+
+        assert!(touch_file(&path).unwrap());
+        assert_eq!(fs::read(&path).unwrap(), b"");
+
+        fs::write(&path, "contenu").unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(&path, old).unwrap();
+        assert!(!touch_file(&path).unwrap());
+
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(&path).unwrap());
+        assert!(mtime > old);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "contenu");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copied_lines_are_pasted_into_another_file() {
+        let dir = test_dir("copy");
+        let source = dir.join("source.txt").to_string_lossy().to_string();
+        let target = dir.join("cible.txt").to_string_lossy().to_string();
+        fs::write(&source, "a\nb\nc\n").unwrap();
+        fs::write(&target, "1\n2\n").unwrap();
+
+        let clipboard = Clipboard { lines: vec!["b".to_string(), "c".to_string()], cut_from: None };
+        paste_clipboard(&clipboard, &target, 2).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "1\nb\nc\n2\n");
+        assert_eq!(fs::read_to_string(&source).unwrap(), "a\nb\nc\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cut_lines_are_removed_from_the_source_on_paste() {
+        let dir = test_dir("cut");
+        let source = dir.join("source.txt").to_string_lossy().to_string();
+        let target = dir.join("cible.txt").to_string_lossy().to_string();
+        fs::write(&source, "a\nb\nc\n").unwrap();
+        fs::write(&target, "1\n").unwrap();
+
+        let clipboard = Clipboard { lines: vec!["a".to_string(), "b".to_string()], cut_from: Some((source.clone(), 1, 2)) };
+        paste_clipboard(&clipboard, &target, 2).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "1\na\nb\n");
+        assert_eq!(fs::read_to_string(&source).unwrap(), "c\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cut_and_paste_within_the_same_file_moves_lines() {
+        let dir = test_dir("cut-same");
+        let file = dir.join("a.txt").to_string_lossy().to_string();
+        fs::write(&file, "a\nb\nc\nd\n").unwrap();
+
+        let clipboard = Clipboard { lines: vec!["a".to_string()], cut_from: Some((file.clone(), 1, 1)) };
+        paste_clipboard(&clipboard, &file, 4).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "b\nc\na\nd\n");
+
+        // La source a changé : le collage est refusé sans rien écrire
+        let err = paste_clipboard(&clipboard, &file, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "b\nc\na\nd\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn batch_replace_updates_each_file_and_keeps_backups() {
+        let dir = test_dir("replace");
+        let a = dir.join("a.txt").to_string_lossy().to_string();
+        let b = dir.join("b.txt").to_string_lossy().to_string();
+        fs::write(&a, "foo bar foo\n").unwrap();
+        fs::write(&b, "foo=1\n").unwrap();
+        let pattern = Regex::new(&regex::escape("foo")).unwrap();
+
+        // La simulation compte sans rien écrire
+        assert_eq!(replace_in_file(&a, &pattern, "$x", true, true).unwrap(), 2);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "foo bar foo\n");
+        assert!(!Path::new(&backup_path(&a)).exists());
+
+        for file in [&a, &b] {
+            replace_in_file(file, &pattern, "$x", true, false).unwrap();
+        }
+        // `$x` reste littéral
+        assert_eq!(fs::read_to_string(&a).unwrap(), "$x bar $x\n");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "$x=1\n");
+        assert_eq!(fs::read_to_string(backup_path(&a)).unwrap(), "foo bar foo\n");
+        assert_eq!(fs::read_to_string(backup_path(&b)).unwrap(), "foo=1\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn regex_replacement_expands_groups() {
+        let pattern = Regex::new(r"(\w+)@(\w+)").unwrap();
+        assert_eq!(replace_all("a@b c@d", &pattern, "$2@$1", false), ("b@a d@c".to_string(), 2));
+        assert_eq!(replace_all("rien", &pattern, "$2", false), ("rien".to_string(), 0));
+    }
+
+    #[test]
+    fn wildcard_filters_file_names() {
+        assert!(wildcard_match("*.txt", "notes.txt"));
+        assert!(!wildcard_match("*.txt", "notes.txt.bak"));
+        assert!(wildcard_match("a?c", "abc"));
+        assert!(!wildcard_match("a?c", "ac"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn json_and_csv_are_pretty_printed() {
+        assert_eq!(
+            pretty_json(r#"{"nom":"Ada","langages":["rust","c"]}"#).unwrap(),
+            "{\n  \"nom\": \"Ada\",\n  \"langages\": [\n    \"rust\",\n    \"c\"\n  ]\n}"
+        );
+        assert!(pretty_json("{invalide").is_err());
+
+        assert_eq!(
+            pretty_csv("nom,age\nAda,36\nAlan,41\n").unwrap(),
+            "nom  | age\n-----+----\nAda  | 36\nAlan | 41"
+        );
+        assert_eq!(PreviewFormat::from_extension("data.CSV"), Some(PreviewFormat::Csv));
+        assert_eq!(PreviewFormat::from_extension("notes.txt"), None);
+    }
+
+    #[test]
+    fn directory_tree_is_deleted_only_with_its_name_retyped() {
+        let dir = test_dir("tree");
+        let tree = dir.join("arbre");
+        fs::create_dir_all(tree.join("a").join("b")).unwrap();
+        fs::write(tree.join("un.txt"), "1").unwrap();
+        fs::write(tree.join("a").join("b").join("deux.txt"), "2").unwrap();
+        assert_eq!(count_tree(&tree).unwrap(), (2, 2));
+
+        let err = delete_tree(&tree, "autre").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(tree.is_dir());
+
+        let err = delete_tree(&tree.join("un.txt"), &tree.join("un.txt").to_string_lossy()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        delete_tree(&tree, &tree.to_string_lossy()).unwrap();
+        assert!(!tree.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn working_directory_and_its_parents_are_protected() {
+        let cwd = std::env::current_dir().unwrap();
+        assert!(is_protected_dir(Path::new(".")));
+        assert!(is_protected_dir(cwd.parent().unwrap()));
+        let err = delete_tree(Path::new("."), ".").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn line_endings_are_detected() {
+        let lf = analyze_line_endings(b"a\nb\n");
+        assert_eq!(lf, LineEndingReport { lf: 2, crlf: 0, bom: None, trailing_newline: true });
+        assert_eq!(lf.style(), "LF");
+
+        let crlf = analyze_line_endings(b"\xEF\xBB\xBFa\r\nb");
+        assert_eq!(crlf, LineEndingReport { lf: 0, crlf: 1, bom: Some(Bom::Utf8), trailing_newline: false });
+        assert_eq!(crlf.style(), "CRLF");
+
+        let mixed = analyze_line_endings(b"a\r\nb\nc\r\n");
+        assert_eq!((mixed.lf, mixed.crlf), (1, 2));
+        assert_eq!(mixed.style(), "mixte");
+
+        assert_eq!(analyze_line_endings(b"").style(), "aucune");
+        assert_eq!(analyze_line_endings(b"\xFF\xFEa\x00\n\x00").bom, Some(Bom::Utf16Le));
+    }
+
+    #[test]
+    fn line_endings_are_normalized() {
+        assert_eq!(normalize_line_endings(b"a\r\nb\nc\r", LineEnding::Lf), b"a\nb\nc\r");
+        assert_eq!(normalize_line_endings(b"a\r\nb\n", LineEnding::Crlf), b"a\r\nb\r\n");
+    }
+
+    #[test]
+    fn rust_snippet_is_highlighted_with_ansi_colors() {
+        let output = highlight("fn main() {\n    let x = 1;\n}\n", "rs").unwrap();
+        assert!(output.contains("\x1b[38;2;"));
+        assert!(output.ends_with("\x1b[0m"));
+        assert!(output.contains("main"));
+        assert!(highlight("fn main() {}\n", "Rust").is_some());
+        assert!(highlight("texte", "langage-inconnu").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_chains_are_described_and_resolved() {
+        let dir = test_dir("symlink");
+        let dir = fs::canonicalize(&dir).unwrap();
+        let target = dir.join("cible.txt");
+        fs::write(&target, "contenu").unwrap();
+        let first = dir.join("lien1");
+        let second = dir.join("lien2");
+        std::os::unix::fs::symlink("cible.txt", &first).unwrap();
+        std::os::unix::fs::symlink(&first, &second).unwrap();
+        let second_name = second.to_string_lossy().to_string();
+
+        assert_eq!(
+            describe_symlink(&second_name),
+            format!("{} est un lien symbolique vers {}", second_name, first.display())
+        );
+        assert_eq!(resolve_symlink(&second_name), format!("{} -> {}", second_name, target.display()));
+        assert_eq!(
+            describe_symlink(&target.to_string_lossy()),
+            format!("{} n'est pas un lien symbolique", target.display())
+        );
+
+        fs::remove_file(&target).unwrap();
+        assert!(resolve_symlink(&second_name).starts_with("Résolution impossible"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn manifest_verification_flags_changed_added_and_removed_files() {
+        let dir = test_dir("manifest");
+        fs::create_dir_all(dir.join("sous")).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("sous").join("b.txt"), "b").unwrap();
+        fs::write(dir.join("c.txt"), "c").unwrap();
+
+        let entries = build_manifest(&dir).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["a.txt", "c.txt", "sous/b.txt"]);
+        let manifest = dir.join(MANIFEST_FILE);
+        write_manifest(&manifest, &entries).unwrap();
+        assert_eq!(read_manifest(&manifest).unwrap(), entries);
+        assert!(verify_manifest(&dir, &manifest).unwrap().is_empty());
+
+        fs::write(dir.join("sous").join("b.txt"), "B").unwrap();
+        fs::remove_file(dir.join("c.txt")).unwrap();
+        fs::write(dir.join("d.txt"), "d").unwrap();
+        assert_eq!(
+            verify_manifest(&dir, &manifest).unwrap(),
+            ManifestDiff {
+                added: vec!["d.txt".to_string()],
+                removed: vec!["c.txt".to_string()],
+                changed: vec!["sous/b.txt".to_string()],
+            }
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn timestamp_lines_are_appended_in_order() {
+        let dir = test_dir("timestamp");
+        let path = dir.join("journal.txt");
+        let first = chrono::Utc.with_ymd_and_hms(2024, 3, 1, 9, 5, 0).unwrap();
+        let second = chrono::Utc.with_ymd_and_hms(2024, 3, 1, 17, 30, 12).unwrap();
+
+        assert_eq!(timestamp_line(&first, "  "), "2024-03-01 09:05:00");
+        append_line(&path, &timestamp_line(&first, "début")).unwrap();
+        append_line(&path, &timestamp_line(&second, " fin ")).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "2024-03-01 09:05:00 début\n2024-03-01 17:30:12 fin\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn byte_range_is_patched_in_place_or_resized() {
+        let dir = test_dir("patch");
+        let path = dir.join("data.bin");
+        fs::write(&path, b"0123456789").unwrap();
+
+        patch_range(&path, 2, 3, &parse_hex("41 42 43").unwrap()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"01ABC56789");
+
+        patch_range(&path, 8, 2, b"Z").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"01ABC567Z");
+        patch_range(&path, 0, 1, b"xyz").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"xyz1ABC567Z");
+
+        let err = patch_range(&path, 10, 5, b"").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read(&path).unwrap(), b"xyz1ABC567Z");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hex_input_is_parsed() {
+        assert_eq!(parse_hex("0a ff00"), Some(vec![0x0a, 0xff, 0x00]));
+        assert_eq!(parse_hex(""), Some(vec![]));
+        assert_eq!(parse_hex("abc"), None);
+        assert_eq!(parse_hex("zz"), None);
+    }
+
+    #[test]
+    fn file_events_are_formatted_relative_to_the_working_directory() {
+        use notify::event::{CreateKind, MetadataKind, RemoveKind, RenameMode};
+
+        let cwd = std::env::current_dir().unwrap();
+        let created = Event::new(EventKind::Create(CreateKind::File)).add_path(cwd.join("nouveau.txt"));
+        assert_eq!(format_event(&created), vec!["[CREATED] nouveau.txt"]);
+
+        let renamed = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from("./ancien.txt"))
+            .add_path(PathBuf::from("/ailleurs/nouveau.txt"));
+        assert_eq!(format_event(&renamed), vec!["[RENAMED] ancien.txt", "[RENAMED] /ailleurs/nouveau.txt"]);
+
+        let removed = Event::new(EventKind::Remove(RemoveKind::File)).add_path(cwd.join("a.txt"));
+        assert_eq!(format_event(&removed), vec!["[DELETED] a.txt"]);
+
+        let metadata = Event::new(EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any))).add_path(cwd.join("a.txt"));
+        assert!(format_event(&metadata).is_empty());
+    }
+
+    #[test]
+    fn occurrences_are_counted_per_text_file() {
+        let dir = test_dir("count");
+        fs::write(dir.join("a.txt"), "foo foo\nbar foo\n").unwrap();
+        fs::write(dir.join("b.txt"), "foofoo").unwrap();
+        fs::write(dir.join("c.txt"), "rien ici").unwrap();
+        fs::write(dir.join("d.bin"), b"foo\0foo").unwrap();
+        fs::create_dir_all(dir.join("sous")).unwrap();
+
+        let counts = count_occurrences(&dir, &Regex::new("foo").unwrap()).unwrap();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[&dir.join("a.txt")], 3);
+        assert_eq!(counts[&dir.join("b.txt")], 2);
+
+        // Les correspondances vides ne comptent pas
+        assert!(count_occurrences(&dir, &Regex::new("x*").unwrap()).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+// Répertoire de travail propre à un test, recréé à vide
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tp2-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Lance tp2 sans entrée standard : toute lecture de stdin obtiendrait une
+// réponse vide, donc un refus
+fn run_tp2(dir: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tp2"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn delete_with_yes_completes_without_reading_stdin() {
+    let dir = test_dir("delete-yes");
+    fs::write(dir.join("a.txt"), "contenu").unwrap();
+
+    let output = run_tp2(&dir, &["--yes", "delete", "a.txt"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(!dir.join("a.txt").exists());
+    assert_eq!(fs::read_dir(dir.join(".trash")).unwrap().count(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn delete_without_yes_is_cancelled_when_stdin_is_empty() {
+    let dir = test_dir("delete-no");
+    fs::write(dir.join("a.txt"), "contenu").unwrap();
+
+    let output = run_tp2(&dir, &["delete", "a.txt"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(dir.join("a.txt").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn hard_delete_with_yes_skips_the_trash() {
+    let dir = test_dir("delete-hard");
+    fs::write(dir.join("a.txt"), "contenu").unwrap();
+
+    let output = run_tp2(&dir, &["delete", "a.txt", "--yes", "--hard-delete"]);
+    assert!(output.status.success());
+    assert!(!dir.join("a.txt").exists());
+    assert!(!dir.join(".trash").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
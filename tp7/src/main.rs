@@ -1,13 +1,62 @@
-use std::collections::HashMap;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use tokio::net::UdpSocket;
-use std::io::{Cursor, Result as IoResult};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use futures_util::future::join_all;
+use tracing::{debug, info, info_span, warn, Level};
 
 
+/// Deuxième mot de l'en-tête, décodé :
+/// QR | OPCODE (4 bits) | AA | TC | RD | RA | Z (3 bits) | RCODE (4 bits)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DnsFlags {
+    pub qr: bool,     // Réponse (true) ou requête
+    pub opcode: u16,  // 0 requête standard, 1 inverse, 2 statut, 4 notify, 5 update
+    pub aa: bool,     // Réponse faisant autorité
+    pub tc: bool,     // Réponse tronquée
+    pub rd: bool,     // Récursion demandée
+    pub ra: bool,     // Récursion disponible
+    pub z: u16,       // Bits réservés (Z, AD, CD), conservés tels quels
+    pub rcode: u16,   // Code de retour
+}
+
+impl DnsFlags {
+    pub fn from_u16(flags: u16) -> Self {
+        Self {
+            qr: flags & 0x8000 != 0,
+            opcode: (flags >> 11) & 0x000F,
+            aa: flags & 0x0400 != 0,
+            tc: flags & 0x0200 != 0,
+            rd: flags & 0x0100 != 0,
+            ra: flags & 0x0080 != 0,
+            z: (flags >> 4) & 0x0007,
+            rcode: flags & 0x000F,
+        }
+    }
+
+    pub fn to_u16(self) -> u16 {
+        (self.qr as u16) << 15
+            | (self.opcode & 0x000F) << 11
+            | (self.aa as u16) << 10
+            | (self.tc as u16) << 9
+            | (self.rd as u16) << 8
+            | (self.ra as u16) << 7
+            | (self.z & 0x0007) << 4
+            | (self.rcode & 0x000F)
+    }
+}
+
+/// Taille fixe de l'en-tête : un datagramme plus court n'est pas un message DNS
+pub const DNS_HEADER_SIZE: usize = 12;
+
 #[derive(Debug, Clone)]
 pub struct DnsHeader {
     pub id: u16,
-    pub flags: u16,
+    pub flags: DnsFlags,
     pub qdcount: u16,  // Nombre de questions
     pub ancount: u16,  // Nombre de réponses
     pub nscount: u16,  // Nombre d'enregistrements d'autorité
@@ -31,6 +80,60 @@ pub struct DnsResourceRecord {
     pub rdata: Vec<u8>,
 }
 
+/// Données d'un enregistrement SOA (type 6) : autorité de la zone et
+/// paramètres de rafraîchissement ; `minimum` sert de TTL au cache négatif
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoaRecord {
+    pub mname: String,   // Serveur de noms primaire
+    pub rname: String,   // Adresse du responsable (le premier '.' remplace '@')
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+/// Données des enregistrements autres que A servis par le serveur
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordData {
+    Aaaa(Ipv6Addr),
+    Txt(String),
+    Mx { preference: u16, exchange: String },
+    Ns(String),
+}
+
+impl RecordData {
+    pub fn rtype(&self) -> u16 {
+        match self {
+            RecordData::Aaaa(_) => 28,
+            RecordData::Txt(_) => 16,
+            RecordData::Mx { .. } => 15,
+            RecordData::Ns(_) => 2,
+        }
+    }
+
+    /// Encode les données ; un TXT est découpé en chaînes de 255 octets au plus
+    pub fn to_rdata(&self) -> Vec<u8> {
+        match self {
+            RecordData::Aaaa(ip) => ip.octets().to_vec(),
+            RecordData::Txt(text) => {
+                let mut bytes = Vec::new();
+                for chunk in text.as_bytes().chunks(255) {
+                    bytes.push(chunk.len() as u8);
+                    bytes.extend_from_slice(chunk);
+                }
+                bytes
+            }
+            RecordData::Mx { preference, exchange } => {
+                let mut bytes = preference.to_be_bytes().to_vec();
+                bytes.extend_from_slice(&encode_domain_name(exchange));
+                bytes
+            }
+            RecordData::Ns(nameserver) => encode_domain_name(nameserver),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DnsMessage {
     pub header: DnsHeader,
@@ -45,7 +148,7 @@ impl DnsHeader {
     pub fn new_query(id: u16) -> Self {
         Self {
             id,
-            flags: 0x0100, // QR=0 (query), RD=1 (recursion desired)
+            flags: DnsFlags { rd: true, ..DnsFlags::default() },
             qdcount: 1,
             ancount: 0,
             nscount: 0,
@@ -56,7 +159,7 @@ impl DnsHeader {
     pub fn new_response(id: u16, questions: u16, answers: u16) -> Self {
         Self {
             id,
-            flags: 0x8180, // QR=1 (response), RD=1, RA=1 (recursion available)
+            flags: DnsFlags { qr: true, rd: true, ra: true, ..DnsFlags::default() },
             qdcount: questions,
             ancount: answers,
             nscount: 0,
@@ -65,9 +168,9 @@ impl DnsHeader {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(12);
+        let mut bytes = Vec::with_capacity(DNS_HEADER_SIZE);
         bytes.extend_from_slice(&self.id.to_be_bytes());
-        bytes.extend_from_slice(&self.flags.to_be_bytes());
+        bytes.extend_from_slice(&self.flags.to_u16().to_be_bytes());
         bytes.extend_from_slice(&self.qdcount.to_be_bytes());
         bytes.extend_from_slice(&self.ancount.to_be_bytes());
         bytes.extend_from_slice(&self.nscount.to_be_bytes());
@@ -76,13 +179,13 @@ impl DnsHeader {
     }
 
     pub fn from_bytes(data: &[u8]) -> Option<Self> {
-        if data.len() < 12 {
+        if data.len() < DNS_HEADER_SIZE {
             return None;
         }
         
         Some(Self {
             id: u16::from_be_bytes([data[0], data[1]]),
-            flags: u16::from_be_bytes([data[2], data[3]]),
+            flags: DnsFlags::from_u16(u16::from_be_bytes([data[2], data[3]])),
             qdcount: u16::from_be_bytes([data[4], data[5]]),
             ancount: u16::from_be_bytes([data[6], data[7]]),
             nscount: u16::from_be_bytes([data[8], data[9]]),
@@ -100,21 +203,17 @@ impl DnsQuestion {
         }
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        
-        // Encoder le nom de domaine
-        for label in self.qname.split('.') {
-            if !label.is_empty() {
-                bytes.push(label.len() as u8);
-                bytes.extend_from_slice(label.as_bytes());
-            }
-        }
-        bytes.push(0); // Terminateur
+    /// Même question : nom (sans tenir compte de la casse), type et classe
+    pub fn matches(&self, other: &DnsQuestion) -> bool {
+        names_match(&self.qname, &other.qname) && self.qtype == other.qtype && self.qclass == other.qclass
+    }
+
+    /// Ajoute la question à la fin du message en cours d'écriture
+    pub fn write_to(&self, bytes: &mut Vec<u8>, names: &mut NameCompressor) {
+        names.write_name(bytes, &self.qname);
         
         bytes.extend_from_slice(&self.qtype.to_be_bytes());
         bytes.extend_from_slice(&self.qclass.to_be_bytes());
-        bytes
     }
 
     pub fn from_bytes(data: &[u8], offset: &mut usize) -> Option<Self> {
@@ -144,25 +243,92 @@ impl DnsResourceRecord {
         }
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        
-        // Encoder le nom
-        for label in self.name.split('.') {
-            if !label.is_empty() {
-                bytes.push(label.len() as u8);
-                bytes.extend_from_slice(label.as_bytes());
-            }
+    pub fn new_soa_record(name: String, soa: &SoaRecord, ttl: u32) -> Self {
+        let rdata = soa.to_rdata();
+        Self {
+            name,
+            rtype: 6, // SOA
+            rclass: 1, // IN
+            ttl,
+            rdlength: rdata.len() as u16,
+            rdata,
+        }
+    }
+
+    pub fn new_record(name: String, data: &RecordData, ttl: u32) -> Self {
+        let rdata = data.to_rdata();
+        Self {
+            name,
+            rtype: data.rtype(),
+            rclass: 1, // IN
+            ttl,
+            rdlength: rdata.len() as u16,
+            rdata,
         }
-        bytes.push(0);
+    }
+
+    /// Décode les données SOA d'un enregistrement de type 6
+    pub fn soa(&self) -> Option<SoaRecord> {
+        if self.rtype != 6 {
+            return None;
+        }
+        SoaRecord::from_bytes(&self.rdata, &mut 0)
+    }
+
+    /// Adresse d'un enregistrement A
+    pub fn a_address(&self) -> Option<Ipv4Addr> {
+        let octets: [u8; 4] = self.rdata.as_slice().try_into().ok()?;
+        (self.rtype == 1).then(|| Ipv4Addr::from(octets))
+    }
+
+    /// Serveur désigné par un enregistrement NS
+    pub fn ns_name(&self) -> Option<String> {
+        (self.rtype == 2).then(|| decode_domain_name(&self.rdata, &mut 0)).flatten()
+    }
+
+    /// Ajoute l'enregistrement à la fin du message en cours d'écriture. Le nom,
+    /// et ceux des données NS, CNAME, MX et SOA, peuvent être compressés ;
+    /// la longueur des données est celle effectivement écrite
+    pub fn write_to(&self, bytes: &mut Vec<u8>, names: &mut NameCompressor) {
+        names.write_name(bytes, &self.name);
         
         bytes.extend_from_slice(&self.rtype.to_be_bytes());
         bytes.extend_from_slice(&self.rclass.to_be_bytes());
         bytes.extend_from_slice(&self.ttl.to_be_bytes());
-        bytes.extend_from_slice(&self.rdlength.to_be_bytes());
+        let rdlength_at = bytes.len();
+        bytes.extend_from_slice(&[0, 0]);
+        let rdata_start = bytes.len();
+        self.write_rdata(bytes, names);
+        let rdlength = (bytes.len() - rdata_start) as u16;
+        bytes[rdlength_at..rdata_start].copy_from_slice(&rdlength.to_be_bytes());
+    }
+
+    // `rdata` est stocké sans compression (voir `from_bytes`) : les noms y sont
+    // décodés puis réécrits via `names`, le reste est recopié tel quel
+    fn write_rdata(&self, bytes: &mut Vec<u8>, names: &mut NameCompressor) {
+        match self.rtype {
+            2 | 5 => {
+                if let Some(target) = decode_domain_name(&self.rdata, &mut 0) {
+                    names.write_name(bytes, &target);
+                    return;
+                }
+            }
+            6 => {
+                if let Some(soa) = self.soa() {
+                    soa.write_to(bytes, names);
+                    return;
+                }
+            }
+            15 if self.rdata.len() >= 2 => {
+                if let Some(exchange) = decode_domain_name(&self.rdata, &mut 2) {
+                    bytes.extend_from_slice(&self.rdata[..2]);
+                    names.write_name(bytes, &exchange);
+                    return;
+                }
+            }
+            _ => {}
+        }
         bytes.extend_from_slice(&self.rdata);
-        
-        bytes
     }
 
     pub fn from_bytes(data: &[u8], offset: &mut usize) -> Option<Self> {
@@ -185,8 +351,33 @@ impl DnsResourceRecord {
             return None;
         }
         
-        let rdata = data[*offset..*offset + rdlength as usize].to_vec();
-        *offset += rdlength as usize;
+        let wire_length = rdlength as usize;
+        let mut rdata = data[*offset..*offset + wire_length].to_vec();
+        let mut rdlength = rdlength;
+        
+        // NS, CNAME, MX et SOA : les noms peuvent contenir des pointeurs de compression
+        // vers le reste du message, on les stocke donc sous forme décompressée
+        let mut rdata_offset = *offset;
+        match rtype {
+            2 | 5 => {
+                let target = decode_domain_name(data, &mut rdata_offset)?;
+                rdata = encode_domain_name(&target);
+                rdlength = rdata.len() as u16;
+            }
+            6 => {
+                rdata = SoaRecord::from_bytes(data, &mut rdata_offset)?.to_rdata();
+                rdlength = rdata.len() as u16;
+            }
+            15 if wire_length >= 2 => {
+                let preference = u16::from_be_bytes([rdata[0], rdata[1]]);
+                rdata_offset += 2;
+                let exchange = decode_domain_name(data, &mut rdata_offset)?;
+                rdata = RecordData::Mx { preference, exchange }.to_rdata();
+                rdlength = rdata.len() as u16;
+            }
+            _ => {}
+        }
+        *offset += wire_length;
         
         Some(Self {
             name, rtype, rclass, ttl, rdlength, rdata
@@ -194,6 +385,124 @@ impl DnsResourceRecord {
     }
 }
 
+impl SoaRecord {
+    /// Encode les données SOA ; les noms sont écrits en entier, la compression
+    /// n'étant possible qu'une fois la position dans le message connue
+    pub fn to_rdata(&self) -> Vec<u8> {
+        let mut bytes = encode_domain_name(&self.mname);
+        bytes.extend_from_slice(&encode_domain_name(&self.rname));
+        self.write_fields(&mut bytes);
+        bytes
+    }
+
+    /// Comme `to_rdata`, écrit dans un message avec compression des deux noms
+    pub fn write_to(&self, bytes: &mut Vec<u8>, names: &mut NameCompressor) {
+        names.write_name(bytes, &self.mname);
+        names.write_name(bytes, &self.rname);
+        self.write_fields(bytes);
+    }
+
+    fn write_fields(&self, bytes: &mut Vec<u8>) {
+        for value in [self.serial, self.refresh, self.retry, self.expire, self.minimum] {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    /// Décode des données SOA à `offset` dans `data` (message complet, pour
+    /// suivre les pointeurs de compression des noms)
+    pub fn from_bytes(data: &[u8], offset: &mut usize) -> Option<Self> {
+        let mname = decode_domain_name(data, offset)?;
+        let rname = decode_domain_name(data, offset)?;
+        
+        if *offset + 20 > data.len() {
+            return None;
+        }
+        
+        let mut fields = [0u32; 5];
+        for (i, field) in fields.iter_mut().enumerate() {
+            let start = *offset + i * 4;
+            *field = u32::from_be_bytes([data[start], data[start + 1], data[start + 2], data[start + 3]]);
+        }
+        *offset += 20;
+        
+        let [serial, refresh, retry, expire, minimum] = fields;
+        Some(Self { mname, rname, serial, refresh, retry, expire, minimum })
+    }
+}
+
+/// Vidage hexadécimal façon `hexdump -C` : une ligne par 16 octets, avec le
+/// décalage, les octets en hexadécimal puis leur version ASCII (`.` si non imprimable)
+pub fn hexdump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            format!("{:04x}  {:<47}  |{}|", index * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Fonction utilitaire pour encoder un nom de domaine en labels DNS
+fn encode_domain_name(name: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for label in name.split('.') {
+        if !label.is_empty() {
+            bytes.push(label.len() as u8);
+            bytes.extend_from_slice(label.as_bytes());
+        }
+    }
+    bytes.push(0); // Terminateur
+    bytes
+}
+
+/// Taille minimale (octets sur le fil, terminateur compris) d'un suffixe remplacé
+/// par un pointeur de 2 octets : en dessous, le gain est nul ou d'un seul octet
+pub const MIN_COMPRESSED_SUFFIX: usize = 4;
+
+/// Les pointeurs de compression ne codent que 14 bits de position
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+
+/// Position des suffixes de noms déjà écrits dans un message, pour les
+/// remplacer ensuite par un pointeur de compression
+#[derive(Debug, Default)]
+pub struct NameCompressor {
+    suffixes: HashMap<String, usize>,
+}
+
+impl NameCompressor {
+    /// Écrit `name` à la fin de `bytes`, en remplaçant par un pointeur le plus
+    /// long suffixe déjà présent s'il est assez long pour que ce soit rentable
+    pub fn write_name(&mut self, bytes: &mut Vec<u8>, name: &str) {
+        let labels: Vec<&str> = name.split('.').filter(|label| !label.is_empty()).collect();
+        for i in 0..labels.len() {
+            let suffix = labels[i..].join(".").to_ascii_lowercase();
+            let encoded_len = labels[i..].iter().map(|label| label.len() + 1).sum::<usize>() + 1;
+            if encoded_len >= MIN_COMPRESSED_SUFFIX
+                && let Some(&offset) = self.suffixes.get(&suffix) {
+                bytes.extend_from_slice(&(0xC000 | offset as u16).to_be_bytes());
+                return;
+            }
+            if bytes.len() <= MAX_POINTER_OFFSET {
+                self.suffixes.entry(suffix).or_insert(bytes.len());
+            }
+            bytes.push(labels[i].len() as u8);
+            bytes.extend_from_slice(labels[i].as_bytes());
+        }
+        bytes.push(0); // Terminateur
+    }
+}
+
+// Compare deux noms de domaine sans tenir compte de la casse ni du point final
+fn names_match(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.').eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
 // Fonction utilitaire pour décoder les noms de domaine DNS
 fn decode_domain_name(data: &[u8], offset: &mut usize) -> Option<String> {
     let mut labels = Vec::new();
@@ -254,15 +563,16 @@ impl DnsMessage {
 
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
+        let mut names = NameCompressor::default();
         
         bytes.extend_from_slice(&self.header.to_bytes());
         
         for question in &self.questions {
-            bytes.extend_from_slice(&question.to_bytes());
+            question.write_to(&mut bytes, &mut names);
         }
         
-        for answer in &self.answers {
-            bytes.extend_from_slice(&answer.to_bytes());
+        for record in self.answers.iter().chain(&self.authority).chain(&self.additional) {
+            record.write_to(&mut bytes, &mut names);
         }
         
         bytes
@@ -270,7 +580,7 @@ impl DnsMessage {
 
     pub fn from_bytes(data: &[u8]) -> Option<Self> {
         let header = DnsHeader::from_bytes(data)?;
-        let mut offset = 12;
+        let mut offset = DNS_HEADER_SIZE;
         
         let mut questions = Vec::new();
         for _ in 0..header.qdcount {
@@ -284,202 +594,1853 @@ impl DnsMessage {
             answers.push(answer);
         }
         
+        let mut authority = Vec::new();
+        for _ in 0..header.nscount {
+            authority.push(DnsResourceRecord::from_bytes(data, &mut offset)?);
+        }
+        
+        let mut additional = Vec::new();
+        for _ in 0..header.arcount {
+            additional.push(DnsResourceRecord::from_bytes(data, &mut offset)?);
+        }
+        
         Some(Self {
             header,
             questions,
             answers,
-            authority: Vec::new(),
-            additional: Vec::new(),
+            authority,
+            additional,
         })
     }
 }
 
+/// Durée par défaut du cache négatif (noms inexistants)
+pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(30);
+
+/// Délai d'attente par défaut d'une réponse, et nombre d'envois par requête
+pub const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_ATTEMPTS: u32 = 2;
+
+/// Fichier listant les résolveurs du système (lignes `nameserver`)
+pub const DEFAULT_RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Options de construction du client DNS
+#[derive(Debug, Clone)]
+pub struct DnsClientOptions {
+    /// Nouveau port source éphémère pour chaque requête : un attaquant hors
+    /// chemin doit deviner l'ID *et* le port. Coûte un bind par requête.
+    pub randomize_port: bool,
+    /// Durée pendant laquelle une réponse vide (NXDOMAIN) est mémorisée
+    pub negative_ttl: Duration,
+    /// Attente maximale d'une réponse pour chaque envoi
+    pub timeout: Duration,
+    /// Nombre d'envois avant d'abandonner (au moins 1)
+    pub attempts: u32,
+    /// Serveur muet : réessayer auprès des résolveurs du système (Unix uniquement)
+    pub system_fallback: bool,
+    /// Fichier d'où lire les résolveurs du système
+    pub resolv_conf: PathBuf,
+    /// Port interrogé sur ces résolveurs (resolv.conf ne donne que des adresses)
+    pub system_port: u16,
+}
+
+impl Default for DnsClientOptions {
+    fn default() -> Self {
+        Self {
+            randomize_port: false,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            timeout: DEFAULT_QUERY_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            system_fallback: false,
+            resolv_conf: PathBuf::from(DEFAULT_RESOLV_CONF),
+            system_port: 53,
+        }
+    }
+}
+
 pub struct DnsClient {
     socket: UdpSocket,
     server_addr: SocketAddr,
+    options: DnsClientOptions,
+    // (nom, qtype) -> expiration de l'entrée négative
+    negative_cache: Mutex<HashMap<(String, u16), Instant>>,
+}
+
+// Choisir l'adresse locale selon la famille du serveur (IPv4 ou IPv6)
+async fn bind_local(server_addr: SocketAddr) -> IoResult<UdpSocket> {
+    let local_addr = if server_addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+    UdpSocket::bind(local_addr).await
 }
 
 impl DnsClient {
     pub async fn new(server_addr: SocketAddr) -> IoResult<Self> {
-        let socket = UdpSocket::bind("0.0.0.0:0").await?;
-        Ok(Self { socket, server_addr })
+        Self::with_options(server_addr, DnsClientOptions::default()).await
+    }
+
+    pub async fn with_options(server_addr: SocketAddr, options: DnsClientOptions) -> IoResult<Self> {
+        let socket = bind_local(server_addr).await?;
+        Ok(Self {
+            socket,
+            server_addr,
+            options,
+            negative_cache: Mutex::new(HashMap::new()),
+        })
     }
 
+    /// Adresse de `domain`, `None` si le serveur ne la connaît pas. Sans
+    /// réponse après `attempts` envois, renvoie une erreur `TimedOut` (après
+    /// avoir essayé les résolveurs du système si `system_fallback` est activé).
     pub async fn resolve(&self, domain: &str) -> IoResult<Option<Ipv4Addr>> {
+        Ok(self.lookup(domain, false).await?.first().copied())
+    }
+
+    /// Résout tous les noms en parallèle, chacun avec toutes ses adresses (vide
+    /// si le nom est inconnu). Une réponse tronquée est redemandée en TCP, pour
+    /// ce nom seulement ; les autres restent en UDP.
+    pub async fn resolve_many(&self, domains: &[&str]) -> Vec<IoResult<Vec<Ipv4Addr>>> {
+        // Une socket par requête : sur la socket partagée, une requête lirait
+        // la réponse destinée à une autre
+        join_all(domains.iter().map(|domain| self.lookup(domain, true))).await
+    }
+
+    async fn lookup(&self, domain: &str, dedicated_socket: bool) -> IoResult<Vec<Ipv4Addr>> {
+        // Nom déjà connu comme inexistant : pas de requête réseau
+        let cache_key = (domain.to_lowercase(), 1);
+        if self.is_negatively_cached(&cache_key) {
+            debug!(domain, "réponse servie par le cache négatif");
+            return Ok(Vec::new());
+        }
+
+        match self.query(self.server_addr, domain, cache_key, dedicated_socket).await {
+            Err(e) if e.kind() == ErrorKind::TimedOut && self.options.system_fallback => {
+                self.resolve_with_system(domain, e).await
+            }
+            result => result,
+        }
+    }
+
+    async fn query(
+        &self,
+        server_addr: SocketAddr,
+        domain: &str,
+        cache_key: (String, u16),
+        dedicated_socket: bool,
+    ) -> IoResult<Vec<Ipv4Addr>> {
         let query_id = rand::random_u16();
         let query = DnsMessage::new_query(query_id, domain);
         let query_bytes = query.to_bytes();
 
-        // Envoyer la requête
-        self.socket.send_to(&query_bytes, &self.server_addr).await?;
-        
-        // Recevoir la réponse
+        // Socket dédiée pour un autre serveur (famille d'adresse éventuellement différente)
+        let fresh_socket;
+        let socket = if dedicated_socket || self.options.randomize_port || server_addr != self.server_addr {
+            fresh_socket = bind_local(server_addr).await?;
+            &fresh_socket
+        } else {
+            &self.socket
+        };
+
         let mut buf = [0u8; 512];
-        let (len, _) = self.socket.recv_from(&mut buf).await?;
-        
-        if let Some(response) = DnsMessage::from_bytes(&buf[..len]) {
-            if response.header.id == query_id && !response.answers.is_empty() {
-                // Extraire l'adresse IP de la première réponse de type A
-                for answer in &response.answers {
-                    if answer.rtype == 1 && answer.rdata.len() == 4 {
-                        let ip = Ipv4Addr::new(
-                            answer.rdata[0],
-                            answer.rdata[1], 
-                            answer.rdata[2],
-                            answer.rdata[3]
-                        );
-                        return Ok(Some(ip));
+        for attempt in 1..=self.options.attempts.max(1) {
+            // Envoyer la requête (renvoyée telle quelle, même ID, en cas de perte)
+            socket.send_to(&query_bytes, &server_addr).await?;
+            let deadline = tokio::time::Instant::now() + self.options.timeout;
+            
+            // Un datagramme qui ne répond pas à cette requête est ignoré et
+            // l'attente continue : sinon le premier paquet usurpé l'interromprait
+            loop {
+                let len = match tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+                    Ok(received) => received?.0,
+                    Err(_) => {
+                        debug!(domain, attempt, server = %server_addr, "pas de réponse");
+                        break;
                     }
+                };
+                
+                // Trop court pour porter un ID : rien ne le rattache à cette
+                // requête, il est ignoré comme un datagramme d'un autre ID
+                if len < DNS_HEADER_SIZE {
+                    warn!(domain, len, server = %server_addr, "paquet trop court ignoré");
+                    continue;
+                }
+                let Some(response) = DnsMessage::from_bytes(&buf[..len]).filter(|r| r.header.id == query_id) else {
+                    debug!(domain, len, server = %server_addr, "datagramme illisible ou d'un autre ID ignoré");
+                    continue;
+                };
+                // Le bon ID ne suffit pas : la réponse doit reprendre notre question
+                // (sinon paquet usurpé ou réponse à une autre requête)
+                let echoed = response.questions.first();
+                if !echoed.is_some_and(|question| question.matches(&query.questions[0])) {
+                    warn!(
+                        domain,
+                        server = %server_addr,
+                        echoed = ?echoed.map(|q| (&q.qname, q.qtype, q.qclass)),
+                        "question de la réponse différente de la requête, datagramme ignoré"
+                    );
+                    continue;
+                }
+                // Réponse tronquée : la même question est reposée en TCP
+                let response = if response.header.flags.tc {
+                    debug!(domain, server = %server_addr, "réponse tronquée (TC), nouvel essai en TCP");
+                    self.query_tcp(server_addr, &query).await?
+                } else {
+                    response
+                };
+                let ips = extract_a_records(&response.answers, domain);
+                if ips.is_empty() {
+                    self.negative_cache
+                        .lock()
+                        .unwrap()
+                        .insert(cache_key, Instant::now() + self.options.negative_ttl);
                 }
+                return Ok(ips);
             }
         }
         
-        Ok(None)
+        Err(IoError::new(ErrorKind::TimedOut, format!("pas de réponse de {}", server_addr)))
     }
-}
 
-pub struct DnsServer {
-    socket: UdpSocket,
-    records: HashMap<String, Ipv4Addr>,
-}
+    /// Envoie `query` sur une connexion TCP, sans limite de taille de réponse
+    async fn query_tcp(&self, server_addr: SocketAddr, query: &DnsMessage) -> IoResult<DnsMessage> {
+        let exchange = async {
+            let mut stream = TcpStream::connect(server_addr).await?;
+            write_tcp_message(&mut stream, &query.to_bytes()).await?;
+            read_tcp_message(&mut stream).await
+        };
+        let bytes = tokio::time::timeout(self.options.timeout, exchange)
+            .await
+            .map_err(|_| IoError::new(ErrorKind::TimedOut, format!("pas de réponse TCP de {}", server_addr)))??;
 
-impl DnsServer {
-    pub async fn new(bind_addr: SocketAddr) -> IoResult<Self> {
-        let socket = UdpSocket::bind(bind_addr).await?;
-        let mut records = HashMap::new();
-        
-        // Ajouter quelques enregistrements prédéfinis
-        records.insert("example.com".to_string(), Ipv4Addr::new(93, 184, 216, 34));
-        records.insert("test.local".to_string(), Ipv4Addr::new(192, 168, 1, 100));
-        records.insert("myserver.local".to_string(), Ipv4Addr::new(10, 0, 0, 1));
-        records.insert("localhost".to_string(), Ipv4Addr::new(127, 0, 0, 1));
-        
-        Ok(Self { socket, records })
+        let response = DnsMessage::from_bytes(&bytes)
+            .filter(|response| response.header.id == query.header.id)
+            .filter(|response| response.questions.first().is_some_and(|q| q.matches(&query.questions[0])));
+        response.ok_or_else(|| IoError::new(ErrorKind::InvalidData, format!("réponse TCP invalide de {}", server_addr)))
     }
 
-    pub fn add_record(&mut self, domain: String, ip: Ipv4Addr) {
-        self.records.insert(domain, ip);
+    /// Comme `resolve`, avec la durée de l'aller-retour (quasi nulle pour une
+    /// réponse servie par le cache négatif)
+    pub async fn resolve_timed(&self, domain: &str) -> IoResult<(Option<Ipv4Addr>, Duration)> {
+        let started = Instant::now();
+        let ip = self.resolve(domain).await?;
+        Ok((ip, started.elapsed()))
     }
 
-    pub async fn run(&self) -> IoResult<()> {
-        println!("Serveur DNS démarré sur {}", self.socket.local_addr()?);
-        println!("Domaines configurés:");
-        for (domain, ip) in &self.records {
-            println!("  {} -> {}", domain, ip);
-        }
-        
-        let mut buf = [0u8; 512];
-        
-        loop {
-            let (len, src) = self.socket.recv_from(&mut buf).await?;
-            
-            if let Some(query) = DnsMessage::from_bytes(&buf[..len]) {
-                let response = self.handle_query(query);
-                let response_bytes = response.to_bytes();
-                
-                self.socket.send_to(&response_bytes, &src).await?;
-                
-                if let Some(question) = response.questions.first() {
-                    let status = if response.answers.is_empty() { "NXDOMAIN" } else { "RESOLVED" };
-                    println!("Query from {}: {} -> {}", src, question.qname, status);
+    /// Essaie dans l'ordre les résolveurs de `resolv_conf` ; renvoie `error`
+    /// si aucun ne répond
+    #[cfg(unix)]
+    async fn resolve_with_system(&self, domain: &str, error: IoError) -> IoResult<Vec<Ipv4Addr>> {
+        let resolvers = match std::fs::read_to_string(&self.options.resolv_conf) {
+            Ok(content) => parse_resolv_conf(&content),
+            Err(e) => {
+                warn!(path = %self.options.resolv_conf.display(), "résolveurs du système illisibles: {}", e);
+                return Err(error);
+            }
+        };
+        let resolvers = resolvers.into_iter().map(|r| SocketAddr::new(r.ip(), self.options.system_port));
+        for resolver in resolvers.filter(|&r| r != self.server_addr) {
+            let cache_key = (domain.to_lowercase(), 1);
+            match self.query(resolver, domain, cache_key, false).await {
+                Ok(ips) => {
+                    info!(domain, %resolver, "réponse obtenue du résolveur système");
+                    return Ok(ips);
                 }
+                Err(e) => warn!(domain, %resolver, "résolveur système en échec: {}", e),
             }
         }
+        Err(error)
     }
 
-    fn handle_query(&self, query: DnsMessage) -> DnsMessage {
-        let mut response = DnsMessage {
-            header: DnsHeader::new_response(query.header.id, 1, 0),
-            questions: query.questions.clone(),
-            answers: Vec::new(),
-            authority: Vec::new(),
-            additional: Vec::new(),
-        };
+    #[cfg(not(unix))]
+    async fn resolve_with_system(&self, domain: &str, error: IoError) -> IoResult<Vec<Ipv4Addr>> {
+        warn!(domain, "repli sur les résolveurs du système non pris en charge sur cette plateforme");
+        Err(error)
+    }
 
-        // Traiter la première question (DNS simple)
-        if let Some(question) = query.questions.first() {
-            if question.qtype == 1 { // Type A
-                if let Some(&ip) = self.records.get(&question.qname) {
-                    let answer = DnsResourceRecord::new_a_record(
-                        question.qname.clone(),
-                        ip,
-                        300 // TTL de 5 minutes
-                    );
-                    response.answers.push(answer);
-                    response.header.ancount = 1;
-                }
+    fn is_negatively_cached(&self, key: &(String, u16)) -> bool {
+        let mut cache = self.negative_cache.lock().unwrap();
+        match cache.get(key) {
+            Some(&expires) if Instant::now() < expires => true,
+            Some(_) => {
+                cache.remove(key);
+                false
             }
+            None => false,
         }
+    }
+}
 
-        response
+/// Interroge les serveurs dans l'ordre et s'arrête à la première adresse
+/// obtenue ; un serveur muet (délai dépassé), injoignable ou qui ne connaît pas
+/// le nom laisse la main au suivant
+pub async fn resolve_with_fallback(domain: &str, resolvers: &[SocketAddr]) -> Option<IpAddr> {
+    for &resolver in resolvers {
+        let client = match DnsClient::new(resolver).await {
+            Ok(client) => client,
+            Err(e) => {
+                warn!(%resolver, "client impossible à créer: {}", e);
+                continue;
+            }
+        };
+        match client.resolve(domain).await {
+            Ok(Some(ip)) => return Some(IpAddr::V4(ip)),
+            Ok(None) => debug!(domain, %resolver, "nom inconnu, serveur suivant"),
+            Err(e) => warn!(domain, %resolver, "échec: {}, serveur suivant", e),
+        }
     }
+    None
 }
 
-#[tokio::main]
-async fn main() -> IoResult<()> {
-    println!("Client et Serveur DNS Simple\n");
-    
-    // Démarrer le serveur DNS en arrière-plan
-    let server_addr = SocketAddr::from(([127, 0, 0, 1], 8053));
-    let server = DnsServer::new(server_addr).await?;
+/// Adresses des lignes `nameserver` d'un resolv.conf (port 53) ; les
+/// commentaires et les adresses illisibles (ex. IPv6 avec zone) sont ignorés
+pub fn parse_resolv_conf(content: &str) -> Vec<SocketAddr> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.split(['#', ';']).next()?;
+            let mut fields = line.split_whitespace();
+            if fields.next()? != "nameserver" {
+                return None;
+            }
+            let ip: IpAddr = fields.next()?.parse().ok()?;
+            Some(SocketAddr::new(ip, 53))
+        })
+        .collect()
+}
+
+/// Cherche les adresses A correspondant à `domain` dans les réponses, en suivant
+/// les CNAME. Les réponses dont le nom ne correspond pas à la question (ou à un
+/// alias de la chaîne) sont ignorées : un serveur malveillant ne peut pas
+/// glisser une adresse pour un autre nom.
+fn extract_a_records(answers: &[DnsResourceRecord], domain: &str) -> Vec<Ipv4Addr> {
+    let mut target = domain.to_string();
     
-    tokio::spawn(async move {
-        if let Err(e) = server.run().await {
-            eprintln!("Erreur serveur DNS: {}", e);
+    // Une chaîne de CNAME ne peut pas être plus longue que la liste des réponses
+    for _ in 0..=answers.len() {
+        let ips: Vec<Ipv4Addr> = answers
+            .iter()
+            .filter(|a| names_match(&a.name, &target))
+            .filter_map(DnsResourceRecord::a_address)
+            .collect();
+        if !ips.is_empty() {
+            return ips;
         }
-    });
-    
-    // Attendre un peu que le serveur démarre
-    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-    
-    // Tester le client DNS
-    println!("\nTest du Client DNS");
-    let client = DnsClient::new(server_addr).await?;
-    
-    let test_domains = vec![
-        "example.com",
-        "test.local", 
-        "localhost",
-        "unknown.domain"
-    ];
-    
-    for domain in test_domains {
-        match client.resolve(domain).await? {
-            Some(ip) => println!("{} résolu vers {}", domain, ip),
-            None => println!("{} non trouvé", domain),
+        
+        let Some(cname) = answers.iter().find(|a| a.rtype == 5 && names_match(&a.name, &target)) else {
+            break;
+        };
+        match decode_domain_name(&cname.rdata, &mut 0) {
+            Some(alias) => target = alias,
+            None => break,
         }
     }
     
-    println!("\nTest avec serveur DNS Google (8.8.8.8)");
-    let google_dns = SocketAddr::from(([8, 8, 8, 8], 53));
-    let google_client = DnsClient::new(google_dns).await?;
-    
-    match google_client.resolve("google.com").await? {
-        Some(ip) => println!("google.com résolu vers {} (via 8.8.8.8)", ip),
-        None => println!("google.com non résolu"),
-    }
-    
-    println!("\nAppuyez sur Ctrl+C pour arrêter le serveur...");
-    
-    // Garder le programme en vie
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    }
+    Vec::new()
 }
 
-// Module utilitaire pour générer des nombres aléatoires simples
-mod rand {
-    use std::convert::TryFrom;
-    use std::sync::atomic::{AtomicU32, Ordering};
-
-    static COUNTER: AtomicU32 = AtomicU32::new(1);
+/// Message DNS sur TCP : précédé de sa longueur sur deux octets (RFC 1035 §4.2.2)
+async fn read_tcp_message(stream: &mut TcpStream) -> IoResult<Vec<u8>> {
+    let len = stream.read_u16().await? as usize;
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes).await?;
+    Ok(bytes)
+}
 
-    pub fn random_u16() -> u16 {
-    let val = COUNTER.fetch_add(1, Ordering::Relaxed);
-    let time = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as u32;
-    (val.wrapping_mul(31).wrapping_add(time) & 0xFFFF) as u16
+async fn write_tcp_message(stream: &mut TcpStream, bytes: &[u8]) -> IoResult<()> {
+    let len = u16::try_from(bytes.len())
+        .map_err(|_| IoError::new(ErrorKind::InvalidInput, "message DNS trop long pour TCP"))?;
+    let mut framed = len.to_be_bytes().to_vec();
+    framed.extend_from_slice(bytes);
+    stream.write_all(&framed).await
 }
+
+/// Codes de retour DNS utilisés par le serveur
+pub const RCODE_NXDOMAIN: u16 = 3;
+pub const RCODE_NOTIMP: u16 = 4;
+pub const RCODE_REFUSED: u16 = 5;
+
+/// Seul opcode pris en charge : la requête standard (QUERY)
+pub const OPCODE_QUERY: u16 = 0;
+
+/// Type de requête ANY : tous les enregistrements connus pour le nom
+pub const QTYPE_ANY: u16 = 255;
+pub const QTYPE_NS: u16 = 2;
+
+/// Taille maximale d'une réponse UDP sans EDNS ; au-delà, TC est positionné
+pub const MAX_UDP_RESPONSE: usize = 512;
+
+/// Port d'écoute du serveur de démonstration
+pub const DEFAULT_PORT: u16 = 8053;
+
+/// TTL par défaut des enregistrements qui n'en précisent pas (5 minutes)
+pub const DEFAULT_TTL: u32 = 300;
+
+/// Nombre de requêtes conservées par défaut dans le journal en mémoire
+pub const DEFAULT_QUERY_LOG_SIZE: usize = 100;
+
+/// Délai pendant lequel une requête renvoyée (même source, ID et nom) reçoit
+/// la réponse déjà calculée
+pub const DEFAULT_RETRANSMIT_WINDOW: Duration = Duration::from_secs(2);
+
+/// Attente maximale d'une requête sur une connexion TCP acceptée
+pub const TCP_QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+type RetransmitKey = (SocketAddr, u16, String);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryResult {
+    Resolved(Vec<Ipv4Addr>),
+    NxDomain,
+    NoData, // Nom connu, mais sans enregistrement du type demandé
+    Delegated(String), // Renvoi vers ce serveur de noms
+    Refused,
+    NotImplemented,
+}
+
+/// Serveur parent vers lequel sont renvoyés les noms hors de nos zones : un NS
+/// pour la racine en autorité, et son adresse (glue) en additionnel
+#[derive(Debug, Clone)]
+pub struct Delegation {
+    pub nameserver: String,
+    pub address: Ipv4Addr,
+}
+
+/// Une requête reçue par le serveur, telle que conservée dans le journal
+#[derive(Debug, Clone)]
+pub struct QueryLogEntry {
+    pub qname: String,
+    pub qtype: u16,
+    pub source: SocketAddr,
+    pub result: QueryResult,
+    pub timestamp: SystemTime,
+}
+
+pub struct DnsServer {
+    socket: UdpSocket,
+    // Même port en TCP, pour les réponses tronquées en UDP
+    tcp_listener: TcpListener,
+    // Adresse et TTL propre à l'enregistrement (None : `default_ttl`)
+    records: HashMap<String, Vec<(Ipv4Addr, Option<u32>)>>,
+    // AAAA, TXT et MX, avec leur TTL propre comme `records`
+    other_records: HashMap<String, Vec<(RecordData, Option<u32>)>>,
+    // Réponses à toute requête avec tous les types connus, comme pour ANY
+    all_types: bool,
+    default_ttl: u32,
+    rotation: Mutex<HashMap<String, usize>>, // Décalage round-robin par nom
+    soa: SoaRecord, // Joint aux réponses négatives pour nos zones, au nom de leur apex
+    // Mode écoute seule : réponses uniquement depuis `records`, jamais de
+    // transfert vers un autre serveur (labo hors ligne / portail captif)
+    listen_only: bool,
+    // Mode autoritaire : AA pour les zones de `zones`, REFUSED ailleurs, pas de RA
+    authoritative: bool,
+    zones: Vec<String>,
+    // Mode relais : délégation vers un parent au lieu d'une réponse négative
+    delegation: Option<Delegation>,
+    // Octets bruts de chaque requête et réponse affichés (débogage de l'encodage)
+    hexdump: bool,
+    // Dernières requêtes, de la plus ancienne à la plus récente
+    query_log: Mutex<VecDeque<QueryLogEntry>>,
+    query_log_size: usize,
+    // Réponses récentes par (source, ID, nom), avec leur échéance
+    recent_responses: Mutex<HashMap<RetransmitKey, (Instant, Vec<u8>)>>,
+    retransmit_window: Duration,
+}
+
+/// Essais de liaison d'un port libre (port 0) commun à l'UDP et au TCP
+const BIND_ATTEMPTS: u32 = 5;
+
+/// Lie le TCP puis l'UDP sur le même port. Avec le port 0, le port choisi
+/// pour le TCP peut être déjà pris en UDP : on recommence alors avec un autre.
+async fn bind_udp_and_tcp(bind_addr: SocketAddr) -> IoResult<(UdpSocket, TcpListener)> {
+    let mut attempt = 1;
+    loop {
+        let tcp_listener = TcpListener::bind(bind_addr).await?;
+        match UdpSocket::bind(tcp_listener.local_addr()?).await {
+            Ok(socket) => return Ok((socket, tcp_listener)),
+            Err(e) if e.kind() == ErrorKind::AddrInUse && bind_addr.port() == 0 && attempt < BIND_ATTEMPTS => {
+                debug!(attempt, "port {} déjà pris en UDP, nouvel essai", tcp_listener.local_addr()?.port());
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl DnsServer {
+    pub async fn new(bind_addr: SocketAddr) -> IoResult<Self> {
+        let (socket, tcp_listener) = bind_udp_and_tcp(bind_addr).await?;
+        let mut records = HashMap::new();
+        
+        // Ajouter quelques enregistrements prédéfinis
+        records.insert("example.com".to_string(), vec![(Ipv4Addr::new(93, 184, 216, 34), None)]);
+        records.insert("test.local".to_string(), vec![(Ipv4Addr::new(192, 168, 1, 100), None)]);
+        records.insert("myserver.local".to_string(), vec![(Ipv4Addr::new(10, 0, 0, 1), None)]);
+        records.insert("localhost".to_string(), vec![(Ipv4Addr::new(127, 0, 0, 1), None)]);
+        
+        let soa = SoaRecord {
+            mname: "ns.local".to_string(),
+            rname: "admin.local".to_string(),
+            serial: 1,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 300,
+        };
+        
+        Ok(Self {
+            socket,
+            tcp_listener,
+            records,
+            other_records: HashMap::new(),
+            all_types: false,
+            default_ttl: DEFAULT_TTL,
+            rotation: Mutex::new(HashMap::new()),
+            soa,
+            listen_only: false,
+            authoritative: false,
+            zones: vec!["example.com".to_string(), "local".to_string(), "localhost".to_string()],
+            delegation: None,
+            hexdump: false,
+            query_log: Mutex::new(VecDeque::new()),
+            query_log_size: DEFAULT_QUERY_LOG_SIZE,
+            recent_responses: Mutex::new(HashMap::new()),
+            retransmit_window: DEFAULT_RETRANSMIT_WINDOW,
+        })
+    }
+
+    /// Adresse effectivement liée ; avec le port 0, celui choisi par le système
+    pub fn local_addr(&self) -> IoResult<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Ajoute une adresse au nom ; plusieurs appels pour un même nom
+    /// donnent plusieurs enregistrements A, servis en round-robin
+    pub fn add_record(&mut self, domain: String, ip: Ipv4Addr) {
+        self.add_record_with_ttl(domain, ip, None);
+    }
+
+    /// Comme `add_record`, avec un TTL propre à l'enregistrement (entrée de
+    /// zone) ; `None` utilise le TTL par défaut du serveur
+    pub fn add_record_with_ttl(&mut self, domain: String, ip: Ipv4Addr, ttl: Option<u32>) {
+        let entries = self.records.entry(domain).or_default();
+        match entries.iter_mut().find(|(existing, _)| *existing == ip) {
+            Some(entry) => entry.1 = ttl,
+            None => entries.push((ip, ttl)),
+        }
+    }
+
+    /// Ajoute un enregistrement AAAA, TXT ou MX au nom (`None` : TTL par défaut)
+    pub fn add_other_record(&mut self, domain: String, data: RecordData, ttl: Option<u32>) {
+        let entries = self.other_records.entry(domain).or_default();
+        match entries.iter_mut().find(|(existing, _)| *existing == data) {
+            Some(entry) => entry.1 = ttl,
+            None => entries.push((data, ttl)),
+        }
+    }
+
+    /// Répondre à toute requête avec tous les types connus pour le nom, et
+    /// pas seulement aux requêtes ANY
+    pub fn set_all_types(&mut self, all_types: bool) {
+        self.all_types = all_types;
+    }
+
+    /// TTL des enregistrements qui n'en précisent pas
+    pub fn set_default_ttl(&mut self, ttl: u32) {
+        self.default_ttl = ttl;
+    }
+
+    /// Active le mode écoute seule : tout nom inconnu reçoit NXDOMAIN, même
+    /// si une délégation est configurée
+    pub fn set_listen_only(&mut self, listen_only: bool) {
+        self.listen_only = listen_only;
+    }
+
+    /// Mode serveur faisant autorité sur ses zones (voir `add_zone`)
+    pub fn set_authoritative(&mut self, authoritative: bool) {
+        self.authoritative = authoritative;
+    }
+
+    pub fn add_zone(&mut self, zone: String) {
+        if !self.zones.iter().any(|z| names_match(z, &zone)) {
+            self.zones.push(zone);
+        }
+    }
+
+    /// Répondre aux noms hors de nos zones par une délégation vers `delegation`
+    /// plutôt que par NXDOMAIN (ou REFUSED en mode autoritaire). Les noms
+    /// configurés localement restent servis ; sans effet en écoute seule.
+    pub fn set_delegation(&mut self, delegation: Option<Delegation>) {
+        self.delegation = delegation;
+    }
+
+    /// Affiche le vidage hexadécimal de chaque datagramme reçu et envoyé
+    pub fn set_hexdump(&mut self, hexdump: bool) {
+        self.hexdump = hexdump;
+    }
+
+    /// Nombre de requêtes gardées en mémoire (0 : journal désactivé)
+    pub fn set_query_log_size(&mut self, size: usize) {
+        self.query_log_size = size;
+        let log = self.query_log.get_mut().unwrap();
+        while log.len() > size {
+            log.pop_front();
+        }
+    }
+
+    /// Durée de réutilisation d'une réponse pour une requête renvoyée
+    /// (`Duration::ZERO` : chaque requête est traitée)
+    pub fn set_retransmit_window(&mut self, window: Duration) {
+        self.retransmit_window = window;
+    }
+
+    /// Réponse déjà envoyée à cette requête si elle est encore dans la fenêtre
+    fn cached_response(&self, key: &RetransmitKey) -> Option<Vec<u8>> {
+        let cache = self.recent_responses.lock().unwrap();
+        match cache.get(key) {
+            Some((expires, bytes)) if Instant::now() < *expires => Some(bytes.clone()),
+            _ => None,
+        }
+    }
+
+    fn remember_response(&self, key: RetransmitKey, bytes: &[u8]) {
+        if self.retransmit_window.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let mut cache = self.recent_responses.lock().unwrap();
+        // Purge des entrées expirées : la taille reste bornée par le débit de la fenêtre
+        cache.retain(|_, (expires, _)| now < *expires);
+        cache.insert(key, (now + self.retransmit_window, bytes.to_vec()));
+    }
+
+    /// Copie des dernières requêtes, de la plus ancienne à la plus récente
+    pub fn recent_queries(&self) -> Vec<QueryLogEntry> {
+        self.query_log.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn log_query(&self, source: SocketAddr, query: &DnsMessage, response: &DnsMessage) {
+        let Some(question) = query.questions.first() else {
+            return;
+        };
+        if self.query_log_size == 0 {
+            return;
+        }
+        let result = if response.header.flags.rcode == RCODE_REFUSED {
+            QueryResult::Refused
+        } else if response.header.flags.rcode == RCODE_NOTIMP {
+            QueryResult::NotImplemented
+        } else if let Some(nameserver) = response.answers.iter().chain(&response.authority).find_map(|r| r.ns_name()) {
+            QueryResult::Delegated(nameserver)
+        } else if response.header.flags.rcode == RCODE_NXDOMAIN {
+            QueryResult::NxDomain
+        } else if response.answers.is_empty() {
+            QueryResult::NoData
+        } else {
+            QueryResult::Resolved(response.answers.iter().filter_map(|a| a.a_address()).collect())
+        };
+        let entry = QueryLogEntry {
+            qname: question.qname.clone(),
+            qtype: question.qtype,
+            source,
+            result,
+            timestamp: SystemTime::now(),
+        };
+
+        let mut log = self.query_log.lock().unwrap();
+        if log.len() == self.query_log_size {
+            log.pop_front();
+        }
+        log.push_back(entry);
+    }
+
+    /// Le nom a au moins un enregistrement, quel que soit son type
+    fn has_name(&self, name: &str) -> bool {
+        self.records.contains_key(name) || self.other_records.contains_key(name)
+    }
+
+    /// Zone possédée contenant `name` (le nom lui-même ou un sous-domaine)
+    fn zone_for(&self, name: &str) -> Option<&str> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        self.zones
+            .iter()
+            .find(|zone| {
+                let zone = zone.trim_end_matches('.').to_ascii_lowercase();
+                name == zone || name.ends_with(&format!(".{}", zone))
+            })
+            .map(String::as_str)
+    }
+
+    /// Sert l'UDP jusqu'à une erreur de socket ; le TCP tourne dans sa propre
+    /// tâche, et chaque connexion dans la sienne
+    pub async fn run(self: Arc<Self>) -> IoResult<()> {
+        info!("Serveur DNS démarré sur {}", self.socket.local_addr()?);
+        for (domain, entries) in &self.records {
+            for (ip, ttl) in entries {
+                info!("Domaine configuré: {} -> {} (TTL {})", domain, ip, ttl.unwrap_or(self.default_ttl));
+            }
+        }
+        for (domain, entries) in &self.other_records {
+            for (data, ttl) in entries {
+                info!("Domaine configuré: {} -> {:?} (TTL {})", domain, data, ttl.unwrap_or(self.default_ttl));
+            }
+        }
+        if self.listen_only {
+            info!("Mode écoute seule : transfert désactivé");
+        }
+        if self.authoritative {
+            info!("Mode autoritaire pour les zones {:?}", self.zones);
+        }
+        if let Some(delegation) = &self.delegation {
+            info!("Délégation hors zone vers {} ({})", delegation.nameserver, delegation.address);
+        }
+        
+        // Un client TCP lent ou muet ne doit jamais retarder l'UDP
+        let tcp = Arc::clone(&self);
+        let tcp_task = tokio::spawn(async move { tcp.accept_tcp().await });
+        
+        let mut buf = [0u8; 512];
+        let result = loop {
+            let (len, src) = match self.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => break Err(e),
+            };
+            if let Err(e) = self.handle_datagram(&buf[..len], src).await {
+                break Err(e);
+            }
+        };
+        tcp_task.abort();
+        result
+    }
+
+    /// Accepte les connexions TCP, chacune traitée dans sa propre tâche et
+    /// bornée par TCP_QUERY_TIMEOUT
+    async fn accept_tcp(self: Arc<Self>) {
+        loop {
+            let (stream, src) = match self.tcp_listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("connexion TCP refusée: {}", e);
+                    continue;
+                }
+            };
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                match tokio::time::timeout(TCP_QUERY_TIMEOUT, server.handle_tcp(stream, src)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!(%src, "requête TCP en échec: {}", e),
+                    Err(_) => warn!(%src, "requête TCP abandonnée (délai dépassé)"),
+                }
+            });
+        }
+    }
+
+    async fn handle_datagram(&self, datagram: &[u8], src: SocketAddr) -> IoResult<()> {
+        let len = datagram.len();
+        if self.hexdump {
+            info!(%src, len, "requête brute\n{}", hexdump(datagram));
+        }
+        
+        // Sans en-tête complet, pas même d'ID auquel répondre
+        if len < DNS_HEADER_SIZE {
+            warn!(%src, len, "paquet trop court rejeté");
+            return Ok(());
+        }
+        let Some(query) = DnsMessage::from_bytes(datagram) else {
+            warn!(%src, len, "requête illisible ignorée");
+            return Ok(());
+        };
+
+        // Retransmission d'une requête déjà traitée : même réponse, sans
+        // nouveau calcul (ni avancée du round-robin)
+        let qname = query.questions.first().map(|q| q.qname.to_ascii_lowercase()).unwrap_or_default();
+        let key = (src, query.header.id, qname);
+        if let Some(response_bytes) = self.cached_response(&key) {
+            debug!(%src, id = query.header.id, "retransmission, réponse précédente renvoyée");
+            if self.hexdump {
+                info!(%src, len = response_bytes.len(), "réponse brute\n{}", hexdump(&response_bytes));
+            }
+            self.socket.send_to(&response_bytes, &src).await?;
+            return Ok(());
+        }
+
+        // Un span par requête : tous les événements portent la source et l'ID
+        let span = info_span!("query", %src, id = query.header.id);
+        let mut response = span.in_scope(|| self.handle_query(query.clone()));
+        span.in_scope(|| truncate_for_udp(&mut response));
+        self.log_query(src, &query, &response);
+        let response_bytes = response.to_bytes();
+        self.remember_response(key, &response_bytes);
+        if self.hexdump {
+            info!(%src, len = response_bytes.len(), "réponse brute\n{}", hexdump(&response_bytes));
+        }
+        
+        self.socket.send_to(&response_bytes, &src).await?;
+        Ok(())
+    }
+
+    /// Une requête par connexion TCP, réponse complète (jamais tronquée)
+    async fn handle_tcp(&self, mut stream: TcpStream, src: SocketAddr) -> IoResult<()> {
+        let request = read_tcp_message(&mut stream).await?;
+        if self.hexdump {
+            info!(%src, len = request.len(), "requête TCP brute\n{}", hexdump(&request));
+        }
+        let Some(query) = DnsMessage::from_bytes(&request) else {
+            warn!(%src, len = request.len(), "requête TCP illisible ignorée");
+            return Ok(());
+        };
+
+        let span = info_span!("query", %src, id = query.header.id, tcp = true);
+        let response = span.in_scope(|| self.handle_query(query.clone()));
+        self.log_query(src, &query, &response);
+        let response_bytes = response.to_bytes();
+        if self.hexdump {
+            info!(%src, len = response_bytes.len(), "réponse TCP brute\n{}", hexdump(&response_bytes));
+        }
+        write_tcp_message(&mut stream, &response_bytes).await
+    }
+
+    fn handle_query(&self, query: DnsMessage) -> DnsMessage {
+        let mut response = DnsMessage {
+            header: DnsHeader::new_response(query.header.id, 1, 0),
+            questions: query.questions.clone(),
+            answers: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+        };
+
+        // Requête inverse, statut, notify, update... : non implémentés
+        let opcode = query.header.flags.opcode;
+        if opcode != OPCODE_QUERY {
+            response.header.flags.opcode = opcode; // La réponse reprend l'opcode
+            response.header.flags.rcode = RCODE_NOTIMP;
+            info!(opcode, "NOTIMP (opcode non pris en charge)");
+            return response;
+        }
+
+        // Nom hors de nos zones et absent de nos enregistrements, avec un parent
+        // configuré : renvoi vers celui-ci (jamais en écoute seule)
+        let delegation = self.delegation.as_ref().filter(|_| !self.listen_only);
+        let delegate = delegation.is_some()
+            && query
+                .questions
+                .first()
+                .is_some_and(|q| self.zone_for(&q.qname).is_none() && !self.has_name(&q.qname));
+
+        // Mode autoritaire : pas de récursion, et refus des noms hors de nos zones
+        // (sauf s'ils sont délégués)
+        if self.authoritative && !delegate {
+            response.header.flags.ra = false;
+            if let Some(question) = query.questions.first() {
+                if self.zone_for(&question.qname).is_none() {
+                    response.header.flags.rcode = RCODE_REFUSED;
+                    info!(qname = %question.qname, qtype = question.qtype, "REFUSED (hors zone)");
+                    return response;
+                }
+                response.header.flags.aa = true;
+            }
+        }
+
+        // Traiter la première question (DNS simple)
+        if let Some(question) = query.questions.first() {
+            let all_types = self.all_types || question.qtype == QTYPE_ANY;
+            if (question.qtype == 1 || all_types)
+                && let Some(entries) = self.records.get(&question.qname) {
+                // Rotation de l'ordre à chaque requête pour répartir la charge
+                let start = {
+                    let mut rotation = self.rotation.lock().unwrap();
+                    let counter = rotation.entry(question.qname.clone()).or_insert(0);
+                    let start = *counter % entries.len();
+                    *counter = counter.wrapping_add(1);
+                    start
+                };
+                for &(ip, ttl) in entries.iter().cycle().skip(start).take(entries.len()) {
+                    response.answers.push(DnsResourceRecord::new_a_record(
+                        question.qname.clone(),
+                        ip,
+                        ttl.unwrap_or(self.default_ttl),
+                    ));
+                }
+            }
+            if let Some(entries) = self.other_records.get(&question.qname) {
+                for (data, ttl) in entries.iter().filter(|(data, _)| all_types || data.rtype() == question.qtype) {
+                    response.answers.push(DnsResourceRecord::new_record(
+                        question.qname.clone(),
+                        data,
+                        ttl.unwrap_or(self.default_ttl),
+                    ));
+                }
+            }
+            response.header.ancount = response.answers.len() as u16;
+        }
+
+        // Délégation ; une requête NS pour la racine reçoit le NS en réponse (amorçage)
+        if delegate
+            && let (Some(delegation), Some(question)) = (delegation, query.questions.first()) {
+            let ns = DnsResourceRecord::new_record(
+                String::new(),
+                &RecordData::Ns(delegation.nameserver.clone()),
+                self.default_ttl,
+            );
+            let glue = DnsResourceRecord::new_a_record(delegation.nameserver.clone(), delegation.address, self.default_ttl);
+            if question.qname.is_empty() && matches!(question.qtype, QTYPE_NS | QTYPE_ANY) {
+                response.answers.push(ns);
+            } else {
+                response.authority.push(ns);
+            }
+            response.additional.push(glue);
+            response.header.ancount = response.answers.len() as u16;
+            response.header.nscount = response.authority.len() as u16;
+            response.header.arcount = response.additional.len() as u16;
+            info!(qname = %question.qname, qtype = question.qtype, nameserver = %delegation.nameserver, "délégation");
+            return response;
+        }
+
+        // Réponse négative : nom absent des enregistrements (NXDOMAIN), ou nom
+        // existant sans données de ce type (NOERROR sans réponse)
+        if response.answers.is_empty()
+            && let Some(question) = query.questions.first() {
+            if self.listen_only && self.delegation.is_some() {
+                debug!("nom inconnu, pas de délégation (écoute seule)");
+            }
+            if !self.has_name(&question.qname) {
+                response.header.flags.rcode = RCODE_NXDOMAIN;
+            }
+            // Pour nos zones, le SOA (au nom de l'apex) indique au client combien
+            // de temps mémoriser la réponse ; hors zone, nous n'avons pas d'autorité
+            if let Some(apex) = self.zone_for(&question.qname) {
+                response.authority.push(DnsResourceRecord::new_soa_record(apex.to_string(), &self.soa, self.soa.minimum));
+                response.header.nscount = 1;
+            }
+        }
+
+        match query.questions.first() {
+            Some(question) if response.header.flags.rcode == RCODE_NXDOMAIN => {
+                info!(qname = %question.qname, qtype = question.qtype, "NXDOMAIN");
+            }
+            Some(question) if response.answers.is_empty() => {
+                info!(qname = %question.qname, qtype = question.qtype, "pas de données pour ce type");
+            }
+            Some(question) => {
+                info!(qname = %question.qname, qtype = question.qtype, "résolu");
+            }
+            None => warn!("requête sans question"),
+        }
+
+        response
+    }
+}
+
+/// Trop grand pour un datagramme : TC, et les enregistrements qui dépassent
+/// sont retirés, réponses d'abord puis additionnels et autorité, jusqu'à ne
+/// garder au besoin que l'en-tête et la question (le client redemande en TCP)
+fn truncate_for_udp(response: &mut DnsMessage) {
+    if response.to_bytes().len() > MAX_UDP_RESPONSE {
+        response.header.flags.tc = true;
+        while response.to_bytes().len() > MAX_UDP_RESPONSE {
+            if response.answers.pop().is_none()
+                && response.additional.pop().is_none()
+                && response.authority.pop().is_none()
+            {
+                break;
+            }
+        }
+        response.header.ancount = response.answers.len() as u16;
+        response.header.nscount = response.authority.len() as u16;
+        response.header.arcount = response.additional.len() as u16;
+        warn!(answers = response.answers.len(), "réponse tronquée (TC)");
+    }
+}
+
+#[tokio::main]
+async fn main() -> IoResult<()> {
+    println!("Client et Serveur DNS Simple\n");
+
+    let args: Vec<String> = std::env::args().collect();
+    // --ipv6 : le serveur écoute sur [::] et le client passe par ::1
+    let ipv6 = args.iter().any(|a| a == "--ipv6");
+    // --random-port : un port source différent pour chaque requête
+    let randomize_port = args.iter().any(|a| a == "--random-port");
+    // --system-fallback [--resolv-conf <fichier>] : repli sur les résolveurs du système
+    let system_fallback = args.iter().any(|a| a == "--system-fallback");
+    let resolv_conf = args
+        .iter()
+        .position(|a| a == "--resolv-conf")
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_RESOLV_CONF));
+    // --listen-only : le serveur ne répond que depuis ses enregistrements
+    let listen_only = args.iter().any(|a| a == "--listen-only");
+    // --authoritative : AA sur nos zones, REFUSED pour le reste, sans RA
+    // --all-types : tous les types connus pour le nom, quel que soit le type demandé
+    let all_types = args.iter().any(|a| a == "--all-types");
+    let authoritative = args.iter().any(|a| a == "--authoritative");
+    // --hexdump : octets bruts des requêtes et réponses du serveur
+    let hexdump = args.iter().any(|a| a == "--hexdump");
+    // --delegate <nom>=<ipv4> : délégation vers ce serveur pour les noms hors zone
+    let delegation = args
+        .iter()
+        .position(|a| a == "--delegate")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|spec| {
+            let parsed = spec.split_once('=').and_then(|(nameserver, address)| {
+                Some(Delegation { nameserver: nameserver.to_string(), address: address.parse().ok()? })
+            });
+            if parsed.is_none() {
+                eprintln!("Délégation invalide '{}', attendu <nom>=<ipv4>", spec);
+            }
+            parsed
+        });
+    // --default-ttl <secondes> : TTL des enregistrements sans TTL propre
+    let default_ttl = args
+        .iter()
+        .position(|a| a == "--default-ttl")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|ttl| ttl.parse().ok())
+        .unwrap_or(DEFAULT_TTL);
+    // --query-log <n> : nombre de requêtes gardées en mémoire (0 : aucune)
+    let query_log_size = args
+        .iter()
+        .position(|a| a == "--query-log")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_QUERY_LOG_SIZE);
+    // --port <n> : port d'écoute du serveur (0 : port libre choisi par le système)
+    let port = args
+        .iter()
+        .position(|a| a == "--port")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_PORT);
+    // --log-level <niveau> : error, warn, info (défaut), debug ou trace
+    let log_level = args
+        .iter()
+        .position(|a| a == "--log-level")
+        .and_then(|i| args.get(i + 1))
+        .map(|level| level.parse::<Level>().unwrap_or_else(|_| {
+            eprintln!("Niveau de log inconnu '{}', utilisation de info", level);
+            Level::INFO
+        }))
+        .unwrap_or(Level::INFO);
+    tracing_subscriber::fmt().with_max_level(log_level).with_target(false).init();
+
+    // Démarrer le serveur DNS en arrière-plan
+    let bind_addr = if ipv6 {
+        SocketAddr::from((Ipv6Addr::UNSPECIFIED, port))
+    } else {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    };
+    let mut server = DnsServer::new(bind_addr).await?;
+    // Port réel (utile avec --port 0), joint par le client en local
+    let bound_port = server.local_addr()?.port();
+    let server_addr = if ipv6 {
+        SocketAddr::from((Ipv6Addr::LOCALHOST, bound_port))
+    } else {
+        SocketAddr::from(([127, 0, 0, 1], bound_port))
+    };
+    server.set_listen_only(listen_only);
+    server.set_authoritative(authoritative);
+    server.set_delegation(delegation);
+    server.set_hexdump(hexdump);
+    server.set_all_types(all_types);
+    server.set_default_ttl(default_ttl);
+    server.set_query_log_size(query_log_size);
+    // Trois adresses pour un même nom : l'ordre tourne à chaque requête.
+    // TTL court, comme pour une entrée de zone de répartition de charge
+    for last in 1..=3 {
+        server.add_record_with_ttl("lb.local".to_string(), Ipv4Addr::new(10, 0, 1, last), Some(60));
+    }
+    // Un nom avec plusieurs types, renvoyés ensemble pour une requête ANY
+    server.add_record("multi.local".to_string(), Ipv4Addr::new(10, 0, 2, 1));
+    server.add_other_record("multi.local".to_string(), RecordData::Aaaa("fd00::2:1".parse().unwrap()), None);
+    server.add_other_record("multi.local".to_string(), RecordData::Txt("v=demo".to_string()), None);
+    server.add_other_record(
+        "multi.local".to_string(),
+        RecordData::Mx { preference: 10, exchange: "mail.local".to_string() },
+        None,
+    );
+    // Trop d'adresses pour 512 octets : réponse UDP tronquée, complétée en TCP
+    for last in 1..=40 {
+        server.add_record("big.local".to_string(), Ipv4Addr::new(10, 0, 3, last));
+    }
+    
+    let server = Arc::new(server);
+    let runner = Arc::clone(&server);
+    tokio::spawn(async move {
+        if let Err(e) = runner.run().await {
+            eprintln!("Erreur serveur DNS: {}", e);
+        }
+    });
+    
+    // Attendre un peu que le serveur démarre
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    
+    // Tester le client DNS
+    println!("\nTest du Client DNS");
+    let options = DnsClientOptions { randomize_port, ..DnsClientOptions::default() };
+    let client = DnsClient::with_options(server_addr, options).await?;
+    
+    let test_domains = vec![
+        "example.com",
+        "test.local", 
+        "localhost",
+        "lb.local",
+        "lb.local",
+        "lb.local",
+        "unknown.domain",
+        "unknown.domain" // Servi par le cache négatif, sans requête
+    ];
+    
+    for domain in test_domains {
+        match client.resolve_timed(domain).await? {
+            (Some(ip), elapsed) => println!("{} résolu vers {} ({:?})", domain, ip, elapsed),
+            (None, elapsed) => println!("{} non trouvé ({:?})", domain, elapsed),
+        }
+    }
+    
+    // Résolution groupée : seul big.local, tronqué en UDP, repasse par TCP
+    for (domain, result) in ["big.local", "test.local"].iter().zip(client.resolve_many(&["big.local", "test.local"]).await) {
+        match result {
+            Ok(ips) => println!("{} résolu vers {} adresse(s) (résolution groupée)", domain, ips.len()),
+            Err(e) => println!("{} non résolu: {}", domain, e),
+        }
+    }
+    
+    // Premier serveur muet (rien n'écoute sur ce port) : le second prend le relais
+    let dead_resolver = SocketAddr::from(([127, 0, 0, 1], 5399));
+    match resolve_with_fallback("test.local", &[dead_resolver, server_addr]).await {
+        Some(ip) => println!("test.local résolu vers {} (après repli)", ip),
+        None => println!("test.local non résolu par les serveurs de repli"),
+    }
+    
+    // Même serveur muet, puis repli sur les résolveurs du système
+    if system_fallback {
+        let options = DnsClientOptions { system_fallback, resolv_conf, ..DnsClientOptions::default() };
+        let client = DnsClient::with_options(dead_resolver, options).await?;
+        match client.resolve("test.local").await {
+            Ok(Some(ip)) => println!("test.local résolu vers {} (résolveurs du système)", ip),
+            Ok(None) => println!("test.local inconnu des résolveurs du système"),
+            Err(e) => println!("test.local non résolu: {}", e),
+        }
+    }
+    
+    println!("\nDernières requêtes reçues par le serveur");
+    for entry in server.recent_queries() {
+        let result = match &entry.result {
+            QueryResult::Resolved(ips) => format!("{:?}", ips),
+            QueryResult::NxDomain => "NXDOMAIN".to_string(),
+            QueryResult::NoData => "aucune donnée de ce type".to_string(),
+            QueryResult::Delegated(nameserver) => format!("délégation vers {}", nameserver),
+            QueryResult::Refused => "REFUSED".to_string(),
+            QueryResult::NotImplemented => "NOTIMP".to_string(),
+        };
+        println!("  {} (type {}) depuis {} -> {}", entry.qname, entry.qtype, entry.source, result);
+    }
+    
+    println!("\nTest avec serveur DNS Google (8.8.8.8)");
+    let google_dns = SocketAddr::from(([8, 8, 8, 8], 53));
+    let google_client = DnsClient::new(google_dns).await?;
+    
+    // Sans accès réseau, la requête expire : le serveur local reste en service
+    match google_client.resolve("google.com").await {
+        Ok(Some(ip)) => println!("google.com résolu vers {} (via 8.8.8.8)", ip),
+        Ok(None) => println!("google.com non résolu"),
+        Err(e) => println!("google.com non résolu ({})", e),
+    }
+    
+    println!("\nAppuyez sur Ctrl+C pour arrêter le serveur...");
+    
+    // Garder le programme en vie
+    loop {
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    }
+}
+
+// Module utilitaire pour générer des nombres aléatoires simples
+mod rand {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static COUNTER: AtomicU32 = AtomicU32::new(1);
+
+    pub fn random_u16() -> u16 {
+    let val = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    (val.wrapping_mul(31).wrapping_add(time) & 0xFFFF) as u16
+}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_server() -> DnsServer {
+        DnsServer::new(SocketAddr::from(([127, 0, 0, 1], 0))).await.unwrap()
+    }
+
+    /// Serveur lancé sur un port libre de 127.0.0.1 ; renvoie le serveur
+    /// (journal des requêtes) et son adresse
+    async fn start_server(configure: impl FnOnce(&mut DnsServer)) -> (Arc<DnsServer>, SocketAddr) {
+        let mut server = test_server().await;
+        configure(&mut server);
+        let addr = server.local_addr().unwrap();
+        let server = Arc::new(server);
+        tokio::spawn(Arc::clone(&server).run());
+        (server, addr)
+    }
+
+    /// Faux serveur : répond à chaque requête par les datagrammes que `replies`
+    /// construit, dans l'ordre
+    async fn start_fake_server(replies: fn(&DnsMessage) -> Vec<Vec<u8>>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            while let Ok((len, src)) = socket.recv_from(&mut buf).await {
+                let query = DnsMessage::from_bytes(&buf[..len]).unwrap();
+                for reply in replies(&query) {
+                    socket.send_to(&reply, src).await.unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    /// Réponse de même ID que `query` donnant `ip` pour `qname`
+    fn a_reply(query: &DnsMessage, qname: &str, ip: Ipv4Addr) -> Vec<u8> {
+        let mut response = DnsMessage::new_query(query.header.id, qname);
+        response.header = DnsHeader::new_response(query.header.id, 1, 1);
+        response.answers.push(DnsResourceRecord::new_a_record(qname.to_string(), ip, 60));
+        response.to_bytes()
+    }
+
+    #[tokio::test]
+    async fn resolve_many_retries_truncated_answers_over_tcp() {
+        let (_, addr) = start_server(|server| {
+            for last in 1..=40 {
+                server.add_record("big.test".to_string(), Ipv4Addr::new(10, 0, 3, last));
+            }
+            server.add_record("small.test".to_string(), Ipv4Addr::new(10, 0, 4, 1));
+        })
+        .await;
+
+        // En UDP, la réponse pour big.test ne tient pas dans 512 octets
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.send_to(&DnsMessage::new_query(1, "big.test").to_bytes(), addr).await.unwrap();
+        let mut buf = [0u8; 512];
+        let len = socket.recv(&mut buf).await.unwrap();
+        let truncated = DnsMessage::from_bytes(&buf[..len]).unwrap();
+        assert!(truncated.header.flags.tc);
+        assert!(truncated.answers.len() < 40);
+
+        let client = DnsClient::new(addr).await.unwrap();
+        let results = client.resolve_many(&["big.test", "small.test"]).await;
+        let big = results[0].as_ref().unwrap();
+        assert_eq!(big.len(), 40);
+        assert!((1..=40).all(|last| big.contains(&Ipv4Addr::new(10, 0, 3, last))));
+        assert_eq!(results[1].as_ref().unwrap(), &vec![Ipv4Addr::new(10, 0, 4, 1)]);
+    }
+
+    fn short_timeout() -> DnsClientOptions {
+        DnsClientOptions { timeout: Duration::from_millis(200), attempts: 1, ..DnsClientOptions::default() }
+    }
+
+    #[tokio::test]
+    async fn mismatched_question_is_ignored_until_the_genuine_reply() {
+        let addr = start_fake_server(|query| {
+            let qname = &query.questions[0].qname;
+            vec![a_reply(query, "evil.test", Ipv4Addr::new(6, 6, 6, 6)), a_reply(query, qname, Ipv4Addr::new(10, 1, 1, 1))]
+        })
+        .await;
+        let client = DnsClient::with_options(addr, short_timeout()).await.unwrap();
+        assert_eq!(client.resolve("good.test").await.unwrap(), Some(Ipv4Addr::new(10, 1, 1, 1)));
+    }
+
+    #[tokio::test]
+    async fn mismatched_question_alone_is_rejected() {
+        let addr = start_fake_server(|query| vec![a_reply(query, "evil.test", Ipv4Addr::new(6, 6, 6, 6))]).await;
+        let client = DnsClient::with_options(addr, short_timeout()).await.unwrap();
+        let error = client.resolve("good.test").await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+    }
+
+    fn query(qname: &str, qtype: u16) -> DnsMessage {
+        let mut query = DnsMessage::new_query(1, qname);
+        query.questions[0].qtype = qtype;
+        query
+    }
+
+    #[tokio::test]
+    async fn listen_only_unknown_name_is_nxdomain() {
+        let mut server = test_server().await;
+        server.set_listen_only(true);
+
+        let response = server.handle_query(query("unknown.domain", 1));
+        assert_eq!(response.header.flags.rcode, RCODE_NXDOMAIN);
+        assert!(response.answers.is_empty());
+
+        // Nom connu sans AAAA : pas de NXDOMAIN, seulement aucune réponse
+        let response = server.handle_query(query("test.local", 28));
+        assert_eq!(response.header.flags.rcode, 0);
+        assert!(response.answers.is_empty());
+
+        let response = server.handle_query(query("test.local", 1));
+        assert_eq!(response.header.flags.rcode, 0);
+        assert_eq!(response.answers[0].a_address(), Some(Ipv4Addr::new(192, 168, 1, 100)));
+    }
+
+    #[test]
+    fn soa_record_round_trip() {
+        let soa = SoaRecord {
+            mname: "ns.local".to_string(),
+            rname: "admin.local".to_string(),
+            serial: 2026101601,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 300,
+        };
+        let mut message = DnsMessage::new_query(7, "missing.local");
+        let question_only = message.to_bytes().len();
+        message.authority.push(DnsResourceRecord::new_soa_record("local".to_string(), &soa, 300));
+        message.header.nscount = 1;
+
+        let bytes = message.to_bytes();
+        // Nom propriétaire (2 octets), type/classe/TTL/longueur (10), données :
+        // mname et rname compressés en pointeurs vers "local"
+        assert!(bytes.len() < question_only + 2 + 10 + soa.to_rdata().len());
+
+        let parsed = DnsMessage::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.authority[0].name, "local");
+        assert_eq!(parsed.authority[0].soa(), Some(soa));
+    }
+
+    #[tokio::test]
+    async fn nxdomain_carries_zone_soa() {
+        let server = test_server().await;
+
+        let response = server.handle_query(query("missing.local", 1));
+        assert_eq!(response.header.flags.rcode, RCODE_NXDOMAIN);
+        assert_eq!(response.authority.len(), 1);
+        assert_eq!(response.authority[0].name, "local");
+        assert_eq!(response.authority[0].soa().map(|soa| soa.mname), Some("ns.local".to_string()));
+
+        // Hors de nos zones : NXDOMAIN sans SOA
+        let response = server.handle_query(query("unknown.domain", 1));
+        assert_eq!(response.header.flags.rcode, RCODE_NXDOMAIN);
+        assert!(response.authority.is_empty());
+    }
+
+    fn parent() -> Delegation {
+        Delegation { nameserver: "a.parent.test".to_string(), address: Ipv4Addr::new(198, 51, 100, 1) }
+    }
+
+    #[tokio::test]
+    async fn out_of_zone_query_returns_ns_and_glue() {
+        let mut server = test_server().await;
+        server.set_delegation(Some(parent()));
+
+        let response = server.handle_query(query("www.example.org", 1));
+        assert!(response.answers.is_empty());
+        assert_eq!(response.authority.len(), 1);
+        assert_eq!(response.authority[0].ns_name().as_deref(), Some("a.parent.test"));
+        assert_eq!(response.additional.len(), 1);
+        assert_eq!(response.additional[0].name, "a.parent.test");
+        assert_eq!(response.additional[0].a_address(), Some(Ipv4Addr::new(198, 51, 100, 1)));
+
+        // Sérialisation des sections d'autorité et additionnelle
+        let parsed = DnsMessage::from_bytes(&response.to_bytes()).unwrap();
+        assert_eq!(parsed.authority[0].ns_name().as_deref(), Some("a.parent.test"));
+        assert_eq!(parsed.additional[0].a_address(), Some(Ipv4Addr::new(198, 51, 100, 1)));
+    }
+
+    #[tokio::test]
+    async fn local_records_win_over_delegation() {
+        let mut server = test_server().await;
+        server.set_delegation(Some(parent()));
+        server.add_record("lab.example.org".to_string(), Ipv4Addr::new(10, 9, 9, 9));
+
+        let response = server.handle_query(query("lab.example.org", 1));
+        assert_eq!(response.answers[0].a_address(), Some(Ipv4Addr::new(10, 9, 9, 9)));
+        assert!(response.additional.is_empty());
+    }
+
+    #[tokio::test]
+    async fn listen_only_ignores_delegation() {
+        let mut server = test_server().await;
+        server.set_delegation(Some(parent()));
+        server.set_listen_only(true);
+
+        let response = server.handle_query(query("www.example.org", 1));
+        assert_eq!(response.header.flags.rcode, RCODE_NXDOMAIN);
+        assert!(response.authority.iter().all(|record| record.ns_name().is_none()));
+        assert!(response.additional.is_empty());
+    }
+
+    #[tokio::test]
+    async fn resolves_over_ipv6_loopback() {
+        let server = DnsServer::new(SocketAddr::from((Ipv6Addr::LOCALHOST, 0))).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        assert!(addr.is_ipv6());
+        tokio::spawn(Arc::new(server).run());
+
+        let client = DnsClient::new(addr).await.unwrap();
+        assert_eq!(client.resolve("test.local").await.unwrap(), Some(Ipv4Addr::new(192, 168, 1, 100)));
+    }
+
+    #[tokio::test]
+    async fn randomized_port_changes_the_source_of_each_query() {
+        let (server, addr) = start_server(|_| {}).await;
+        let ports = |server: &DnsServer| server.recent_queries().iter().map(|entry| entry.source.port()).collect::<Vec<_>>();
+
+        let options = DnsClientOptions { randomize_port: true, ..DnsClientOptions::default() };
+        let client = DnsClient::with_options(addr, options).await.unwrap();
+        client.resolve("test.local").await.unwrap();
+        client.resolve("localhost").await.unwrap();
+        let randomized = ports(&server);
+        assert_eq!(randomized.len(), 2);
+        assert_ne!(randomized[0], randomized[1]);
+
+        // Sans l'option, la socket du client sert pour toutes les requêtes
+        let client = DnsClient::new(addr).await.unwrap();
+        client.resolve("test.local").await.unwrap();
+        client.resolve("localhost").await.unwrap();
+        let fixed = ports(&server);
+        assert_eq!(fixed[2], fixed[3]);
+    }
+
+    #[tokio::test]
+    async fn answer_for_another_name_is_not_accepted() {
+        // Bonne question reprise, mais l'adresse est donnée pour un autre nom
+        let addr = start_fake_server(|query| {
+            let mut response = query.clone();
+            response.header = DnsHeader::new_response(query.header.id, 1, 1);
+            response.answers.push(DnsResourceRecord::new_a_record("evil.test".to_string(), Ipv4Addr::new(6, 6, 6, 6), 60));
+            vec![response.to_bytes()]
+        })
+        .await;
+        let client = DnsClient::with_options(addr, short_timeout()).await.unwrap();
+        assert_eq!(client.resolve("good.test").await.unwrap(), None);
+
+        // Un CNAME de la question mène bien à l'adresse ; la casse est ignorée
+        let alias = encode_domain_name("alias.good.test");
+        let cname = DnsResourceRecord {
+            name: "WWW.good.test".to_string(),
+            rtype: 5,
+            rclass: 1,
+            ttl: 60,
+            rdlength: alias.len() as u16,
+            rdata: alias,
+        };
+        let answers = vec![
+            cname,
+            DnsResourceRecord::new_a_record("evil.test".to_string(), Ipv4Addr::new(6, 6, 6, 6), 60),
+            DnsResourceRecord::new_a_record("alias.good.test".to_string(), Ipv4Addr::new(10, 0, 0, 7), 60),
+        ];
+        assert_eq!(extract_a_records(&answers, "www.good.test"), vec![Ipv4Addr::new(10, 0, 0, 7)]);
+    }
+
+    #[tokio::test]
+    async fn unknown_name_is_served_from_the_negative_cache() {
+        let (server, addr) = start_server(|_| {}).await;
+        let client = DnsClient::new(addr).await.unwrap();
+        assert_eq!(client.resolve("unknown.domain").await.unwrap(), None);
+        assert_eq!(client.resolve("UNKNOWN.domain").await.unwrap(), None);
+        assert_eq!(server.recent_queries().len(), 1);
+
+        // Entrée expirée aussitôt : chaque recherche repart sur le réseau
+        let options = DnsClientOptions { negative_ttl: Duration::ZERO, ..DnsClientOptions::default() };
+        let client = DnsClient::with_options(addr, options).await.unwrap();
+        client.resolve("unknown.domain").await.unwrap();
+        client.resolve("unknown.domain").await.unwrap();
+        assert_eq!(server.recent_queries().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn three_addresses_rotate_across_queries() {
+        let mut server = test_server().await;
+        let ips: Vec<Ipv4Addr> = (1..=3).map(|last| Ipv4Addr::new(10, 0, 1, last)).collect();
+        for &ip in &ips {
+            server.add_record("lb.test".to_string(), ip);
+        }
+
+        let mut firsts = Vec::new();
+        for _ in 0..4 {
+            let response = server.handle_query(query("lb.test", 1));
+            assert_eq!(response.header.ancount, 3);
+            let answers: Vec<Ipv4Addr> = response.answers.iter().filter_map(|a| a.a_address()).collect();
+            assert!(ips.iter().all(|ip| answers.contains(ip)));
+            firsts.push(answers[0]);
+        }
+        assert_eq!(firsts, vec![ips[0], ips[1], ips[2], ips[0]]);
+    }
+
+    #[tokio::test]
+    async fn zone_entry_ttl_overrides_the_default() {
+        let mut server = test_server().await;
+        server.set_default_ttl(120);
+        server.add_record_with_ttl("zone.test".to_string(), Ipv4Addr::new(10, 0, 5, 1), Some(42));
+        server.add_record("plain.test".to_string(), Ipv4Addr::new(10, 0, 5, 2));
+
+        let response = server.handle_query(query("zone.test", 1));
+        assert_eq!(response.answers[0].ttl, 42);
+        let parsed = DnsMessage::from_bytes(&response.to_bytes()).unwrap();
+        assert_eq!(parsed.answers[0].ttl, 42);
+
+        // Sans TTL propre : défaut du serveur, et non plus 300
+        let response = server.handle_query(query("plain.test", 1));
+        assert_eq!(response.answers[0].ttl, 120);
+    }
+
+    #[tokio::test]
+    async fn authoritative_mode_sets_aa_and_refuses_other_zones() {
+        let mut server = test_server().await;
+        server.set_authoritative(true);
+        server.add_zone("corp.test".to_string());
+        server.add_record("www.corp.test".to_string(), Ipv4Addr::new(10, 0, 6, 1));
+
+        for qname in ["test.local", "www.corp.test"] {
+            let response = server.handle_query(query(qname, 1));
+            let flags = DnsMessage::from_bytes(&response.to_bytes()).unwrap().header.flags;
+            assert!(flags.aa && !flags.ra, "{}: {:?}", qname, flags);
+            assert_eq!(flags.rcode, 0);
+        }
+
+        let response = server.handle_query(query("unknown.domain", 1));
+        assert_eq!(response.header.flags.rcode, RCODE_REFUSED);
+        assert!(!response.header.flags.aa && !response.header.flags.ra);
+        assert!(response.answers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn query_log_keeps_the_last_queries_in_order() {
+        let (server, addr) = start_server(|server| server.set_query_log_size(3)).await;
+        let client = DnsClient::new(addr).await.unwrap();
+        for name in ["example.com", "test.local", "unknown.domain", "localhost"] {
+            client.resolve(name).await.unwrap();
+        }
+
+        let log = server.recent_queries();
+        let summary: Vec<(&str, u16, &QueryResult)> = log.iter().map(|e| (e.qname.as_str(), e.qtype, &e.result)).collect();
+        assert_eq!(summary, vec![
+            ("test.local", 1, &QueryResult::Resolved(vec![Ipv4Addr::new(192, 168, 1, 100)])),
+            ("unknown.domain", 1, &QueryResult::NxDomain),
+            ("localhost", 1, &QueryResult::Resolved(vec![Ipv4Addr::new(127, 0, 0, 1)])),
+        ]);
+        assert!(log.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+        assert!(log.iter().all(|entry| entry.source.ip() == Ipv4Addr::LOCALHOST));
+    }
+
+    #[tokio::test]
+    async fn update_opcode_gets_notimp() {
+        let (_, addr) = start_server(|_| {}).await;
+        let mut update = query("test.local", 1);
+        update.header.flags.opcode = 5;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.send_to(&update.to_bytes(), addr).await.unwrap();
+        let mut buf = [0u8; 512];
+        let len = socket.recv(&mut buf).await.unwrap();
+        let response = DnsMessage::from_bytes(&buf[..len]).unwrap();
+        assert_eq!(response.header.flags.rcode, RCODE_NOTIMP);
+        assert_eq!(response.header.flags.opcode, 5);
+        assert!(response.header.flags.qr);
+        assert!(response.answers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fallback_skips_a_dead_resolver() {
+        // Socket gardée ouverte mais jamais lue : le premier serveur reste muet
+        let dead = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (_, live) = start_server(|_| {}).await;
+
+        let resolvers = [dead.local_addr().unwrap(), live];
+        let ip = resolve_with_fallback("test.local", &resolvers).await;
+        assert_eq!(ip, Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100))));
+        assert_eq!(resolve_with_fallback("unknown.domain", &[live]).await, None);
+    }
+
+    #[test]
+    fn flags_round_trip_through_u16() {
+        // Requête standard, réponse classique, NXDOMAIN autoritaire, UPDATE tronqué avec Z
+        for raw in [0x0100, 0x8180, 0x8583, 0x2A70, 0xFFFF, 0x0000] {
+            assert_eq!(DnsFlags::from_u16(raw).to_u16(), raw, "{:#06x}", raw);
+        }
+
+        let flags = DnsFlags::from_u16(0x8583);
+        assert_eq!(flags, DnsFlags { qr: true, aa: true, rd: true, ra: true, rcode: RCODE_NXDOMAIN, ..DnsFlags::default() });
+        let flags = DnsFlags { opcode: 5, tc: true, rcode: RCODE_NOTIMP, ..DnsFlags::default() };
+        assert_eq!(flags.to_u16(), 0x2A04);
+        assert_eq!(DnsFlags::from_u16(flags.to_u16()), flags);
+    }
+
+    #[tokio::test]
+    async fn timed_resolution_matches_a_plain_resolve() {
+        let (_, addr) = start_server(|_| {}).await;
+        let client = DnsClient::new(addr).await.unwrap();
+
+        let (ip, elapsed) = client.resolve_timed("example.com").await.unwrap();
+        assert!(elapsed > Duration::ZERO);
+        assert_eq!(ip, client.resolve("example.com").await.unwrap());
+        assert_eq!(ip, Some(Ipv4Addr::new(93, 184, 216, 34)));
+    }
+
+    #[tokio::test]
+    async fn retransmitted_query_is_answered_once() {
+        let (server, addr) = start_server(|server| {
+            server.add_record("lb.test".to_string(), Ipv4Addr::new(10, 0, 7, 1));
+            server.add_record("lb.test".to_string(), Ipv4Addr::new(10, 0, 7, 2));
+        })
+        .await;
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut buf = [0u8; 512];
+        let mut exchange = async |query: &DnsMessage| {
+            socket.send_to(&query.to_bytes(), addr).await.unwrap();
+            let len = socket.recv(&mut buf).await.unwrap();
+            DnsMessage::from_bytes(&buf[..len]).unwrap().answers[0].a_address()
+        };
+
+        let first = exchange(&DnsMessage::new_query(42, "lb.test")).await;
+        let resent = exchange(&DnsMessage::new_query(42, "lb.test")).await;
+        // Même réponse, calculée une seule fois : le round-robin n'a pas avancé
+        assert_eq!(first, resent);
+        assert_eq!(server.recent_queries().len(), 1);
+
+        let next = exchange(&DnsMessage::new_query(43, "lb.test")).await;
+        assert_ne!(first, next);
+        assert_eq!(server.recent_queries().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn three_byte_datagram_is_skipped_until_the_genuine_reply() {
+        // Côté client : le paquet trop court est ignoré, l'attente continue
+        let addr = start_fake_server(|query| {
+            let qname = &query.questions[0].qname;
+            vec![vec![0x12, 0x34, 0x81], a_reply(query, qname, Ipv4Addr::new(10, 2, 2, 2))]
+        })
+        .await;
+        let client = DnsClient::with_options(addr, short_timeout()).await.unwrap();
+        assert_eq!(client.resolve("test.local").await.unwrap(), Some(Ipv4Addr::new(10, 2, 2, 2)));
+
+        // Seul, il ne fait pas échouer la recherche avant l'échéance
+        let addr = start_fake_server(|_| vec![vec![0x12, 0x34, 0x81]]).await;
+        let client = DnsClient::with_options(addr, short_timeout()).await.unwrap();
+        let error = client.resolve("test.local").await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+
+        // Côté serveur : paquet écarté sans réponse, le service continue
+        let (server, addr) = start_server(|_| {}).await;
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.send_to(&[0x12, 0x34, 0x01], addr).await.unwrap();
+        socket.send_to(&DnsMessage::new_query(9, "test.local").to_bytes(), addr).await.unwrap();
+        let mut buf = [0u8; 512];
+        let len = socket.recv(&mut buf).await.unwrap();
+        assert_eq!(DnsMessage::from_bytes(&buf[..len]).unwrap().header.id, 9);
+        assert_eq!(server.recent_queries().len(), 1);
+    }
+
+    #[test]
+    fn only_suffixes_above_the_threshold_are_compressed() {
+        let decode_all = |bytes: &[u8]| {
+            let mut offset = 0;
+            let first = decode_domain_name(bytes, &mut offset).unwrap();
+            (first, decode_domain_name(bytes, &mut offset).unwrap(), offset)
+        };
+
+        // "a" s'encode sur 3 octets : un pointeur de 2 octets ne gagnerait qu'un octet
+        let mut names = NameCompressor::default();
+        let mut bytes = Vec::new();
+        names.write_name(&mut bytes, "a");
+        names.write_name(&mut bytes, "a");
+        assert_eq!(bytes, [1, b'a', 0, 1, b'a', 0]);
+        assert_eq!(decode_all(&bytes), ("a".to_string(), "a".to_string(), 6));
+
+        let mut names = NameCompressor::default();
+        let mut bytes = Vec::new();
+        names.write_name(&mut bytes, "www.example.org");
+        let full = bytes.len();
+        names.write_name(&mut bytes, "mail.example.org");
+        // "mail" en clair, puis un pointeur vers "example.org"
+        assert_eq!(&bytes[full..], [&[4][..], b"mail", &[0xC0, 4]].concat());
+        assert_eq!(
+            decode_all(&bytes),
+            ("www.example.org".to_string(), "mail.example.org".to_string(), bytes.len())
+        );
+    }
+
+    #[tokio::test]
+    async fn port_zero_reports_the_bound_address() {
+        let mut server = test_server().await;
+        server.add_record("eph.test".to_string(), Ipv4Addr::new(10, 0, 8, 1));
+        let addr = server.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+        assert_eq!(addr.ip(), Ipv4Addr::LOCALHOST);
+        tokio::spawn(Arc::new(server).run());
+
+        let client = DnsClient::new(addr).await.unwrap();
+        assert_eq!(client.resolve("eph.test").await.unwrap(), Some(Ipv4Addr::new(10, 0, 8, 1)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn dead_server_falls_back_to_the_system_resolvers() {
+        let (_, live) = start_server(|_| {}).await;
+        let dead = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let resolv_conf = std::env::temp_dir().join(format!("tp7-resolv-{}.conf", std::process::id()));
+        std::fs::write(&resolv_conf, "# généré pour le test\nsearch local\nnameserver 127.0.0.1\n").unwrap();
+
+        let options = DnsClientOptions {
+            system_fallback: true,
+            resolv_conf: resolv_conf.clone(),
+            system_port: live.port(),
+            ..short_timeout()
+        };
+        let client = DnsClient::with_options(dead.local_addr().unwrap(), options).await.unwrap();
+        let ip = client.resolve("test.local").await;
+        let _ = std::fs::remove_file(&resolv_conf);
+        assert_eq!(ip.unwrap(), Some(Ipv4Addr::new(192, 168, 1, 100)));
+
+        // Sans le repli, le serveur muet donne une erreur
+        let client = DnsClient::with_options(dead.local_addr().unwrap(), short_timeout()).await.unwrap();
+        assert_eq!(client.resolve("test.local").await.unwrap_err().kind(), ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn any_query_returns_every_record_type() {
+        let mut server = test_server().await;
+        server.add_record("multi.test".to_string(), Ipv4Addr::new(10, 0, 2, 1));
+        server.add_other_record("multi.test".to_string(), RecordData::Aaaa("fd00::2:1".parse().unwrap()), None);
+        server.add_other_record("multi.test".to_string(), RecordData::Txt("v=demo".to_string()), None);
+
+        let response = server.handle_query(query("multi.test", QTYPE_ANY));
+        let parsed = DnsMessage::from_bytes(&response.to_bytes()).unwrap();
+        assert_eq!(parsed.header.ancount, 3);
+        let mut types: Vec<u16> = parsed.answers.iter().map(|answer| answer.rtype).collect();
+        types.sort();
+        assert_eq!(types, vec![1, 16, 28]);
+
+        // Requête A ordinaire : seulement l'adresse IPv4
+        let response = server.handle_query(query("multi.test", 1));
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.answers[0].a_address(), Some(Ipv4Addr::new(10, 0, 2, 1)));
+    }
+
+    #[test]
+    fn hexdump_lays_out_offset_hex_and_ascii() {
+        let bytes: Vec<u8> = b"\x12\x34DNS test\x00\x01\xff example.com".to_vec();
+        let expected = [
+            "0000  12 34 44 4e 53 20 74 65 73 74 00 01 ff 20 65 78  |.4DNS test... ex|",
+            "0010  61 6d 70 6c 65 2e 63 6f 6d                       |ample.com|",
+        ]
+        .join("\n");
+        assert_eq!(hexdump(&bytes), expected);
+        assert_eq!(hexdump(&[]), "");
+    }
+
+    #[tokio::test]
+    async fn silent_tcp_client_does_not_delay_udp() {
+        let (_, addr) = start_server(|_| {}).await;
+        // Connexion TCP ouverte mais muette : son traitement attend TCP_QUERY_TIMEOUT
+        let _silent = TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client = DnsClient::with_options(addr, short_timeout()).await.unwrap();
+        let started = Instant::now();
+        assert_eq!(client.resolve("test.local").await.unwrap(), Some(Ipv4Addr::new(192, 168, 1, 100)));
+        assert!(started.elapsed() < TCP_QUERY_TIMEOUT / 2, "{:?}", started.elapsed());
+
+        // Une autre connexion TCP est servie en parallèle
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        write_tcp_message(&mut stream, &DnsMessage::new_query(5, "test.local").to_bytes()).await.unwrap();
+        let response = DnsMessage::from_bytes(&read_tcp_message(&mut stream).await.unwrap()).unwrap();
+        assert_eq!(response.answers[0].a_address(), Some(Ipv4Addr::new(192, 168, 1, 100)));
+    }
+
+    #[tokio::test]
+    async fn udp_and_tcp_share_the_port_chosen_by_the_system() {
+        for _ in 0..10 {
+            let server = test_server().await;
+            let udp = server.socket.local_addr().unwrap();
+            assert_ne!(udp.port(), 0);
+            assert_eq!(server.tcp_listener.local_addr().unwrap(), udp);
+        }
+    }
+
+    #[test]
+    fn oversized_authority_and_additional_are_dropped_to_fit_in_udp() {
+        // Pas de réponse, mais une délégation bien trop longue pour 512 octets
+        let mut response = query("deleg.test", 1);
+        response.header.flags.qr = true;
+        for i in 0..40 {
+            let ns = format!("serveur-de-noms-numero-{}.fournisseur-{}.example", i, i);
+            response.authority.push(DnsResourceRecord::new_record("deleg.test".to_string(), &RecordData::Ns(ns.clone()), 300));
+            response.additional.push(DnsResourceRecord::new_a_record(ns, Ipv4Addr::new(10, 0, 0, i), 300));
+        }
+        assert!(response.to_bytes().len() > MAX_UDP_RESPONSE);
+
+        truncate_for_udp(&mut response);
+
+        let bytes = response.to_bytes();
+        assert!(bytes.len() <= MAX_UDP_RESPONSE);
+        let decoded = DnsMessage::from_bytes(&bytes).unwrap();
+        assert!(decoded.header.flags.tc);
+        assert_eq!(decoded.questions[0].qname, "deleg.test");
+        assert_eq!(decoded.authority.len(), response.authority.len());
+        assert_eq!(decoded.additional.len(), response.additional.len());
+    }
+
+    /// Sortie des logs capturée pour les tests
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CapturedLogs {
+        fn text(&self) -> String {
+            String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+        }
+    }
+
+    #[tokio::test]
+    async fn each_query_is_traced_in_a_span_with_its_source_and_id() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(Level::DEBUG)
+            .with_target(false)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (_, addr) = start_server(|server| server.set_delegation(Some(parent()))).await;
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let src = socket.local_addr().unwrap();
+        let mut buf = [0u8; 512];
+        // Résolue, retransmise (réponse en cache), puis renvoyée au parent
+        for (id, qname) in [(7, "test.local"), (7, "test.local"), (8, "www.example.org")] {
+            socket.send_to(&DnsMessage::new_query(id, qname).to_bytes(), addr).await.unwrap();
+            socket.recv(&mut buf).await.unwrap();
+        }
+        // En-tête complet mais question illisible : ignorée sans réponse
+        socket.send_to(&[0, 9, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 63], addr).await.unwrap();
+        socket.send_to(&DnsMessage::new_query(10, "test.local").to_bytes(), addr).await.unwrap();
+        socket.recv(&mut buf).await.unwrap();
+
+        let text = logs.text();
+        let line = |needle: &str| text.lines().find(|l| l.contains(needle)).unwrap_or_else(|| panic!("{}\n{}", needle, text)).to_string();
+        assert!(line("résolu").contains(&format!("query{{src={} id=7}}", src)));
+        assert!(line("retransmission").contains("id=7"));
+        assert!(line("délégation").contains(&format!("query{{src={} id=8}}", src)));
+        assert!(line("délégation").contains("a.parent.test"));
+        assert!(line("requête illisible").contains(&src.to_string()));
+    }
 }
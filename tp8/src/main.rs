@@ -1,32 +1,84 @@
 
-use std::collections::HashMap;
-use std::io::{self, BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use serde::{Deserialize, Serialize};
-use bincode;
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum MessageType {
     // Messages client -> serveur
-    Connect { username: String },
+    // `token` : secret partagé, exigé seulement si le serveur en a un.
+    // `codec` : format demandé pour la suite de la connexion (None : garder celui du Connect).
+    // `password` : vérifié seulement si le serveur tient un fichier de comptes (`--users`)
+    Connect {
+        username: String,
+        #[serde(default)]
+        password: String,
+        token: Option<String>,
+        codec: Option<Codec>,
+    },
     JoinRoom { room: String },
-    SendMessage { room: String, content: String },
-    ListRooms,
+    // `seq` strictement croissant par utilisateur : un renvoi (même seq) n'est pas rediffusé.
+    // `reply_to` : id d'un message encore dans l'historique du salon (fil de discussion)
+    SendMessage { room: String, content: String, seq: u64, reply_to: Option<String> },
+    // `only_active` : seulement les salons où au moins un utilisateur est présent
+    ListRooms { only_active: bool },
     ListUsers { room: String },
+    GetHistory { room: String, limit: u32 },
+    // Message `root_id` du salon courant et toutes ses réponses, directes ou non
+    GetThread { root_id: String },
+    // Message direct à un utilisateur connecté, jamais diffusé au salon
+    PrivateMessage { to: String, content: String },
+    Echo { payload: String },
+    // Abonnement au canal de présence : connexions/déconnexions de tout le serveur
+    Subscribe,
+    // Annonce à tous les connectés, réservée à l'administrateur du serveur
+    Announce { content: String },
+    // Diagnostic : version, uptime et occupation du serveur, sans authentification
+    ServerInfo,
     Disconnect,
     
     // Messages serveur -> client
-    ConnectAck { success: bool, message: String },
+    // `codec` : format retenu par le serveur, utilisé après cet accusé
+    ConnectAck { success: bool, message: String, codec: Codec },
     JoinRoomAck { success: bool, room: String, message: String },
-    MessageBroadcast { room: String, username: String, content: String, timestamp: u64 },
+    MessageBroadcast { id: String, room: String, username: String, content: String, timestamp: u64, reply_to: Option<String> },
     RoomList { rooms: Vec<String> },
     UserList { room: String, users: Vec<String> },
     Error { message: String },
     UserJoined { room: String, username: String },
     UserLeft { room: String, username: String },
+    History { room: String, messages: Vec<HistoryEntry> },
+    Thread { room: String, root_id: String, messages: Vec<HistoryEntry> },
+    PrivateMessageDelivered { from: String, content: String, timestamp: u64 },
+    EchoReply { payload: String },
+    MessageAck { seq: u64 },
+    UserOnline { username: String },
+    UserOffline { username: String },
+    System { content: String },
+    ServerInfoReply { version: String, uptime_secs: u64, users: u32, rooms: u32 },
+}
+
+/// Préfixe des messages `System` issus d'une annonce de l'administrateur
+pub const ANNOUNCE_MARKER: &str = "[ANNOUNCE]";
+
+/// Nombre de messages conservés par salon
+pub const HISTORY_SIZE: usize = 50;
+
+/// Message conservé dans l'historique d'un salon
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoryEntry {
+    pub id: String, // Attribué par le serveur, unique pour toute sa durée de vie
+    pub reply_to: Option<String>,
+    pub username: String,
+    pub content: String,
+    pub timestamp: u64,
 }
 
 /// Structure principale d'un message du protocole
@@ -49,31 +101,140 @@ impl ProtocolMessage {
         }
     }
     
-    /// Sérialise le message en bytes
-    pub fn serialize(&self) -> Result<Vec<u8>, bincode::Error> {
-        let data = bincode::serialize(self)?;
-        let len = data.len() as u32;
-        let mut result = len.to_be_bytes().to_vec();
-        result.extend(data);
-        Ok(result)
+    /// Sérialise le message en une trame du codec choisi
+    pub fn serialize(&self, codec: Codec) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match codec {
+            Codec::Bincode => {
+                let data = bincode::serialize(self)?;
+                let len = data.len() as u32;
+                let mut result = len.to_be_bytes().to_vec();
+                result.extend(data);
+                Ok(result)
+            }
+            Codec::Json => {
+                let mut result = serde_json::to_vec(self)?;
+                result.push(b'\n');
+                Ok(result)
+            }
+            Codec::MessagePack => {
+                let data = rmp_serde::to_vec_named(self)?;
+                let len = data.len() as u32;
+                let mut result = len.to_be_bytes().to_vec();
+                result.extend(data);
+                Ok(result)
+            }
+        }
     }
     
     /// Désérialise un message depuis un stream
-    pub fn deserialize_from_stream(stream: &mut TcpStream) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut reader = BufReader::new(stream);
-        
-        // Lire la taille du message (4 bytes)
-        let mut len_bytes = [0u8; 4];
-        std::io::Read::read_exact(&mut reader, &mut len_bytes)?;
-        let len = u32::from_be_bytes(len_bytes) as usize;
-        
-        // Lire le message
-        let mut buffer = vec![0u8; len];
-        std::io::Read::read_exact(&mut reader, &mut buffer)?;
-        
-        let message: ProtocolMessage = bincode::deserialize(&buffer)?;
-        Ok(message)
+    pub fn deserialize_from_stream(stream: &mut TcpStream, codec: Codec) -> Result<Self, Box<dyn std::error::Error>> {
+        read_frame(stream, &mut Vec::new(), codec)
+    }
+}
+
+/// Format des trames d'une connexion : bincode ou MessagePack préfixés par
+/// leur longueur, ou JSON à raison d'un message par ligne (scripts, ponts navigateur)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Codec {
+    #[default]
+    Bincode,
+    Json,
+    MessagePack,
+}
+
+impl Codec {
+    /// Devine le codec d'après le premier octet reçu : `{` ouvre un objet JSON,
+    /// alors qu'une longueur bincode commençant par 0x7B dépasserait MAX_FRAME_SIZE
+    pub fn detect(first_byte: u8) -> Self {
+        if first_byte == b'{' { Codec::Json } else { Codec::Bincode }
+    }
+    
+    /// Codec du `Connect` et de son accusé : MessagePack n'est pas reconnaissable
+    /// au premier octet, il est négocié après une poignée de main en bincode
+    pub fn handshake(self) -> Self {
+        if self == Codec::Json { Codec::Json } else { Codec::Bincode }
+    }
+}
+
+/// Connexion d'un client côté serveur, avec le codec qu'il a choisi
+pub struct Connection {
+    pub stream: TcpStream,
+    pub codec: Codec,
+}
+
+impl Connection {
+    pub fn try_clone(&self) -> io::Result<Connection> {
+        Ok(Connection { stream: self.stream.try_clone()?, codec: self.codec })
+    }
+}
+
+/// Taille maximale d'une trame : une longueur annoncée plus grande est refusée
+/// avant toute allocation
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// Taille maximale d'un message sérialisé : une trame en porte exactement un
+pub const MAX_MESSAGE_SIZE: usize = MAX_FRAME_SIZE;
+
+/// Lit une trame `[longueur u32 big-endian][ProtocolMessage bincode ou MessagePack]`,
+/// ou une ligne JSON, depuis n'importe quel lecteur. `buffer` est réutilisé d'un appel
+/// à l'autre pour éviter une allocation par message.
+pub fn read_frame<R: Read>(reader: &mut R, buffer: &mut Vec<u8>, codec: Codec) -> Result<ProtocolMessage, Box<dyn std::error::Error>> {
+    if codec == Codec::Json {
+        return read_json_line(reader, buffer);
     }
+    
+    // Lire la taille du message (4 bytes)
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes)?;
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(format!("trame trop grande ({} octets, max {})", len, MAX_MESSAGE_SIZE).into());
+    }
+    
+    // Lire le message
+    buffer.resize(len, 0);
+    reader.read_exact(buffer)?;
+    
+    if codec == Codec::MessagePack {
+        return Ok(rmp_serde::from_slice(buffer)?);
+    }
+    Ok(bincode::deserialize(buffer)?)
+}
+
+/// Lit une ligne JSON octet par octet : le lecteur n'est pas tamponné, rien
+/// au-delà du `\n` ne doit être consommé
+fn read_json_line<R: Read>(reader: &mut R, buffer: &mut Vec<u8>) -> Result<ProtocolMessage, Box<dyn std::error::Error>> {
+    buffer.clear();
+    let mut byte = [0u8; 1];
+    loop {
+        reader.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        if buffer.len() == MAX_FRAME_SIZE {
+            return Err(format!("ligne JSON trop longue (max {} octets)", MAX_FRAME_SIZE).into());
+        }
+        buffer.push(byte[0]);
+    }
+    
+    Ok(serde_json::from_slice(buffer)?)
+}
+
+/// Action de modération consignée dans le journal d'audit
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Join,
+    Leave,
+}
+
+/// Ligne JSON du journal d'audit (distinct de l'historique des messages)
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub timestamp: u64,
+    pub action: AuditAction,
+    pub username: String,
+    pub room: String,
 }
 
 #[derive(Debug, Clone)]
@@ -82,38 +243,393 @@ pub struct User {
     pub current_room: Option<String>,
 }
 
+/// Nombre maximal de connexions simultanées par défaut
+pub const DEFAULT_MAX_CONNECTIONS: usize = 100;
+/// Délai d'écriture par défaut avant de considérer un client comme bloqué
+pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Nombre maximal de salons ouverts simultanément par défaut
+pub const DEFAULT_MAX_ROOMS: usize = 50;
+/// Nombre maximal de salons par utilisateur par défaut
+pub const DEFAULT_MAX_ROOMS_PER_USER: usize = 5;
+/// Dossier des historiques archivés (`--archive`)
+pub const DEFAULT_ARCHIVE_DIR: &str = "archives";
+
+/// Archivage des salons inactifs : dossier de destination et durée d'inactivité
+#[derive(Debug, Clone)]
+pub struct ArchiveConfig {
+    pub dir: String,
+    pub idle: Duration,
+}
+
+/// Comptes utilisateurs (`--users`) : une ligne `nom:empreinte` par compte, où
+/// l'empreinte est le SHA-256 hexadécimal de `nom:mot de passe`. Un nom inconnu
+/// est enregistré à sa première connexion ; si le fichier n'existe pas, la base
+/// démarre vide et le fichier est créé au premier enregistrement.
+#[derive(Debug)]
+pub struct Accounts {
+    path: String,
+    hashes: HashMap<String, String>,
+}
+
+impl Accounts {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let hashes = content
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(username, hash)| (username.to_string(), hash.trim().to_string()))
+            .collect();
+        Ok(Self { path: path.to_string(), hashes })
+    }
+    
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+    
+    /// Vérifie le mot de passe d'un compte existant, ou enregistre le nouveau
+    /// compte ; `Err` porte le motif du refus, à renvoyer au client
+    pub fn authenticate(&mut self, username: &str, password: &str) -> Result<(), String> {
+        if let Some(hash) = self.hashes.get(username) {
+            return if *hash == password_hash(username, password) {
+                Ok(())
+            } else {
+                Err("Mot de passe incorrect".to_string())
+            };
+        }
+        
+        if password.is_empty() {
+            return Err("Mot de passe requis pour créer le compte".to_string());
+        }
+        if username.is_empty() || username.contains([':', '\n', '\r']) {
+            return Err("Nom d'utilisateur invalide".to_string());
+        }
+        let hash = password_hash(username, password);
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)
+            .map_err(|e| format!("Enregistrement du compte impossible: {}", e))?;
+        writeln!(file, "{}:{}", username, hash).map_err(|e| format!("Enregistrement du compte impossible: {}", e))?;
+        self.hashes.insert(username.to_string(), hash);
+        println!("Compte {} créé", username);
+        Ok(())
+    }
+}
+
+/// Empreinte stockée dans le fichier de comptes ; le nom sert de sel
+fn password_hash(username: &str, password: &str) -> String {
+    Sha256::digest(format!("{}:{}", username, password).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// État des salons sauvegardé à l'arrêt (`--snapshot`) : les connexions ne
+/// survivent pas au redémarrage, les salons et leur historique si
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub rooms: HashMap<String, Vec<HistoryEntry>>, // Salon -> derniers messages
+    pub next_message_id: u64,
+}
+
+/// Transformation appliquée au contenu d'un message avant sa diffusion
+pub trait MessageTransformer: Send + Sync {
+    fn transform(&self, content: &str) -> String;
+}
+
+/// Retire les espaces en début et fin de message
+pub struct Trim;
+
+impl MessageTransformer for Trim {
+    fn transform(&self, content: &str) -> String {
+        content.trim().to_string()
+    }
+}
+
+/// Mots masqués par défaut par `ProfanityFilter`
+pub const DEFAULT_PROFANITY: &[&str] = &["merde", "putain", "connard", "salaud"];
+
+/// Remplace les mots interdits (sans tenir compte de la casse) par des astérisques
+pub struct ProfanityFilter {
+    words: HashSet<String>,
+}
+
+impl ProfanityFilter {
+    pub fn new(words: &[&str]) -> Self {
+        Self { words: words.iter().map(|w| w.to_lowercase()).collect() }
+    }
+    
+    fn push_word(&self, out: &mut String, word: &str) {
+        if self.words.contains(&word.to_lowercase()) {
+            out.extend(std::iter::repeat_n('*', word.chars().count()));
+        } else {
+            out.push_str(word);
+        }
+    }
+}
+
+impl MessageTransformer for ProfanityFilter {
+    fn transform(&self, content: &str) -> String {
+        let mut out = String::with_capacity(content.len());
+        let mut word = String::new();
+        for c in content.chars() {
+            if c.is_alphanumeric() {
+                word.push(c);
+            } else {
+                self.push_word(&mut out, &word);
+                word.clear();
+                out.push(c);
+            }
+        }
+        self.push_word(&mut out, &word);
+        out
+    }
+}
+
+/// Remplace les émoticônes et codes courts (`:)`, `:heart:`...) par leur emoji
+pub struct EmojiExpander;
+
+const EMOJIS: &[(&str, &str)] = &[
+    (":heart:", "❤️"),
+    (":fire:", "🔥"),
+    (":+1:", "👍"),
+    (":)", "🙂"),
+    (":(", "🙁"),
+    (":D", "😄"),
+    (";)", "😉"),
+];
+
+impl MessageTransformer for EmojiExpander {
+    fn transform(&self, content: &str) -> String {
+        EMOJIS
+            .iter()
+            .fold(content.to_string(), |acc, (code, emoji)| acc.replace(code, emoji))
+    }
+}
+
+/// Tronque le message à `max_chars` caractères
+pub struct Truncate {
+    pub max_chars: usize,
+}
+
+impl MessageTransformer for Truncate {
+    fn transform(&self, content: &str) -> String {
+        content.chars().take(self.max_chars).collect()
+    }
+}
+
+/// Construit la chaîne de transformations depuis une liste séparée par des virgules,
+/// appliquée dans l'ordre : `trim`, `profanity`, `emoji`, `max=<caractères>`
+pub fn parse_transformers(spec: &str) -> Result<Vec<Box<dyn MessageTransformer>>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| -> Result<Box<dyn MessageTransformer>, String> {
+            match name {
+                "trim" => Ok(Box::new(Trim)),
+                "profanity" => Ok(Box::new(ProfanityFilter::new(DEFAULT_PROFANITY))),
+                "emoji" => Ok(Box::new(EmojiExpander)),
+                _ => match name.strip_prefix("max=").map(str::parse) {
+                    Some(Ok(max_chars)) => Ok(Box::new(Truncate { max_chars })),
+                    _ => Err(format!("transformation inconnue: {}", name)),
+                },
+            }
+        })
+        .collect()
+}
+
+// Clone partage l'état : tous les champs mutables sont des Arc
+#[derive(Clone)]
 pub struct ChatServer {
     users: Arc<Mutex<HashMap<String, User>>>,
     rooms: Arc<Mutex<HashMap<String, Vec<String>>>>, // room -> list of usernames
-    connections: Arc<Mutex<HashMap<String, TcpStream>>>,
+    connections: Arc<Mutex<HashMap<String, Connection>>>,
+    history: Arc<Mutex<HashMap<String, VecDeque<HistoryEntry>>>>, // room -> derniers messages
+    last_seq: Arc<Mutex<HashMap<String, u64>>>, // username -> dernier seq accepté
+    next_message_id: Arc<AtomicU64>,
+    presence_subscribers: Arc<Mutex<HashSet<String>>>,
+    last_activity: Arc<Mutex<HashMap<String, Instant>>>, // room -> dernier message
+    archive: Option<ArchiveConfig>,
+    token: Option<String>, // Secret exigé dans Connect (None : pas d'authentification)
+    accounts: Option<Arc<Mutex<Accounts>>>, // Mots de passe vérifiés (None : noms libres)
+    transformers: Arc<Vec<Box<dyn MessageTransformer>>>, // Appliquées dans l'ordre à chaque message
+    active_connections: Arc<AtomicUsize>,
+    max_connections: usize,
+    max_rooms: usize,
+    max_rooms_per_user: usize,
+    audit_path: Option<String>,
+    write_timeout: Duration,
+    admin: Option<String>,
+    started_at: Instant,
+    audit_lock: Arc<Mutex<()>>, // Une seule écriture d'audit à la fois
 }
 
 impl ChatServer {
-    pub fn new() -> Self {
-        Self {
+    /// `max_connections` borne le nombre de clients (et donc de threads) simultanés,
+    /// `max_rooms` le nombre de salons ouverts et `max_rooms_per_user` ceux d'un même utilisateur.
+    /// Avec `audit_path`, entrées et sorties de salon sont ajoutées à ce fichier (JSON Lines).
+    /// Un client qui n'absorbe pas une écriture en `write_timeout` est déconnecté.
+    /// Seul l'utilisateur `admin` peut envoyer des annonces (`Announce`).
+    /// Avec `snapshot`, les salons de cette sauvegarde sont restaurés si le fichier
+    /// existe (voir `load_snapshot`) ; un fichier illisible est une erreur.
+    pub fn new(
+        max_connections: usize,
+        max_rooms: usize,
+        max_rooms_per_user: usize,
+        audit_path: Option<String>,
+        write_timeout: Duration,
+        admin: Option<String>,
+        snapshot: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let server = Self {
             users: Arc::new(Mutex::new(HashMap::new())),
             rooms: Arc::new(Mutex::new(HashMap::new())),
             connections: Arc::new(Mutex::new(HashMap::new())),
+            history: Arc::new(Mutex::new(HashMap::new())),
+            last_seq: Arc::new(Mutex::new(HashMap::new())),
+            next_message_id: Arc::new(AtomicU64::new(1)),
+            presence_subscribers: Arc::new(Mutex::new(HashSet::new())),
+            last_activity: Arc::new(Mutex::new(HashMap::new())),
+            archive: None,
+            token: None,
+            accounts: None,
+            transformers: Arc::new(Vec::new()),
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            max_connections,
+            max_rooms,
+            max_rooms_per_user,
+            audit_path,
+            write_timeout,
+            admin,
+            started_at: Instant::now(),
+            audit_lock: Arc::new(Mutex::new(())),
+        };
+        if let Some(path) = snapshot.filter(|path| std::path::Path::new(path).exists()) {
+            let count = server.load_snapshot(path)?;
+            println!("{} salon(s) restauré(s) depuis {}", count, path);
+        }
+        Ok(server)
+    }
+    
+    /// Archive l'historique des salons vides restés sans message pendant
+    /// `config.idle`, puis le retire de la mémoire (à appeler avant `serve`)
+    pub fn set_archive(&mut self, config: Option<ArchiveConfig>) {
+        self.archive = config;
+    }
+    
+    /// Exige ce secret dans chaque `Connect` ; sans secret, le jeton est ignoré
+    pub fn set_token(&mut self, token: Option<String>) {
+        self.token = token;
+    }
+    
+    /// Exige un mot de passe dans chaque `Connect`, vérifié contre les comptes de
+    /// `path` (voir `Accounts`) ; retourne le nombre de comptes chargés
+    pub fn set_accounts(&mut self, path: &str) -> io::Result<usize> {
+        let accounts = Accounts::load(path)?;
+        let count = accounts.len();
+        self.accounts = Some(Arc::new(Mutex::new(accounts)));
+        Ok(count)
+    }
+    
+    /// Transformations appliquées dans l'ordre au contenu des messages avant diffusion
+    pub fn set_transformers(&mut self, transformers: Vec<Box<dyn MessageTransformer>>) {
+        self.transformers = Arc::new(transformers);
+    }
+    
+    /// Écrit les salons (occupés ou avec un historique) et leur historique dans
+    /// `path`, en JSON ; le fichier est remplacé d'un coup
+    pub fn save_snapshot(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut rooms: HashMap<String, Vec<HistoryEntry>> = self
+            .rooms
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|room| (room.clone(), Vec::new()))
+            .collect();
+        for (room, messages) in self.history.lock().unwrap().iter() {
+            rooms.insert(room.clone(), messages.iter().cloned().collect());
+        }
+        let snapshot = Snapshot { rooms, next_message_id: self.next_message_id.load(Ordering::SeqCst) };
+        
+        let tmp_path = format!("{}.tmp", path);
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(&snapshot)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+    
+    /// Restaure les salons d'une sauvegarde `save_snapshot` : ils réapparaissent
+    /// vides d'utilisateurs, avec au plus `HISTORY_SIZE` messages. Leur dernière
+    /// activité est celle de leur message le plus récent, si bien qu'un salon déjà
+    /// inactif depuis `ArchiveConfig::idle` est archivé dès le lancement de `serve`
+    fn load_snapshot(&self, path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let snapshot: Snapshot = serde_json::from_slice(&std::fs::read(path)?)?;
+        let count = snapshot.rooms.len();
+        
+        let now_secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let mut history = self.history.lock().unwrap();
+        let mut last_activity = self.last_activity.lock().unwrap();
+        for (room, messages) in snapshot.rooms {
+            let skip = messages.len().saturating_sub(HISTORY_SIZE);
+            let messages: VecDeque<HistoryEntry> = messages.into_iter().skip(skip).collect();
+            let age = messages.back().map_or(0, |entry| now_secs.saturating_sub(entry.timestamp));
+            let last = Instant::now().checked_sub(Duration::from_secs(age)).unwrap_or_else(Instant::now);
+            history.insert(room.clone(), messages);
+            last_activity.insert(room, last);
         }
+        self.next_message_id.fetch_max(snapshot.next_message_id, Ordering::SeqCst);
+        Ok(count)
     }
     
     pub fn start(&self, addr: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (listener, local_addr) = self.bind(addr)?;
+        println!("Serveur SimpleChat démarré sur {}", local_addr);
+        self.serve(listener)
+    }
+    
+    /// Ouvre l'écoute et retourne l'adresse effective (port réel si `addr` demande
+    /// le port 0). `[::]:<port>` écoute en double pile IPv4/IPv6 sur les systèmes
+    /// où IPV6_V6ONLY est désactivé par défaut (Linux, macOS).
+    pub fn bind(&self, addr: &str) -> io::Result<(TcpListener, SocketAddr)> {
         let listener = TcpListener::bind(addr)?;
-        println!("Serveur SimpleChat démarré sur {}", addr);
+        let local_addr = listener.local_addr()?;
+        Ok((listener, local_addr))
+    }
+    
+    /// Boucle d'acceptation sur un listener obtenu par `bind`
+    pub fn serve(&self, listener: TcpListener) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(config) = self.archive.clone() {
+            let server = self.clone();
+            thread::spawn(move || server.archive_loop(config));
+        }
         
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
-                    let server_clone = ChatServer {
-                        users: Arc::clone(&self.users),
-                        rooms: Arc::clone(&self.rooms),
-                        connections: Arc::clone(&self.connections),
-                    };
+                    // Serveur plein : refuser avant de créer un thread (le codec
+                    // du client n'est pas encore connu, le refus part en bincode)
+                    if self.active_connections.fetch_add(1, Ordering::SeqCst) >= self.max_connections {
+                        self.active_connections.fetch_sub(1, Ordering::SeqCst);
+                        let error = ProtocolMessage::new(
+                            MessageType::Error { message: "Serveur plein".to_string() }
+                        );
+                        let mut conn = Connection { stream, codec: Codec::Bincode };
+                        let _ = self.send_message(&mut conn, &error);
+                        eprintln!("Connexion refusée (serveur plein)");
+                        continue;
+                    }
+                    
+                    let server_clone = self.clone();
                     
                     thread::spawn(move || {
                         if let Err(e) = server_clone.handle_client(stream) {
                             eprintln!("Erreur client: {}", e);
                         }
+                        server_clone.active_connections.fetch_sub(1, Ordering::SeqCst);
                     });
                 }
                 Err(e) => eprintln!("Erreur connexion: {}", e),
@@ -123,34 +639,57 @@ impl ChatServer {
         Ok(())
     }
     
-    fn handle_client(&self, mut stream: TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+    fn handle_client(&self, stream: TcpStream) -> Result<(), Box<dyn std::error::Error>> {
         let peer_addr = stream.peer_addr()?;
         println!("Nouvelle connexion: {}", peer_addr);
+        // Partagé par les clones du stream : borne aussi les diffusions vers ce client
+        stream.set_write_timeout(Some(self.write_timeout))?;
+        
+        // Le premier octet, laissé dans le tampon, désigne le codec du client
+        let mut first_byte = [0u8; 1];
+        if stream.peek(&mut first_byte)? == 0 {
+            return Ok(());
+        }
+        let codec = Codec::detect(first_byte[0]);
+        if codec == Codec::Json {
+            println!("{} utilise le protocole JSON", peer_addr);
+        }
         
+        // Lecture tamponnée : ce thread est le seul à lire ce stream
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut conn = Connection { stream, codec };
         let mut current_user: Option<String> = None;
+        let mut buffer = Vec::new();
         
+        // Une erreur de lecture met fin à la connexion : déconnexion du client,
+        // ou trame refusée (taille annoncée hors limite, contenu illisible).
+        // Le codec peut changer après le Connect (négociation)
         loop {
-            match ProtocolMessage::deserialize_from_stream(&mut stream) {
-                Ok(message) => {
-                    match self.process_message(message, &mut current_user, &mut stream) {
-                        Ok(should_continue) => {
-                            if !should_continue {
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Erreur traitement message: {}", e);
-                            let error_msg = ProtocolMessage::new(
-                                MessageType::Error { message: e.to_string() }
-                            );
-                            let _ = self.send_message(&mut stream, &error_msg);
-                        }
+            let message = match read_frame(&mut reader, &mut buffer, conn.codec) {
+                Ok(message) => message,
+                Err(e) => {
+                    let disconnected = e.downcast_ref::<io::Error>().is_some_and(|e| {
+                        matches!(e.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset)
+                    });
+                    if !disconnected {
+                        eprintln!("Trame rejetée de {}, connexion fermée: {}", peer_addr, e);
                     }
-                }
-                Err(_) => {
-                    // Client déconnecté
                     break;
                 }
+            };
+            match self.process_message(message, &mut current_user, &mut conn) {
+                Ok(should_continue) => {
+                    if !should_continue {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Erreur traitement message: {}", e);
+                    let error_msg = ProtocolMessage::new(
+                        MessageType::Error { message: e.to_string() }
+                    );
+                    let _ = self.send_message(&mut conn, &error_msg);
+                }
             }
         }
         
@@ -167,41 +706,112 @@ impl ChatServer {
         &self,
         message: ProtocolMessage,
         current_user: &mut Option<String>,
-        stream: &mut TcpStream,
+        conn: &mut Connection,
     ) -> Result<bool, Box<dyn std::error::Error>> {
         match message.message_type {
-            MessageType::Connect { username } => {
-                self.handle_connect(username, current_user, stream)?;
+            MessageType::Connect { username, password, token, codec } => {
+                self.handle_connect(username, password, token, codec, current_user, conn)?;
             }
             
             MessageType::JoinRoom { room } => {
-                if let Some(ref user) = current_user {
-                    self.handle_join_room(user.clone(), room, stream)?;
+                if let Some(user) = current_user {
+                    self.handle_join_room(user.clone(), room, conn)?;
                 } else {
                     let error = ProtocolMessage::new(
                         MessageType::Error { message: "Non connecté".to_string() }
                     );
-                    self.send_message(stream, &error)?;
+                    self.send_message(conn, &error)?;
                 }
             }
             
-            MessageType::SendMessage { room, content } => {
-                if let Some(ref user) = current_user {
-                    self.handle_send_message(user.clone(), room, content)?;
+            MessageType::SendMessage { room, content, seq, reply_to } => {
+                if let Some(user) = current_user {
+                    self.handle_send_message(user.clone(), room, content, seq, reply_to, conn)?;
                 } else {
                     let error = ProtocolMessage::new(
                         MessageType::Error { message: "Non connecté".to_string() }
                     );
-                    self.send_message(stream, &error)?;
+                    self.send_message(conn, &error)?;
                 }
             }
             
-            MessageType::ListRooms => {
-                self.handle_list_rooms(stream)?;
+            MessageType::ListRooms { only_active } => {
+                self.handle_list_rooms(only_active, conn)?;
             }
             
             MessageType::ListUsers { room } => {
-                self.handle_list_users(room, stream)?;
+                self.handle_list_users(room, conn)?;
+            }
+            
+            MessageType::GetHistory { room, limit } => {
+                if current_user.is_some() {
+                    self.handle_get_history(room, limit, conn)?;
+                } else {
+                    let error = ProtocolMessage::new(
+                        MessageType::Error { message: "Non connecté".to_string() }
+                    );
+                    self.send_message(conn, &error)?;
+                }
+            }
+            
+            MessageType::GetThread { root_id } => {
+                if let Some(user) = current_user {
+                    self.handle_get_thread(user.clone(), root_id, conn)?;
+                } else {
+                    let error = ProtocolMessage::new(
+                        MessageType::Error { message: "Non connecté".to_string() }
+                    );
+                    self.send_message(conn, &error)?;
+                }
+            }
+            
+            // Diagnostic : renvoyé tel quel, sans authentification ni salon
+            MessageType::Echo { payload } => {
+                let reply = ProtocolMessage::new(MessageType::EchoReply { payload });
+                self.send_message(conn, &reply)?;
+            }
+            
+            MessageType::Subscribe => {
+                if let Some(user) = current_user {
+                    self.handle_subscribe(user.clone(), conn)?;
+                } else {
+                    let error = ProtocolMessage::new(
+                        MessageType::Error { message: "Non connecté".to_string() }
+                    );
+                    self.send_message(conn, &error)?;
+                }
+            }
+            
+            MessageType::PrivateMessage { to, content } => {
+                if let Some(user) = current_user {
+                    self.handle_private_message(user.clone(), to, content, conn)?;
+                } else {
+                    let error = ProtocolMessage::new(
+                        MessageType::Error { message: "Non connecté".to_string() }
+                    );
+                    self.send_message(conn, &error)?;
+                }
+            }
+            
+            MessageType::Announce { content } => {
+                if current_user.is_some() && *current_user == self.admin {
+                    self.handle_announce(content);
+                } else {
+                    let error = ProtocolMessage::new(
+                        MessageType::Error { message: "Annonce réservée à l'administrateur".to_string() }
+                    );
+                    self.send_message(conn, &error)?;
+                }
+            }
+            
+            MessageType::ServerInfo => {
+                let reply = ProtocolMessage::new(MessageType::ServerInfoReply {
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    uptime_secs: self.started_at.elapsed().as_secs(),
+                    users: self.users.lock().unwrap().len() as u32,
+                    rooms: self.rooms.lock().unwrap().len() as u32,
+                });
+                self.send_message(conn, &reply)?;
             }
             
             MessageType::Disconnect => {
@@ -212,7 +822,7 @@ impl ChatServer {
                 let error = ProtocolMessage::new(
                     MessageType::Error { message: "Message non supporté".to_string() }
                 );
-                self.send_message(stream, &error)?;
+                self.send_message(conn, &error)?;
             }
         }
         
@@ -222,73 +832,175 @@ impl ChatServer {
     fn handle_connect(
         &self,
         username: String,
+        password: String,
+        token: Option<String>,
+        codec: Option<Codec>,
         current_user: &mut Option<String>,
-        stream: &mut TcpStream,
+        conn: &mut Connection,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut users = self.users.lock().unwrap();
-        
-        if users.contains_key(&username) {
+        // Un second Connect sur la même connexion laisserait l'ancien nom orphelin
+        if let Some(existing) = current_user {
             let response = ProtocolMessage::new(
                 MessageType::ConnectAck {
                     success: false,
-                    message: "Nom d'utilisateur déjà utilisé".to_string(),
+                    message: format!("Déjà connecté en tant que {}", existing),
+                    codec: conn.codec,
                 }
             );
-            self.send_message(stream, &response)?;
-        } else {
-            users.insert(username.clone(), User {
-                username: username.clone(),
-                current_room: None,
-            });
-            
-            // Stocker la connexion
+            return self.send_message(conn, &response);
+        }
+        
+        if let Some(ref secret) = self.token
+            && token.as_ref() != Some(secret) {
+            eprintln!("Connexion de {} refusée (jeton invalide)", username);
+            let response = ProtocolMessage::new(
+                MessageType::ConnectAck { success: false, message: "bad token".to_string(), codec: conn.codec }
+            );
+            return self.send_message(conn, &response);
+        }
+        
+        if let Some(ref accounts) = self.accounts
+            && let Err(message) = accounts.lock().unwrap().authenticate(&username, &password) {
+            eprintln!("Connexion de {} refusée ({})", username, message);
+            let response = ProtocolMessage::new(
+                MessageType::ConnectAck { success: false, message, codec: conn.codec }
+            );
+            return self.send_message(conn, &response);
+        }
+        
+        // Cloné avant les verrous : un échec ne laisse rien d'enregistré.
+        // Les diffusions utiliseront déjà le codec négocié.
+        let chosen = codec.unwrap_or(conn.codec);
+        let mut stored_conn = conn.try_clone()?;
+        stored_conn.codec = chosen;
+        
+        // Vérification et enregistrement (utilisateur et connexion) sous les deux
+        // verrous à la fois : deux Connect simultanés ne peuvent pas réussir tous les deux
+        let registered = {
+            let mut users = self.users.lock().unwrap();
             let mut connections = self.connections.lock().unwrap();
-            connections.insert(username.clone(), stream.try_clone()?);
-            
-            *current_user = Some(username.clone());
-            
+            if users.contains_key(&username) {
+                false
+            } else {
+                users.insert(username.clone(), User {
+                    username: username.clone(),
+                    current_room: None,
+                });
+                connections.insert(username.clone(), stored_conn);
+                true
+            }
+        };
+        
+        // Réponse envoyée verrous relâchés : un client lent ne bloque pas les autres
+        if !registered {
             let response = ProtocolMessage::new(
                 MessageType::ConnectAck {
-                    success: true,
-                    message: format!("Bienvenue, {} !", username),
+                    success: false,
+                    message: "Nom d'utilisateur déjà utilisé".to_string(),
+                    codec: conn.codec,
                 }
             );
-            self.send_message(stream, &response)?;
-            
-            println!("{} s'est connecté", username);
+            return self.send_message(conn, &response);
+        }
+        
+        *current_user = Some(username.clone());
+        let response = ProtocolMessage::new(
+            MessageType::ConnectAck {
+                success: true,
+                message: format!("Bienvenue, {} !", username),
+                codec: chosen,
+            }
+        );
+        // Accusé envoyé dans le codec du Connect, la suite dans le codec retenu
+        self.send_message(conn, &response)?;
+        if chosen != conn.codec {
+            println!("{} passe au codec {:?}", username, chosen);
+            conn.codec = chosen;
         }
+        println!("{} s'est connecté", username);
         
+        // Verrous relâchés : la diffusion peut déconnecter un abonné bloqué
+        self.broadcast_presence(MessageType::UserOnline { username: username.clone() }, &username);
+        
+        Ok(())
+    }
+    
+    /// Abonne l'utilisateur au canal de présence et lui envoie d'abord les
+    /// utilisateurs déjà en ligne, pour initialiser sa liste de contacts
+    fn handle_subscribe(&self, username: String, conn: &mut Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let mut online: Vec<String> = self
+            .users
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|user| **user != username)
+            .cloned()
+            .collect();
+        online.sort();
+        self.presence_subscribers.lock().unwrap().insert(username);
+        
+        for user in online {
+            self.send_message(conn, &ProtocolMessage::new(MessageType::UserOnline { username: user }))?;
+        }
         Ok(())
     }
     
+    /// Relaie l'annonce à tous les utilisateurs connectés, quel que soit leur salon
+    fn handle_announce(&self, content: String) {
+        let usernames: Vec<String> = self.connections.lock().unwrap().keys().cloned().collect();
+        println!("Annonce envoyée à {} utilisateur(s)", usernames.len());
+        self.deliver(&usernames, MessageType::System {
+            content: format!("{} {}", ANNOUNCE_MARKER, content),
+        });
+    }
+    
     fn handle_join_room(
         &self,
         username: String,
         room: String,
-        stream: &mut TcpStream,
+        conn: &mut Connection,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut users = self.users.lock().unwrap();
-        let mut rooms = self.rooms.lock().unwrap();
-        
-        // Quitter l'ancien salon si nécessaire
-        if let Some(user) = users.get(&username) {
-            if let Some(ref old_room) = user.current_room {
-                if let Some(room_users) = rooms.get_mut(old_room) {
-                    room_users.retain(|u| u != &username);
-                    // Notifier les autres utilisateurs
-                    self.broadcast_to_room(old_room, MessageType::UserLeft {
-                        room: old_room.clone(),
-                        username: username.clone(),
-                    }, Some(&username))?;
-                }
+        let old_room = {
+            let mut users = self.users.lock().unwrap();
+            let mut rooms = self.rooms.lock().unwrap();
+            
+            let old_room = users.get(&username).and_then(|user| user.current_room.clone());
+            if let Err(message) = self.check_room_limits(&rooms, &room) {
+                drop(rooms);
+                drop(users);
+                let error = ProtocolMessage::new(MessageType::Error { message });
+                self.send_message(conn, &error)?;
+                return Ok(());
             }
-        }
+            
+            // Quitter l'ancien salon si nécessaire
+            if let Some(ref old_room) = old_room
+                && Self::leave_room(&mut rooms, old_room, &username)
+                && *old_room != room {
+                self.forget_empty_room(old_room);
+            }
+            
+            // Rejoindre le nouveau salon
+            rooms.entry(room.clone()).or_default().push(username.clone());
+            
+            if let Some(user) = users.get_mut(&username) {
+                user.current_room = Some(room.clone());
+            }
+            
+            old_room
+        };
         
-        // Rejoindre le nouveau salon
-        rooms.entry(room.clone()).or_insert_with(Vec::new).push(username.clone());
+        if let Some(ref old_room) = old_room {
+            self.audit(AuditAction::Leave, &username, old_room);
+        }
+        self.audit(AuditAction::Join, &username, &room);
         
-        if let Some(user) = users.get_mut(&username) {
-            user.current_room = Some(room.clone());
+        // Notifier les autres utilisateurs (verrous relâchés)
+        if let Some(old_room) = old_room {
+            self.broadcast_to_room(&old_room, MessageType::UserLeft {
+                room: old_room.clone(),
+                username: username.clone(),
+            }, Some(&username));
         }
         
         let response = ProtocolMessage::new(
@@ -298,69 +1010,347 @@ impl ChatServer {
                 message: format!("Vous avez rejoint le salon {}", room),
             }
         );
-        self.send_message(stream, &response)?;
+        self.send_message(conn, &response)?;
+        
+        // Rattrapage : derniers messages du salon, juste après l'accusé
+        let messages: Vec<HistoryEntry> = self
+            .history
+            .lock()
+            .unwrap()
+            .get(&room)
+            .map(|messages| messages.iter().cloned().collect())
+            .unwrap_or_default();
+        if !messages.is_empty() {
+            self.send_message(conn, &ProtocolMessage::new(MessageType::History { room: room.clone(), messages }))?;
+        }
         
         // Notifier les autres utilisateurs
         self.broadcast_to_room(&room, MessageType::UserJoined {
             room: room.clone(),
             username: username.clone(),
-        }, Some(&username))?;
+        }, Some(&username));
         
         println!("{} a rejoint le salon {}", username, room);
         
         Ok(())
     }
     
-    fn handle_send_message(
-        &self,
-        username: String,
-        room: String,
-        content: String,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let users = self.users.lock().unwrap();
-        
-        // Vérifier que l'utilisateur est dans le salon
-        if let Some(user) = users.get(&username) {
-            if user.current_room.as_ref() == Some(&room) {
-                let timestamp = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                
-                self.broadcast_to_room(&room, MessageType::MessageBroadcast {
-                    room: room.clone(),
-                    username: username.clone(),
-                    content,
-                    timestamp,
-                }, None)?;
-                
-                println!("[{}] {}: message envoyé", room, username);
-            }
+    /// Ajoute un événement au journal d'audit ; une erreur d'écriture est
+    /// signalée sans interrompre le traitement du client
+    fn audit(&self, action: AuditAction, username: &str, room: &str) {
+        let Some(ref path) = self.audit_path else {
+            return;
+        };
+        let event = AuditEvent {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            action,
+            username: username.to_string(),
+            room: room.to_string(),
+        };
+        
+        let _guard = self.audit_lock.lock().unwrap();
+        let result = serde_json::to_string(&event)
+            .map_err(io::Error::from)
+            .and_then(|line| {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", line)
+            });
+        if let Err(e) = result {
+            eprintln!("Erreur journal d'audit {}: {}", path, e);
+        }
+    }
+    
+    /// Vérifie les limites de salons avant qu'un utilisateur ne rejoigne `room`
+    fn check_room_limits(&self, rooms: &HashMap<String, Vec<String>>, room: &str) -> Result<(), String> {
+        // Création d'un nouveau salon : vérifier la limite globale
+        if !rooms.contains_key(room) && rooms.len() >= self.max_rooms {
+            return Err(format!(
+                "Impossible de créer le salon {} : limite de {} salons atteinte",
+                room, self.max_rooms
+            ));
+        }
+        
+        // Un seul salon à la fois : l'ancien est quitté, l'utilisateur n'est
+        // ensuite que dans `room`. À revoir quand plusieurs salons seront possibles
+        let joined_after = 1;
+        if joined_after > self.max_rooms_per_user {
+            return Err(format!(
+                "Impossible de rejoindre {} : limite de {} salons par utilisateur atteinte",
+                room, self.max_rooms_per_user
+            ));
         }
         
         Ok(())
     }
     
-    fn handle_list_rooms(&self, stream: &mut TcpStream) -> Result<(), Box<dyn std::error::Error>> {
-        let rooms = self.rooms.lock().unwrap();
-        let room_list: Vec<String> = rooms.keys().cloned().collect();
+    /// Retire l'utilisateur du salon ; `true` si le salon est désormais vide
+    fn leave_room(rooms: &mut HashMap<String, Vec<String>>, room: &str, username: &str) -> bool {
+        if let Some(room_users) = rooms.get_mut(room) {
+            room_users.retain(|u| u != username);
+            if room_users.is_empty() {
+                rooms.remove(room);
+                return true;
+            }
+        }
+        false
+    }
+    
+    /// Oublie l'historique d'un salon qui vient de se vider, sauf si l'archivage
+    /// est actif : c'est alors lui qui l'écrit sur disque puis le retire
+    fn forget_empty_room(&self, room: &str) {
+        if self.archive.is_none() {
+            self.history.lock().unwrap().remove(room);
+            self.last_activity.lock().unwrap().remove(room);
+        }
+    }
+    
+    fn handle_send_message(
+        &self,
+        username: String,
+        room: String,
+        content: String,
+        seq: u64,
+        reply_to: Option<String>,
+        conn: &mut Connection,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let ack = ProtocolMessage::new(MessageType::MessageAck { seq });
+        
+        // Une réponse doit viser un message encore présent dans l'historique du salon
+        if let Some(ref parent) = reply_to {
+            let known = self
+                .history
+                .lock()
+                .unwrap()
+                .get(&room)
+                .is_some_and(|messages| messages.iter().any(|entry| entry.id == *parent));
+            if !known {
+                let error = ProtocolMessage::new(MessageType::Error {
+                    message: format!("Message {} introuvable dans {}", parent, room),
+                });
+                return self.send_message(conn, &error);
+            }
+        }
+        
+        // Vérifier que l'utilisateur est dans le salon (verrou relâché avant la
+        // diffusion, qui peut déconnecter un client bloqué via cleanup_user), et
+        // ce avant de consommer le numéro : un renvoi une fois le salon rejoint
+        // ne doit pas passer pour un doublon
+        let in_room = self
+            .users
+            .lock()
+            .unwrap()
+            .get(&username)
+            .is_some_and(|user| user.current_room.as_ref() == Some(&room));
+        if !in_room {
+            let error = ProtocolMessage::new(MessageType::Error {
+                message: format!("Vous n'êtes pas dans le salon {}", room),
+            });
+            return self.send_message(conn, &error);
+        }
+        
+        // Doublon (renvoi après un ack perdu) : acquitter de nouveau sans rediffuser
+        {
+            let mut last_seq = self.last_seq.lock().unwrap();
+            if last_seq.get(&username).is_some_and(|&last| seq <= last) {
+                drop(last_seq);
+                self.send_message(conn, &ack)?;
+                return Ok(());
+            }
+            last_seq.insert(username.clone(), seq);
+        }
+        
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let content = self
+            .transformers
+            .iter()
+            .fold(content, |content, transformer| transformer.transform(&content));
+        
+        let id = self.next_message_id.fetch_add(1, Ordering::SeqCst).to_string();
+        
+        self.last_activity.lock().unwrap().insert(room.clone(), Instant::now());
+        self.record_history(&room, HistoryEntry {
+            id: id.clone(),
+            reply_to: reply_to.clone(),
+            username: username.clone(),
+            content: content.clone(),
+            timestamp,
+        });
+        
+        self.broadcast_to_room(&room, MessageType::MessageBroadcast {
+            id,
+            room: room.clone(),
+            username: username.clone(),
+            content,
+            timestamp,
+            reply_to,
+        }, None);
+        
+        println!("[{}] {}: message envoyé", room, username);
+        self.send_message(conn, &ack)
+    }
+    
+    /// Vérifie régulièrement les salons inactifs ; ne se termine pas. Le premier
+    /// passage est immédiat : les salons restaurés déjà inactifs partent aussitôt
+    fn archive_loop(&self, config: ArchiveConfig) {
+        let interval = (config.idle / 2).clamp(Duration::from_millis(100), Duration::from_secs(60));
+        loop {
+            self.archive_idle_rooms(&config);
+            thread::sleep(interval);
+        }
+    }
+    
+    /// Écrit sur disque puis oublie l'historique des salons vides inactifs depuis
+    /// `config.idle` ; rejoindre le salon le recrée sans historique
+    fn archive_idle_rooms(&self, config: &ArchiveConfig) {
+        // Verrou des salons conservé : personne ne peut rejoindre pendant l'archivage
+        let rooms = self.rooms.lock().unwrap();
+        let mut history = self.history.lock().unwrap();
+        let mut last_activity = self.last_activity.lock().unwrap();
+        
+        let idle_rooms: Vec<String> = last_activity
+            .iter()
+            .filter(|(room, last)| last.elapsed() >= config.idle && !rooms.contains_key(*room))
+            .map(|(room, _)| room.clone())
+            .collect();
+        
+        for room in idle_rooms {
+            let entries = history.get(&room).map(|messages| messages.iter().collect()).unwrap_or_default();
+            match write_archive(&config.dir, &room, entries) {
+                Ok(path) => {
+                    println!("Salon {} inactif archivé dans {}", room, path);
+                    history.remove(&room);
+                    last_activity.remove(&room);
+                }
+                // Historique gardé en mémoire, nouvel essai au prochain passage
+                Err(e) => eprintln!("Archivage du salon {} impossible: {}", room, e),
+            }
+        }
+    }
+    
+    fn record_history(&self, room: &str, entry: HistoryEntry) {
+        let mut history = self.history.lock().unwrap();
+        let messages = history.entry(room.to_string()).or_default();
+        
+        // File bornée : on oublie les plus anciens messages
+        if messages.len() >= HISTORY_SIZE {
+            messages.pop_front();
+        }
+        messages.push_back(entry);
+    }
+    
+    fn handle_get_history(&self, room: String, limit: u32, conn: &mut Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let history = self.history.lock().unwrap();
+        let limit = (limit as usize).min(HISTORY_SIZE);
+        
+        let messages: Vec<HistoryEntry> = match history.get(&room) {
+            Some(messages) => messages.iter().skip(messages.len().saturating_sub(limit)).cloned().collect(),
+            None => Vec::new(),
+        };
+        drop(history);
+        
+        let response = ProtocolMessage::new(
+            MessageType::History { room, messages }
+        );
+        self.send_message(conn, &response)?;
+        
+        Ok(())
+    }
+    
+    /// Transmet le message au seul destinataire, sans historique ni diffusion
+    fn handle_private_message(&self, from: String, to: String, content: String, conn: &mut Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let refusal = if to == from {
+            Some("Impossible de s'envoyer un message privé".to_string())
+        } else if !self.connections.lock().unwrap().contains_key(&to) {
+            Some(format!("Utilisateur {} introuvable ou déconnecté", to))
+        } else {
+            None
+        };
+        if let Some(message) = refusal {
+            return self.send_message(conn, &ProtocolMessage::new(MessageType::Error { message }));
+        }
+        
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let content = self
+            .transformers
+            .iter()
+            .fold(content, |content, transformer| transformer.transform(&content));
+        self.deliver(&[to], MessageType::PrivateMessageDelivered { from, content, timestamp });
+        Ok(())
+    }
+    
+    /// Envoie le message `root_id` du salon courant suivi de ses réponses
+    /// (réponses de réponses comprises), dans l'ordre de l'historique
+    fn handle_get_thread(&self, username: String, root_id: String, conn: &mut Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let room = self.users.lock().unwrap().get(&username).and_then(|user| user.current_room.clone());
+        let Some(room) = room else {
+            let error = ProtocolMessage::new(MessageType::Error { message: "Pas dans un salon".to_string() });
+            return self.send_message(conn, &error);
+        };
+        
+        let messages = {
+            let history = self.history.lock().unwrap();
+            let mut thread_ids: HashSet<&str> = HashSet::new();
+            let mut messages = Vec::new();
+            for entry in history.get(&room).into_iter().flatten() {
+                let in_thread = entry.id == root_id
+                    || entry.reply_to.as_deref().is_some_and(|parent| thread_ids.contains(parent));
+                if in_thread {
+                    thread_ids.insert(&entry.id);
+                    messages.push(entry.clone());
+                }
+            }
+            messages
+        };
+        
+        let response = if messages.first().is_some_and(|entry| entry.id == root_id) {
+            ProtocolMessage::new(MessageType::Thread { room, root_id, messages })
+        } else {
+            ProtocolMessage::new(MessageType::Error {
+                message: format!("Message {} introuvable dans {}", root_id, room),
+            })
+        };
+        self.send_message(conn, &response)
+    }
+    
+    /// Salons occupés, ou avec `only_active = false` tous les salons connus,
+    /// y compris ceux vidés de leurs utilisateurs mais qui ont un historique
+    fn handle_list_rooms(&self, only_active: bool, conn: &mut Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let mut room_list: Vec<String> = self
+            .rooms
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, users)| !users.is_empty())
+            .map(|(room, _)| room.clone())
+            .collect();
+        if !only_active {
+            room_list.extend(self.history.lock().unwrap().keys().cloned());
+        }
+        room_list.sort();
+        room_list.dedup();
         
         let response = ProtocolMessage::new(
             MessageType::RoomList { rooms: room_list }
         );
-        self.send_message(stream, &response)?;
+        self.send_message(conn, &response)?;
         
         Ok(())
     }
     
-    fn handle_list_users(&self, room: String, stream: &mut TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+    fn handle_list_users(&self, room: String, conn: &mut Connection) -> Result<(), Box<dyn std::error::Error>> {
         let rooms = self.rooms.lock().unwrap();
         let users = rooms.get(&room).cloned().unwrap_or_default();
         
         let response = ProtocolMessage::new(
             MessageType::UserList { room, users }
         );
-        self.send_message(stream, &response)?;
+        self.send_message(conn, &response)?;
         
         Ok(())
     }
@@ -370,64 +1360,154 @@ impl ChatServer {
         room: &str,
         message_type: MessageType,
         exclude_user: Option<&str>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let rooms = self.rooms.lock().unwrap();
-        let connections = self.connections.lock().unwrap();
+    ) {
+        let usernames: Vec<String> = self
+            .rooms
+            .lock()
+            .unwrap()
+            .get(room)
+            .into_iter()
+            .flatten()
+            .filter(|username| exclude_user != Some(username.as_str()))
+            .cloned()
+            .collect();
+        self.deliver(&usernames, message_type);
+    }
+    
+    /// Événement de présence pour tous les abonnés, sauf l'utilisateur concerné
+    fn broadcast_presence(&self, message_type: MessageType, about: &str) {
+        let subscribers: Vec<String> = self
+            .presence_subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|subscriber| subscriber.as_str() != about)
+            .cloned()
+            .collect();
+        self.deliver(&subscribers, message_type);
+    }
+    
+    /// Envoie le message aux utilisateurs indiqués encore connectés
+    fn deliver(&self, usernames: &[String], message_type: MessageType) {
+        // Copier les destinataires puis relâcher les verrous avant d'écrire
+        let recipients: Vec<(String, Connection)> = {
+            let connections = self.connections.lock().unwrap();
+            usernames
+                .iter()
+                .filter_map(|username| {
+                    let conn = connections.get(username)?.try_clone().ok()?;
+                    Some((username.clone(), conn))
+                })
+                .collect()
+        };
         
-        if let Some(room_users) = rooms.get(room) {
-            let message = ProtocolMessage::new(message_type);
-            
-            for username in room_users {
-                if let Some(exclude) = exclude_user {
-                    if username == exclude {
-                        continue;
-                    }
-                }
-                
-                if let Some(mut stream) = connections.get(username).and_then(|s| s.try_clone().ok()) {
-                    let _ = self.send_message(&mut stream, &message);
-                }
+        let message = ProtocolMessage::new(message_type);
+        let mut stalled = Vec::new();
+        for (username, mut conn) in recipients {
+            // Écriture expirée (client qui ne lit plus) ou en échec : déconnexion
+            if let Err(e) = self.send_message(&mut conn, &message) {
+                eprintln!("Envoi à {} impossible ({}), déconnexion", username, e);
+                let peer = conn.stream.peer_addr().ok();
+                let _ = conn.stream.shutdown(std::net::Shutdown::Both);
+                stalled.push((username, peer));
             }
         }
         
-        Ok(())
+        for (username, peer) in stalled {
+            // Ne pas évincer un utilisateur reconnecté entre-temps sous le même nom
+            let same_session = self
+                .connections
+                .lock()
+                .unwrap()
+                .get(&username)
+                .is_some_and(|current| current.stream.peer_addr().ok() == peer);
+            if same_session {
+                self.cleanup_user(&username);
+            }
+        }
     }
     
-    fn send_message(stream: &mut TcpStream, message: &ProtocolMessage) -> Result<(), Box<dyn std::error::Error>> {
-        let data = message.serialize()?;
-        stream.write_all(&data)?;
+    fn send_message(&self, conn: &mut Connection, message: &ProtocolMessage) -> Result<(), Box<dyn std::error::Error>> {
+        let data = message.serialize(conn.codec)?;
+        let stream = &mut conn.stream;
+        
+        // Le délai du socket est remis à zéro à chaque écriture partielle : un
+        // client qui lit au compte-gouttes est borné par une échéance globale
+        let deadline = Instant::now() + self.write_timeout;
+        let mut written = 0;
+        while written < data.len() {
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "délai d'écriture dépassé").into());
+            }
+            match stream.write(&data[written..]) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
         stream.flush()?;
         Ok(())
     }
     
     fn cleanup_user(&self, username: &str) {
-        let mut users = self.users.lock().unwrap();
-        let mut rooms = self.rooms.lock().unwrap();
-        let mut connections = self.connections.lock().unwrap();
-        
-        // Retirer l'utilisateur de son salon
-        if let Some(user) = users.get(username) {
-            if let Some(ref room) = user.current_room {
-                if let Some(room_users) = rooms.get_mut(room) {
-                    room_users.retain(|u| u != username);
-                    // Notifier les autres
-                    let _ = self.broadcast_to_room(room, MessageType::UserLeft {
-                        room: room.clone(),
-                        username: username.to_string(),
-                    }, Some(username));
-                }
+        // Appelé aussi pour un client bloqué lors d'une diffusion : seul le
+        // premier appel trouve encore l'utilisateur et notifie les autres
+        let user = {
+            let mut users = self.users.lock().unwrap();
+            let mut rooms = self.rooms.lock().unwrap();
+            let mut connections = self.connections.lock().unwrap();
+            
+            // Retirer l'utilisateur de son salon
+            let user = users.remove(username);
+            if let Some(room) = user.as_ref().and_then(|user| user.current_room.as_ref())
+                && Self::leave_room(&mut rooms, room, username) {
+                self.forget_empty_room(room);
             }
-        }
+            
+            connections.remove(username);
+            self.last_seq.lock().unwrap().remove(username);
+            self.presence_subscribers.lock().unwrap().remove(username);
+            user
+        };
+        let Some(user) = user else {
+            return;
+        };
         
-        users.remove(username);
-        connections.remove(username);
+        // Notifier les autres (verrous relâchés)
+        self.broadcast_presence(MessageType::UserOffline { username: username.to_string() }, username);
+        if let Some(room) = user.current_room {
+            self.audit(AuditAction::Leave, username, &room);
+            self.broadcast_to_room(&room, MessageType::UserLeft {
+                room: room.clone(),
+                username: username.to_string(),
+            }, Some(username));
+        }
     }
 }
 
+/// Callback appelé pour chaque message reçu par le client (bots, automatisation)
+pub type MessageHandler = Box<dyn FnMut(&MessageType) + Send>;
+
 pub struct ChatClient {
     stream: Option<TcpStream>,
     username: Option<String>,
-    current_room: Option<String>,
+    // Partagé avec le thread d'écoute, qui le met à jour à la réception du JoinRoomAck
+    current_room: Arc<Mutex<Option<String>>>,
+    on_message: Option<MessageHandler>,
+    // Numéro du prochain SendMessage, partagé entre les clones
+    next_seq: Arc<AtomicU64>,
+    // Une fois l'écoute démarrée, seul le thread d'écoute lit le stream
+    listening: bool,
+    codec: Codec,
+    token: Option<String>,
+    password: String,
+}
+
+impl Default for ChatClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ChatClient {
@@ -435,10 +1515,62 @@ impl ChatClient {
         Self {
             stream: None,
             username: None,
-            current_room: None,
+            current_room: Arc::new(Mutex::new(None)),
+            on_message: None,
+            next_seq: Arc::new(AtomicU64::new(1)),
+            listening: false,
+            codec: Codec::default(),
+            token: None,
+            password: String::new(),
         }
     }
     
+    /// Jeton envoyé avec `login`, pour un serveur lancé avec `--token`
+    pub fn set_token(&mut self, token: Option<String>) {
+        self.token = token;
+    }
+    
+    /// Mot de passe envoyé avec `login`, pour un serveur lancé avec `--users`
+    pub fn set_password(&mut self, password: String) {
+        self.password = password;
+    }
+    
+    /// Codec des trames, demandé au serveur dans le `Connect` ; le serveur
+    /// reconnaît le JSON dès le premier octet, les autres passent par bincode
+    pub fn set_codec(&mut self, codec: Codec) {
+        self.codec = codec;
+    }
+    
+    /// Enregistre un callback invoqué par `start_message_listener` pour chaque
+    /// message reçu, à la place de l'affichage par défaut sur stdout
+    pub fn set_on_message<F>(&mut self, handler: F)
+    where
+        F: FnMut(&MessageType) + Send + 'static,
+    {
+        self.on_message = Some(Box::new(handler));
+    }
+    
+    /// Duplique le client sur la même connexion, pour pouvoir envoyer des
+    /// messages depuis un callback (ex: un bot qui répond à des mots-clés)
+    pub fn try_clone(&self) -> io::Result<ChatClient> {
+        let stream = match self.stream {
+            Some(ref stream) => Some(stream.try_clone()?),
+            None => None,
+        };
+        
+        Ok(ChatClient {
+            stream,
+            username: self.username.clone(),
+            current_room: Arc::clone(&self.current_room),
+            on_message: None,
+            next_seq: Arc::clone(&self.next_seq),
+            listening: self.listening,
+            codec: self.codec,
+            token: self.token.clone(),
+            password: self.password.clone(),
+        })
+    }
+    
     pub fn connect(&mut self, addr: &str) -> Result<(), Box<dyn std::error::Error>> {
         let stream = TcpStream::connect(addr)?;
         self.stream = Some(stream);
@@ -448,18 +1580,29 @@ impl ChatClient {
     
     pub fn login(&mut self, username: String) -> Result<bool, Box<dyn std::error::Error>> {
         if let Some(ref mut stream) = self.stream {
-            let message = ProtocolMessage::new(MessageType::Connect { username: username.clone() });
-            self.send_message(stream, &message)?;
+            let message = ProtocolMessage::new(MessageType::Connect {
+                username: username.clone(),
+                password: self.password.clone(),
+                token: self.token.clone(),
+                codec: Some(self.codec),
+            });
+            let handshake = self.codec.handshake();
+            Self::send_message(stream, &message, handshake)?;
             
-            let response = ProtocolMessage::deserialize_from_stream(stream)?;
+            let response = ProtocolMessage::deserialize_from_stream(stream, handshake)?;
             match response.message_type {
-                MessageType::ConnectAck { success, message } => {
+                MessageType::ConnectAck { success, message, codec } => {
                     println!("{}", message);
                     if success {
                         self.username = Some(username);
+                        self.codec = codec;
                     }
                     Ok(success)
                 }
+                MessageType::Error { message } => {
+                    println!("Erreur: {}", message);
+                    Ok(false)
+                }
                 _ => Ok(false),
             }
         } else {
@@ -470,17 +1613,26 @@ impl ChatClient {
     pub fn join_room(&mut self, room: String) -> Result<bool, Box<dyn std::error::Error>> {
         if let Some(ref mut stream) = self.stream {
             let message = ProtocolMessage::new(MessageType::JoinRoom { room: room.clone() });
-            self.send_message(stream, &message)?;
+            Self::send_message(stream, &message, self.codec)?;
+            
+            // La réponse sera traitée par le thread d'écoute
+            if self.listening {
+                return Ok(true);
+            }
             
-            let response = ProtocolMessage::deserialize_from_stream(stream)?;
+            let response = ProtocolMessage::deserialize_from_stream(stream, self.codec)?;
             match response.message_type {
                 MessageType::JoinRoomAck { success, message, .. } => {
                     println!("{}", message);
                     if success {
-                        self.current_room = Some(room);
+                        *self.current_room.lock().unwrap() = Some(room);
                     }
                     Ok(success)
                 }
+                MessageType::Error { message } => {
+                    println!("Erreur: {}", message);
+                    Ok(false)
+                }
                 _ => Ok(false),
             }
         } else {
@@ -488,32 +1640,45 @@ impl ChatClient {
         }
     }
     
-    pub fn send_message(&mut self, stream: &mut TcpStream, message: &ProtocolMessage) -> Result<(), Box<dyn std::error::Error>> {
-        let data = message.serialize()?;
+    pub fn send_message(stream: &mut TcpStream, message: &ProtocolMessage, codec: Codec) -> Result<(), Box<dyn std::error::Error>> {
+        let data = message.serialize(codec)?;
         stream.write_all(&data)?;
         stream.flush()?;
         Ok(())
     }
     
     pub fn send_chat_message(&mut self, content: String) -> Result<(), Box<dyn std::error::Error>> {
-        if let (Some(ref mut stream), Some(ref room)) = (&mut self.stream, &self.current_room) {
+        self.send_reply(content, None)
+    }
+    
+    /// Envoie un message, en réponse au message `reply_to` s'il est donné
+    pub fn send_reply(&mut self, content: String, reply_to: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+        let current_room = self.current_room.lock().unwrap().clone();
+        if let (Some(stream), Some(room)) = (&mut self.stream, &current_room) {
             let message = ProtocolMessage::new(MessageType::SendMessage {
                 room: room.clone(),
                 content,
+                seq: self.next_seq.fetch_add(1, Ordering::SeqCst),
+                reply_to,
             });
-            self.send_message(stream, &message)?;
+            Self::send_message(stream, &message, self.codec)?;
             Ok(())
         } else {
             Err("Non connecté ou pas dans un salon".into())
         }
     }
     
-    pub fn list_rooms(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Liste les salons occupés, ou tous les salons connus si `only_active` est faux
+    pub fn list_rooms(&mut self, only_active: bool) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(ref mut stream) = self.stream {
-            let message = ProtocolMessage::new(MessageType::ListRooms);
-            self.send_message(stream, &message)?;
+            let message = ProtocolMessage::new(MessageType::ListRooms { only_active });
+            Self::send_message(stream, &message, self.codec)?;
             
-            let response = ProtocolMessage::deserialize_from_stream(stream)?;
+            if self.listening {
+                return Ok(());
+            }
+            
+            let response = ProtocolMessage::deserialize_from_stream(stream, self.codec)?;
             match response.message_type {
                 MessageType::RoomList { rooms } => {
                     println!("Salons disponibles:");
@@ -529,29 +1694,98 @@ impl ChatClient {
         }
     }
     
+    pub fn get_history(&mut self, limit: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let current_room = self.current_room.lock().unwrap().clone();
+        if let (Some(stream), Some(room)) = (&mut self.stream, current_room) {
+            let message = ProtocolMessage::new(MessageType::GetHistory { room, limit });
+            Self::send_message(stream, &message, self.codec)?;
+            Ok(())
+        } else {
+            Err("Non connecté ou pas dans un salon".into())
+        }
+    }
+    
+    /// Demande le fil de discussion partant de `root_id` ; la réponse
+    /// `Thread` arrive au thread d'écoute
+    pub fn get_thread(&mut self, root_id: String) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ref mut stream) = self.stream {
+            Self::send_message(stream, &ProtocolMessage::new(MessageType::GetThread { root_id }), self.codec)?;
+            Ok(())
+        } else {
+            Err("Non connecté au serveur".into())
+        }
+    }
+    
+    /// S'abonne aux connexions/déconnexions de tout le serveur ; les
+    /// `UserOnline`/`UserOffline` arrivent ensuite au thread d'écoute
+    pub fn subscribe_presence(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ref mut stream) = self.stream {
+            Self::send_message(stream, &ProtocolMessage::new(MessageType::Subscribe), self.codec)?;
+            Ok(())
+        } else {
+            Err("Non connecté au serveur".into())
+        }
+    }
+    
+    pub fn server_info(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ref mut stream) = self.stream {
+            Self::send_message(stream, &ProtocolMessage::new(MessageType::ServerInfo), self.codec)?;
+            Ok(())
+        } else {
+            Err("Non connecté au serveur".into())
+        }
+    }
+    
+    pub fn send_private_message(&mut self, to: String, content: String) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ref mut stream) = self.stream {
+            Self::send_message(stream, &ProtocolMessage::new(MessageType::PrivateMessage { to, content }), self.codec)?;
+            Ok(())
+        } else {
+            Err("Non connecté au serveur".into())
+        }
+    }
+    
+    pub fn announce(&mut self, content: String) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ref mut stream) = self.stream {
+            Self::send_message(stream, &ProtocolMessage::new(MessageType::Announce { content }), self.codec)?;
+            Ok(())
+        } else {
+            Err("Non connecté au serveur".into())
+        }
+    }
+    
+    /// Envoie un écho horodaté ; le RTT est affiché à la réception de `EchoReply`
+    pub fn ping(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ref mut stream) = self.stream {
+            let message = ProtocolMessage::new(MessageType::Echo { payload: now_millis().to_string() });
+            Self::send_message(stream, &message, self.codec)?;
+            Ok(())
+        } else {
+            Err("Non connecté au serveur".into())
+        }
+    }
+    
     pub fn start_message_listener(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(stream) = self.stream.take() {
             let mut stream_clone = stream.try_clone()?;
             self.stream = Some(stream);
+            self.listening = true;
+            let mut handler = self.on_message.take();
+            let current_room = Arc::clone(&self.current_room);
+            let codec = self.codec;
             
             thread::spawn(move || {
+                let mut buffer = Vec::new();
                 loop {
-                    match ProtocolMessage::deserialize_from_stream(&mut stream_clone) {
+                    match read_frame(&mut stream_clone, &mut buffer, codec) {
                         Ok(message) => {
-                            match message.message_type {
-                                MessageType::MessageBroadcast { room, username, content, .. } => {
-                                    println!("[{}] {}: {}", room, username, content);
-                                }
-                                MessageType::UserJoined { room, username } => {
-                                    println!("{} a rejoint le salon {}", username, room);
-                                }
-                                MessageType::UserLeft { room, username } => {
-                                    println!("{} a quitté le salon {}", username, room);
-                                }
-                                MessageType::Error { message } => {
-                                    println!("Erreur: {}", message);
-                                }
-                                _ => {}
+                            if let MessageType::JoinRoomAck { success: true, ref room, .. } = message.message_type {
+                                *current_room.lock().unwrap() = Some(room.clone());
+                            }
+                            
+                            match handler {
+                                Some(ref mut handler) => handler(&message.message_type),
+                                None => print_message(&message.message_type),
                             }
                         }
                         Err(_) => {
@@ -569,35 +1803,210 @@ impl ChatClient {
     pub fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(ref mut stream) = self.stream {
             let message = ProtocolMessage::new(MessageType::Disconnect);
-            self.send_message(stream, &message)?;
+            Self::send_message(stream, &message, self.codec)?;
         }
         self.stream = None;
         self.username = None;
-        self.current_room = None;
+        *self.current_room.lock().unwrap() = None;
+        self.listening = false;
         println!("Déconnecté");
         Ok(())
     }
 }
 
+/// Affichage par défaut des messages reçus par le client
+fn print_message(message_type: &MessageType) {
+    match message_type {
+        MessageType::JoinRoomAck { message, .. } => {
+            println!("{}", message);
+        }
+        MessageType::RoomList { rooms } => {
+            println!("Salons disponibles:");
+            for room in rooms {
+                println!("  - {}", room);
+            }
+        }
+        MessageType::MessageBroadcast { id, room, username, content, reply_to, .. } => match reply_to {
+            Some(parent) => println!("[{}] #{} {} (↳ #{}): {}", room, id, username, parent, content),
+            None => println!("[{}] #{} {}: {}", room, id, username, content),
+        },
+        MessageType::UserJoined { room, username } => {
+            println!("{} a rejoint le salon {}", username, room);
+        }
+        MessageType::UserLeft { room, username } => {
+            println!("{} a quitté le salon {}", username, room);
+        }
+        MessageType::Error { message } => {
+            println!("Erreur: {}", message);
+        }
+        MessageType::History { room, messages } => {
+            println!("Historique de {} ({} messages):", room, messages.len());
+            for entry in messages {
+                println!("  [{}] #{} {}: {}", room, entry.id, entry.username, entry.content);
+            }
+        }
+        MessageType::Thread { room, root_id, messages } => {
+            println!("Fil #{} dans {} ({} messages):", root_id, room, messages.len());
+            for entry in messages {
+                match &entry.reply_to {
+                    Some(parent) => println!("  #{} {} (↳ #{}): {}", entry.id, entry.username, parent, entry.content),
+                    None => println!("  #{} {}: {}", entry.id, entry.username, entry.content),
+                }
+            }
+        }
+        MessageType::UserOnline { username } => {
+            println!("🟢 {} est en ligne", username);
+        }
+        MessageType::UserOffline { username } => {
+            println!("⚪ {} est hors ligne", username);
+        }
+        MessageType::PrivateMessageDelivered { from, content, .. } => {
+            println!("💬 [privé] {}: {}", from, content);
+        }
+        MessageType::System { content } => match content.strip_prefix(ANNOUNCE_MARKER) {
+            Some(announcement) => {
+                println!("\n📢 ==================== ANNONCE ====================");
+                println!("📢 {}", announcement.trim());
+                println!("📢 =================================================\n");
+            }
+            None => println!("ℹ️  {}", content),
+        },
+        MessageType::ServerInfoReply { version, uptime_secs, users, rooms } => {
+            println!(
+                "Serveur SimpleChat {} : en ligne depuis {} s, {} utilisateur(s), {} salon(s)",
+                version, uptime_secs, users, rooms
+            );
+        }
+        MessageType::EchoReply { payload } => match payload.parse::<u128>() {
+            Ok(sent) => println!("Pong : {} ms", now_millis().saturating_sub(sent)),
+            Err(_) => println!("Écho : {}", payload),
+        },
+        _ => {}
+    }
+}
+
+/// Écrit l'historique d'un salon dans `<dir>/<salon>-<horodatage>.jsonl`
+/// (une entrée JSON par ligne) et retourne le chemin du fichier
+fn write_archive(dir: &str, room: &str, entries: Vec<&HistoryEntry>) -> io::Result<String> {
+    std::fs::create_dir_all(dir)?;
+    // Le nom du salon vient des clients : rien d'autre que des caractères sûrs
+    let safe_room: String = room
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let path = format!("{}/{}-{}.jsonl", dir, safe_room, timestamp);
+    
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    for entry in entries {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    file.sync_all()?;
+    Ok(path)
+}
+
+/// Horodatage en millisecondes, utilisé comme charge utile de `/ping`
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = std::env::args().collect();
     
     if args.len() < 2 {
         println!("Usage: {} [server|client] [options...]", args[0]);
-        println!("  server <addr>           - Démarrer le serveur");
-        println!("  client <addr>           - Démarrer le client");
+        println!(
+            "  server <addr> [max] [salons] [salons/utilisateur] [audit.jsonl] [--admin <nom>] [--archive <secondes>] [--token <secret>] [--transform <liste>] [--snapshot <fichier>] [--users <fichier>] - Démarrer le serveur (défauts {}, {}, {})",
+            DEFAULT_MAX_CONNECTIONS, DEFAULT_MAX_ROOMS, DEFAULT_MAX_ROOMS_PER_USER
+        );
+        println!("  (--archive : historique des salons vides et inactifs depuis N s déplacé dans {}/)", DEFAULT_ARCHIVE_DIR);
+        println!("  (--transform : transformations appliquées dans l'ordre aux messages, ex. trim,profanity,emoji,max=200)");
+        println!("  (--users : mots de passe exigés, comptes nom:sha256 ; fichier créé au premier compte s'il n'existe pas)");
+        println!("  (--snapshot : salons et historiques restaurés depuis ce fichier, sauvegardés sur Ctrl+C)");
+        println!("  client <addr> [--json|--msgpack] [--token <secret>] [--script <fichier>]  - Démarrer le client (trames JSON ou MessagePack au lieu de bincode)");
+        println!("  (--script : directives login <nom> [mot de passe], join <salon>, msg <texte>, sleep <ms>, quit, une par ligne)");
+        println!("  (addr \"[::]:8080\" : double pile IPv4/IPv6, port 0 : port choisi par le système)");
         return Ok(());
     }
     
     match args[1].as_str() {
         "server" => {
+            // Options nommées n'importe où, les autres sont positionnelles
+            let mut args = args.clone();
+            let admin = take_option(&mut args, "--admin");
+            let token = take_option(&mut args, "--token");
+            let transformers = match take_option(&mut args, "--transform") {
+                Some(spec) => parse_transformers(&spec)?,
+                None => Vec::new(),
+            };
+            let snapshot = take_option(&mut args, "--snapshot");
+            let users_file = take_option(&mut args, "--users");
+            let archive = take_option(&mut args, "--archive")
+                .and_then(|secs| secs.parse().ok())
+                .map(|secs| ArchiveConfig {
+                    dir: DEFAULT_ARCHIVE_DIR.to_string(),
+                    idle: Duration::from_secs(secs),
+                });
             let addr = args.get(2).map(|s| s.as_str()).unwrap_or("127.0.0.1:8080");
-            let server = ChatServer::new();
+            let max_connections = args
+                .get(3)
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(DEFAULT_MAX_CONNECTIONS);
+            let max_rooms = args
+                .get(4)
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(DEFAULT_MAX_ROOMS);
+            let max_rooms_per_user = args
+                .get(5)
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(DEFAULT_MAX_ROOMS_PER_USER);
+            let audit_path = args.get(6).cloned();
+            let mut server = ChatServer::new(
+                max_connections,
+                max_rooms,
+                max_rooms_per_user,
+                audit_path,
+                DEFAULT_WRITE_TIMEOUT,
+                admin,
+                snapshot.as_deref(),
+            )?;
+            server.set_archive(archive);
+            server.set_token(token);
+            server.set_transformers(transformers);
+            if let Some(path) = users_file {
+                let count = server.set_accounts(&path)?;
+                println!("{} compte(s) chargé(s) depuis {}", count, path);
+            }
+            // --snapshot <fichier> : salons restaurés par `new`, sauvegardés sur Ctrl+C
+            if let Some(path) = snapshot {
+                let server = server.clone();
+                ctrlc::set_handler(move || {
+                    match server.save_snapshot(&path) {
+                        Ok(()) => println!("\nSalons sauvegardés dans {}", path),
+                        Err(e) => eprintln!("\nSauvegarde de {} impossible: {}", path, e),
+                    }
+                    std::process::exit(0);
+                })?;
+            }
             server.start(addr)?;
         }
         "client" => {
+            let mut args = args.clone();
+            let token = take_option(&mut args, "--token");
+            let script = take_option(&mut args, "--script");
+            let codec = if args.iter().any(|a| a == "--json") {
+                Codec::Json
+            } else if args.iter().any(|a| a == "--msgpack") {
+                Codec::MessagePack
+            } else {
+                Codec::Bincode
+            };
+            args.retain(|a| a != "--json" && a != "--msgpack");
             let addr = args.get(2).map(|s| s.as_str()).unwrap_or("127.0.0.1:8080");
-            run_client(addr)?;
+            run_client(addr, codec, token, script)?;
         }
         _ => {
             println!("Mode non reconnu. Utilisez 'server' ou 'client'");
@@ -607,16 +2016,118 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn run_client(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Retire `--nom <valeur>` de `args` et retourne la valeur
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|a| a == name)?;
+    let value = args.get(index + 1).cloned();
+    args.drain(index..(index + 2).min(args.len()));
+    value
+}
+
+/// Directive d'un script client (`--script`), une par ligne
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+    Login { username: String, password: String },
+    Join(String),
+    Msg(String),
+    Sleep(Duration),
+    Quit,
+}
+
+/// Délai maximal d'attente de l'accusé d'un `join` avant la directive suivante
+const SCRIPT_JOIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Lit un script : `login <nom> [mot de passe]` d'abord, puis `join <salon>`, `msg <texte>`,
+/// `sleep <ms>` et `quit`. Lignes vides et commentaires (`#`) ignorés ; toute
+/// autre directive est une erreur, signalée avec son numéro de ligne.
+pub fn parse_script(content: &str) -> Result<Vec<ScriptCommand>, String> {
+    let mut commands = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (directive, arg) = line.split_once(' ').map(|(d, a)| (d, a.trim())).unwrap_or((line, ""));
+        let command = match (directive, arg) {
+            ("login", credentials) if !credentials.is_empty() => {
+                let (username, password) = credentials.split_once(' ').unwrap_or((credentials, ""));
+                ScriptCommand::Login { username: username.to_string(), password: password.trim().to_string() }
+            }
+            ("join", room) if !room.is_empty() => ScriptCommand::Join(room.to_string()),
+            ("msg", text) if !text.is_empty() => ScriptCommand::Msg(text.to_string()),
+            ("sleep", ms) => match ms.parse() {
+                Ok(ms) => ScriptCommand::Sleep(Duration::from_millis(ms)),
+                Err(_) => return Err(format!("ligne {}: durée invalide '{}'", index + 1, ms)),
+            },
+            ("quit", "") => ScriptCommand::Quit,
+            _ => return Err(format!("ligne {}: directive inconnue ou incomplète '{}'", index + 1, line)),
+        };
+        commands.push(command);
+    }
+    if !matches!(commands.first(), Some(ScriptCommand::Login { .. })) {
+        return Err("le script doit commencer par 'login <nom> [mot de passe]'".to_string());
+    }
+    Ok(commands)
+}
+
+/// Exécute les directives dans l'ordre ; les messages reçus s'affichent comme
+/// en mode interactif. Déconnexion à `quit` ou à la fin du script.
+fn run_script(client: &mut ChatClient, commands: Vec<ScriptCommand>) -> Result<(), Box<dyn std::error::Error>> {
+    for command in commands {
+        match command {
+            ScriptCommand::Login { username, password } => {
+                client.set_password(password);
+                if !client.login(username)? {
+                    return Err("échec de l'authentification".into());
+                }
+                client.start_message_listener()?;
+            }
+            ScriptCommand::Join(room) => {
+                client.join_room(room.clone())?;
+                // L'accusé passe par le thread d'écoute : l'attendre avant d'envoyer
+                let deadline = Instant::now() + SCRIPT_JOIN_TIMEOUT;
+                while client.current_room.lock().unwrap().as_deref() != Some(room.as_str()) {
+                    if Instant::now() >= deadline {
+                        return Err(format!("salon {} non rejoint", room).into());
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+            ScriptCommand::Msg(text) => client.send_chat_message(text)?,
+            ScriptCommand::Sleep(duration) => thread::sleep(duration),
+            ScriptCommand::Quit => break,
+        }
+    }
+    client.disconnect()
+}
+
+fn run_client(addr: &str, codec: Codec, token: Option<String>, script: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    // Script lu et vérifié avant toute connexion
+    let commands = match script {
+        Some(path) => Some(parse_script(&std::fs::read_to_string(&path)?).map_err(|e| format!("{}: {}", path, e))?),
+        None => None,
+    };
+    
     let mut client = ChatClient::new();
+    client.set_codec(codec);
+    client.set_token(token);
     client.connect(addr)?;
     
+    if let Some(commands) = commands {
+        return run_script(&mut client, commands);
+    }
+    
     // Authentification
     print!("👤 Nom d'utilisateur: ");
     io::stdout().flush()?;
     let mut username = String::new();
     io::stdin().read_line(&mut username)?;
     let username = username.trim().to_string();
+    print!("🔑 Mot de passe (vide si le serveur n'en demande pas): ");
+    io::stdout().flush()?;
+    let mut password = String::new();
+    io::stdin().read_line(&mut password)?;
+    client.set_password(password.trim().to_string());
     
     if !client.login(username)? {
         println!("Échec de l'authentification");
@@ -628,7 +2139,15 @@ fn run_client(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
     
     println!("\nCommandes disponibles:");
     println!("  /join <salon>     - Rejoindre un salon");
-    println!("  /rooms            - Lister les salons");
+    println!("  /rooms [all]      - Lister les salons occupés (all : aussi les vides)");
+    println!("  /history [n]      - Afficher les n derniers messages du salon");
+    println!("  /reply <id> <msg> - Répondre au message #id");
+    println!("  /thread <id>      - Afficher le fil du message #id");
+    println!("  /ping             - Mesurer la latence avec le serveur");
+    println!("  /info             - Version et état du serveur");
+    println!("  /presence         - Suivre les connexions de tout le serveur");
+    println!("  /msg <nom> <msg>  - Message privé à un utilisateur");
+    println!("  /announce <texte> - Annonce à tout le serveur (administrateur)");
     println!("  /quit             - Quitter");
     println!("  <message>         - Envoyer un message dans le salon actuel\n");
     
@@ -653,7 +2172,70 @@ fn run_client(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
                 "/rooms" => {
-                    let _ = client.list_rooms();
+                    // "/rooms all" : inclure les salons vides
+                    let only_active = parts.get(1).map(|arg| arg.trim()) != Some("all");
+                    let _ = client.list_rooms(only_active);
+                }
+                "/history" => {
+                    let limit = parts.get(1).and_then(|n| n.trim().parse().ok()).unwrap_or(10);
+                    if let Err(e) = client.get_history(limit) {
+                        println!("Erreur: {}", e);
+                    }
+                }
+                "/reply" => {
+                    let reply = parts.get(1).and_then(|rest| rest.trim().split_once(' '));
+                    match reply {
+                        Some((id, content)) => {
+                            let id = id.trim_start_matches('#').to_string();
+                            if let Err(e) = client.send_reply(content.trim().to_string(), Some(id)) {
+                                println!("Erreur: {}", e);
+                            }
+                        }
+                        None => println!("Usage: /reply <id> <message>"),
+                    }
+                }
+                "/thread" => match parts.get(1) {
+                    Some(id) => {
+                        if let Err(e) = client.get_thread(id.trim().trim_start_matches('#').to_string()) {
+                            println!("Erreur: {}", e);
+                        }
+                    }
+                    None => println!("Usage: /thread <id>"),
+                },
+                "/ping" => {
+                    if let Err(e) = client.ping() {
+                        println!("Erreur: {}", e);
+                    }
+                }
+                "/info" => {
+                    if let Err(e) = client.server_info() {
+                        println!("Erreur: {}", e);
+                    }
+                }
+                "/presence" => {
+                    if let Err(e) = client.subscribe_presence() {
+                        println!("Erreur: {}", e);
+                    }
+                }
+                "/msg" => {
+                    let private = parts.get(1).and_then(|rest| rest.trim().split_once(' '));
+                    match private {
+                        Some((to, content)) => {
+                            if let Err(e) = client.send_private_message(to.to_string(), content.trim().to_string()) {
+                                println!("Erreur: {}", e);
+                            }
+                        }
+                        None => println!("Usage: /msg <utilisateur> <message>"),
+                    }
+                }
+                "/announce" => {
+                    if parts.len() > 1 {
+                        if let Err(e) = client.announce(parts[1].to_string()) {
+                            println!("Erreur: {}", e);
+                        }
+                    } else {
+                        println!("Usage: /announce <message>");
+                    }
                 }
                 "/quit" => {
                     break;
@@ -672,4 +2254,967 @@ fn run_client(addr: &str) -> Result<(), Box<dyn std::error::Error>> {
     
     client.disconnect()?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::mpsc;
+
+    /// Délai d'attente d'un message attendu
+    const RECV_TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn test_server() -> ChatServer {
+        ChatServer::new(
+            DEFAULT_MAX_CONNECTIONS,
+            DEFAULT_MAX_ROOMS,
+            DEFAULT_MAX_ROOMS_PER_USER,
+            None,
+            DEFAULT_WRITE_TIMEOUT,
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    /// Lance le serveur sur un port libre et retourne son adresse
+    fn start_server(server: ChatServer) -> SocketAddr {
+        let (listener, addr) = server.bind("127.0.0.1:0").unwrap();
+        thread::spawn(move || {
+            let _ = server.serve(listener);
+        });
+        addr
+    }
+
+    /// Client brut : envoie et reçoit directement des `MessageType`
+    struct TestClient {
+        stream: TcpStream,
+        codec: Codec,
+        buffer: Vec<u8>,
+    }
+
+    impl TestClient {
+        fn connect(addr: SocketAddr) -> Self {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(Some(RECV_TIMEOUT)).unwrap();
+            TestClient { stream, codec: Codec::Bincode, buffer: Vec::new() }
+        }
+
+        /// Connexion et `Connect` réussi sous ce nom
+        fn login(addr: SocketAddr, username: &str) -> Self {
+            let mut client = Self::connect(addr);
+            let ack = client.connect_as(username, "", None);
+            assert!(matches!(ack, MessageType::ConnectAck { success: true, .. }), "{:?}", ack);
+            client
+        }
+
+        fn connect_as(&mut self, username: &str, password: &str, token: Option<&str>) -> MessageType {
+            self.send(MessageType::Connect {
+                username: username.to_string(),
+                password: password.to_string(),
+                token: token.map(str::to_string),
+                codec: None,
+            });
+            self.recv()
+        }
+
+        fn send(&mut self, message_type: MessageType) {
+            let data = ProtocolMessage::new(message_type).serialize(self.codec).unwrap();
+            self.stream.write_all(&data).unwrap();
+        }
+
+        fn recv(&mut self) -> MessageType {
+            read_frame(&mut self.stream, &mut self.buffer, self.codec).unwrap().message_type
+        }
+
+        /// Premier message reçu qui satisfait `pred`, les autres étant ignorés
+        fn recv_until(&mut self, pred: impl Fn(&MessageType) -> bool) -> MessageType {
+            loop {
+                let message = self.recv();
+                if pred(&message) {
+                    return message;
+                }
+            }
+        }
+
+        /// Aucun message ne satisfaisant `pred` n'arrive pendant `wait`
+        fn assert_no(&mut self, wait: Duration, pred: impl Fn(&MessageType) -> bool) {
+            self.stream.set_read_timeout(Some(wait)).unwrap();
+            while let Ok(message) = read_frame(&mut self.stream, &mut self.buffer, self.codec) {
+                assert!(!pred(&message.message_type), "message inattendu: {:?}", message.message_type);
+            }
+            self.stream.set_read_timeout(Some(RECV_TIMEOUT)).unwrap();
+        }
+
+        /// Rejoint le salon et attend l'accusé
+        fn join(&mut self, room: &str) {
+            self.send(MessageType::JoinRoom { room: room.to_string() });
+            let ack = self.recv_until(|m| matches!(m, MessageType::JoinRoomAck { .. } | MessageType::Error { .. }));
+            assert!(matches!(ack, MessageType::JoinRoomAck { success: true, .. }), "{:?}", ack);
+        }
+
+        /// Envoie un message dans `room` et attend son acquittement
+        fn say(&mut self, room: &str, content: &str, seq: u64) {
+            self.send(MessageType::SendMessage { room: room.to_string(), content: content.to_string(), seq, reply_to: None });
+            self.recv_until(|m| matches!(m, MessageType::MessageAck { seq: acked } if *acked == seq));
+        }
+    }
+
+    /// Contenu du prochain message diffusé
+    fn next_broadcast(client: &mut TestClient) -> String {
+        match client.recv_until(|m| matches!(m, MessageType::MessageBroadcast { .. })) {
+            MessageType::MessageBroadcast { content, .. } => content,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Fichier temporaire propre au test, supprimé s'il existait déjà
+    fn test_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("tp8-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_dir_all(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn absurd_frame_length_is_rejected_without_allocating() {
+        // En-tête annonçant 4 Gio, sans aucune donnée derrière
+        let mut reader = Cursor::new(vec![0xFF, 0xFF, 0xFF, 0xFF]);
+        let mut buffer = Vec::new();
+        for codec in [Codec::Bincode, Codec::MessagePack] {
+            let error = read_frame(&mut reader, &mut buffer, codec).unwrap_err();
+            assert!(error.to_string().contains(&format!("max {}", MAX_MESSAGE_SIZE)));
+            assert_eq!(buffer.capacity(), 0);
+            reader.set_position(0);
+        }
+    }
+
+    #[test]
+    fn message_callback_counts_received_broadcasts() {
+        let addr = start_server(test_server());
+        let (sender, received) = mpsc::channel();
+
+        let mut bot = ChatClient::new();
+        bot.connect(&addr.to_string()).unwrap();
+        assert!(bot.login("bot".to_string()).unwrap());
+        assert!(bot.join_room("general".to_string()).unwrap());
+        let mut count = 0;
+        bot.set_on_message(move |message| {
+            if let MessageType::MessageBroadcast { content, .. } = message {
+                count += 1;
+                sender.send((count, content.clone())).unwrap();
+            }
+        });
+        bot.start_message_listener().unwrap();
+
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("general");
+        alice.say("general", "un", 1);
+        alice.say("general", "deux", 2);
+
+        assert_eq!(received.recv_timeout(RECV_TIMEOUT).unwrap(), (1, "un".to_string()));
+        assert_eq!(received.recv_timeout(RECV_TIMEOUT).unwrap(), (2, "deux".to_string()));
+    }
+
+    #[test]
+    fn history_returns_the_last_messages_in_order() {
+        let addr = start_server(test_server());
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("general");
+        for (seq, content) in ["un", "deux", "trois"].into_iter().enumerate() {
+            alice.say("general", content, seq as u64 + 1);
+        }
+
+        alice.send(MessageType::GetHistory { room: "general".to_string(), limit: 2 });
+        match alice.recv_until(|m| matches!(m, MessageType::History { .. })) {
+            MessageType::History { room, messages } => {
+                assert_eq!(room, "general");
+                let contents: Vec<&str> = messages.iter().map(|entry| entry.content.as_str()).collect();
+                assert_eq!(contents, vec!["deux", "trois"]);
+                assert!(messages.iter().all(|entry| entry.username == "alice"));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn connection_beyond_the_limit_is_refused() {
+        let mut server = test_server();
+        server.max_connections = 2;
+        let active = Arc::clone(&server.active_connections);
+        let addr = start_server(server);
+        let mut first = TestClient::login(addr, "alice");
+        let _second = TestClient::login(addr, "bob");
+
+        let mut third = TestClient::connect(addr);
+        assert_eq!(third.recv(), MessageType::Error { message: "Serveur plein".to_string() });
+
+        // Une place libérée est de nouveau disponible
+        first.send(MessageType::Disconnect);
+        let deadline = Instant::now() + RECV_TIMEOUT;
+        while active.load(Ordering::SeqCst) >= 2 {
+            assert!(Instant::now() < deadline, "la place libérée n'a jamais été rendue");
+            thread::sleep(Duration::from_millis(10));
+        }
+        TestClient::login(addr, "carol");
+    }
+
+    /// Rejoint le salon et retourne le motif du refus attendu
+    fn join_refused(client: &mut TestClient, room: &str) -> String {
+        client.send(MessageType::JoinRoom { room: room.to_string() });
+        match client.recv_until(|m| matches!(m, MessageType::JoinRoomAck { .. } | MessageType::Error { .. })) {
+            MessageType::Error { message } => message,
+            other => panic!("salon {} rejoint malgré la limite: {:?}", room, other),
+        }
+    }
+
+    #[test]
+    fn global_room_cap_refuses_a_new_room_but_not_an_existing_one() {
+        let mut server = test_server();
+        server.max_rooms = 1;
+        let addr = start_server(server);
+        let mut alice = TestClient::login(addr, "alice");
+        let mut bob = TestClient::login(addr, "bob");
+        alice.join("a");
+
+        assert!(join_refused(&mut bob, "b").contains("limite de 1 salons"));
+        bob.join("a");
+        // Changer de salon libère l'ancien s'il se vide : pas de refus
+        alice.join("a");
+    }
+
+    #[test]
+    fn per_user_room_cap_refuses_joining() {
+        let mut server = test_server();
+        server.max_rooms_per_user = 0;
+        let addr = start_server(server);
+        let mut alice = TestClient::login(addr, "alice");
+
+        assert!(join_refused(&mut alice, "a").contains("limite de 0 salons par utilisateur"));
+
+        // Avec une limite d'un salon, en changer reste possible
+        let mut server = test_server();
+        server.max_rooms_per_user = 1;
+        let addr = start_server(server);
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("a");
+        alice.join("b");
+    }
+
+    #[test]
+    fn echo_returns_the_payload_without_login() {
+        let addr = start_server(test_server());
+        let mut client = TestClient::connect(addr);
+        client.send(MessageType::Echo { payload: "diagnostic é 🙂".to_string() });
+        assert_eq!(client.recv(), MessageType::EchoReply { payload: "diagnostic é 🙂".to_string() });
+    }
+
+    #[test]
+    fn several_frames_round_trip_through_one_cursor() {
+        let messages = vec![
+            MessageType::JoinRoom { room: "general".to_string() },
+            MessageType::SendMessage { room: "general".to_string(), content: "salut".to_string(), seq: 1, reply_to: None },
+            MessageType::Disconnect,
+        ];
+        for codec in [Codec::Bincode, Codec::Json, Codec::MessagePack] {
+            let mut bytes = Vec::new();
+            for message in &messages {
+                bytes.extend(ProtocolMessage::new(message.clone()).serialize(codec).unwrap());
+            }
+
+            let mut reader = Cursor::new(bytes);
+            let mut buffer = Vec::new();
+            for message in &messages {
+                assert_eq!(&read_frame(&mut reader, &mut buffer, codec).unwrap().message_type, message);
+            }
+            // Tout a été consommé, sans lire au-delà de chaque trame
+            assert_eq!(reader.position() as usize, reader.get_ref().len());
+            assert!(read_frame(&mut reader, &mut buffer, codec).is_err());
+        }
+    }
+
+    #[test]
+    fn binding_port_zero_reports_the_actual_port() {
+        let server = test_server();
+        let (listener, addr) = server.bind("127.0.0.1:0").unwrap();
+        assert_ne!(addr.port(), 0);
+        assert_eq!(listener.local_addr().unwrap(), addr);
+
+        thread::spawn(move || {
+            let _ = server.serve(listener);
+        });
+        TestClient::login(addr, "alice");
+    }
+
+    #[test]
+    fn resent_seq_is_acked_but_broadcast_once() {
+        let addr = start_server(test_server());
+        let mut alice = TestClient::login(addr, "alice");
+        let mut bob = TestClient::login(addr, "bob");
+        alice.join("general");
+        bob.join("general");
+
+        alice.say("general", "une fois", 7);
+        // Renvoi du même seq (ack perdu) : acquitté de nouveau, jamais rediffusé
+        alice.say("general", "une fois", 7);
+        alice.say("general", "plus ancien", 3);
+
+        assert!(matches!(
+            bob.recv_until(|m| matches!(m, MessageType::MessageBroadcast { .. })),
+            MessageType::MessageBroadcast { content, .. } if content == "une fois"
+        ));
+        bob.assert_no(Duration::from_millis(200), |m| matches!(m, MessageType::MessageBroadcast { .. }));
+    }
+
+    #[test]
+    fn message_outside_the_room_is_refused_without_using_its_seq() {
+        let addr = start_server(test_server());
+        let mut alice = TestClient::login(addr, "alice");
+        let mut bob = TestClient::login(addr, "bob");
+        bob.join("general");
+
+        alice.send(MessageType::SendMessage { room: "general".to_string(), content: "trop tôt".to_string(), seq: 1, reply_to: None });
+        assert_eq!(
+            alice.recv_until(|m| matches!(m, MessageType::Error { .. } | MessageType::MessageAck { .. })),
+            MessageType::Error { message: "Vous n'êtes pas dans le salon general".to_string() }
+        );
+
+        // Le même seq, renvoyé une fois le salon rejoint, est bien diffusé
+        alice.join("general");
+        alice.say("general", "trop tôt", 1);
+        assert_eq!(next_broadcast(&mut bob), "trop tôt");
+    }
+
+    /// Attend que `path` compte au moins `count` lignes, puis les retourne
+    fn wait_for_lines(path: &str, count: usize) -> Vec<String> {
+        let deadline = Instant::now() + RECV_TIMEOUT;
+        loop {
+            let lines: Vec<String> = std::fs::read_to_string(path)
+                .map(|content| content.lines().map(String::from).collect())
+                .unwrap_or_default();
+            if lines.len() >= count || Instant::now() >= deadline {
+                return lines;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn joining_and_leaving_writes_two_audit_entries() {
+        let path = test_path("audit.jsonl");
+        let mut server = test_server();
+        server.audit_path = Some(path.clone());
+        let addr = start_server(server);
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("general");
+        alice.send(MessageType::Disconnect);
+
+        let lines = wait_for_lines(&path, 2);
+        assert_eq!(lines.len(), 2);
+        let events: Vec<serde_json::Value> = lines.iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+        for (event, action) in events.iter().zip(["join", "leave"]) {
+            assert_eq!(event["action"], action);
+            assert_eq!(event["username"], "alice");
+            assert_eq!(event["room"], "general");
+        }
+    }
+
+    #[test]
+    fn client_that_never_reads_does_not_block_broadcasts() {
+        let mut server = test_server();
+        server.write_timeout = Duration::from_millis(200);
+        let addr = start_server(server);
+        let mut stuck = TestClient::login(addr, "lent");
+        stuck.join("general");
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("general");
+        let mut bob = TestClient::login(addr, "bob");
+        bob.join("general");
+
+        // Bien plus que ce que les tampons du socket de `lent` peuvent absorber
+        let content = "x".repeat(512 * 1024);
+        let started = Instant::now();
+        let reader = thread::spawn(move || {
+            let mut received = 0;
+            loop {
+                match bob.recv_until(|m| matches!(m, MessageType::MessageBroadcast { .. } | MessageType::UserLeft { .. })) {
+                    MessageType::MessageBroadcast { .. } => received += 1,
+                    MessageType::UserLeft { username, .. } => {
+                        assert_eq!(username, "lent");
+                        return received;
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        });
+        for seq in 1..=40 {
+            alice.say("general", &content, seq);
+        }
+
+        let received = reader.join().unwrap();
+        assert!(received > 0);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        drop(stuck);
+    }
+
+    #[test]
+    fn json_codec_round_trips_message_variants() {
+        let entry = HistoryEntry {
+            id: "4".to_string(),
+            reply_to: Some("2".to_string()),
+            username: "alice".to_string(),
+            content: "ligne \"citée\"\net saut".to_string(),
+            timestamp: 1_700_000_000,
+        };
+        let messages = vec![
+            MessageType::Connect { username: "alice".to_string(), password: String::new(), token: None, codec: Some(Codec::Json) },
+            MessageType::ListRooms { only_active: false },
+            MessageType::History { room: "general".to_string(), messages: vec![entry] },
+            MessageType::ConnectAck { success: true, message: "Bienvenue".to_string(), codec: Codec::MessagePack },
+            MessageType::Disconnect,
+        ];
+        for message in messages {
+            let bytes = ProtocolMessage::new(message.clone()).serialize(Codec::Json).unwrap();
+            // Une ligne par message : les sauts de ligne du contenu sont échappés
+            assert_eq!(bytes.iter().filter(|&&b| b == b'\n').count(), 1);
+            assert_eq!(bytes.last(), Some(&b'\n'));
+            let decoded = read_frame(&mut Cursor::new(bytes), &mut Vec::new(), Codec::Json).unwrap();
+            assert_eq!(decoded.message_type, message);
+        }
+    }
+
+    #[test]
+    fn json_client_is_detected_by_the_server() {
+        let addr = start_server(test_server());
+        let mut client = TestClient::connect(addr);
+        client.codec = Codec::Json;
+        let ack = client.connect_as("alice", "", None);
+        assert!(matches!(ack, MessageType::ConnectAck { success: true, codec: Codec::Json, .. }), "{:?}", ack);
+        client.send(MessageType::Echo { payload: "json".to_string() });
+        assert_eq!(client.recv(), MessageType::EchoReply { payload: "json".to_string() });
+    }
+
+    #[test]
+    fn presence_subscriber_sees_online_then_offline() {
+        let addr = start_server(test_server());
+        let mut watcher = TestClient::login(addr, "veilleur");
+        let _early = TestClient::login(addr, "alice");
+        watcher.send(MessageType::Subscribe);
+        // Les utilisateurs déjà en ligne sont annoncés à l'abonnement
+        assert_eq!(watcher.recv(), MessageType::UserOnline { username: "alice".to_string() });
+
+        let mut bob = TestClient::login(addr, "bob");
+        // L'événement en direct d'alice peut encore arriver après la liste initiale
+        let online = watcher.recv_until(|m| *m != MessageType::UserOnline { username: "alice".to_string() });
+        assert_eq!(online, MessageType::UserOnline { username: "bob".to_string() });
+        bob.send(MessageType::Disconnect);
+        assert_eq!(watcher.recv(), MessageType::UserOffline { username: "bob".to_string() });
+    }
+
+    fn room_list(client: &mut TestClient, only_active: bool) -> Vec<String> {
+        client.send(MessageType::ListRooms { only_active });
+        match client.recv_until(|m| matches!(m, MessageType::RoomList { .. })) {
+            MessageType::RoomList { rooms } => rooms,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn room_listing_can_skip_empty_rooms() {
+        // Avec l'archivage, un salon vidé garde son historique jusqu'à l'archivage
+        let mut server = test_server();
+        server.set_archive(Some(ArchiveConfig { dir: test_path("archives-list"), idle: Duration::from_secs(3600) }));
+        let addr = start_server(server);
+        let mut alice = TestClient::login(addr, "alice");
+        let mut bob = TestClient::login(addr, "bob");
+        bob.join("vide");
+        bob.say("vide", "écho", 1);
+        alice.join("general");
+        assert_eq!(room_list(&mut alice, true), vec!["general", "vide"]);
+
+        bob.join("general");
+        assert_eq!(room_list(&mut alice, true), vec!["general"]);
+        assert_eq!(room_list(&mut alice, false), vec!["general", "vide"]);
+    }
+
+    /// Inscrit directement `username` dans `room`, sans thread de lecture côté
+    /// serveur ; renvoie l'autre extrémité de sa connexion
+    fn attach(server: &ChatServer, username: &str, room: &str) -> TestClient {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TestClient::connect(listener.local_addr().unwrap());
+        let (stream, _) = listener.accept().unwrap();
+        server.users.lock().unwrap().insert(username.to_string(), User {
+            username: username.to_string(),
+            current_room: Some(room.to_string()),
+        });
+        server.rooms.lock().unwrap().entry(room.to_string()).or_default().push(username.to_string());
+        server.connections.lock().unwrap().insert(username.to_string(), Connection { stream, codec: Codec::Bincode });
+        client
+    }
+
+    #[test]
+    fn closed_recipient_is_removed_from_the_room() {
+        let server = test_server();
+        let mut alice = attach(&server, "alice", "general");
+        // carol ne lit pas et aucun gestionnaire ne la surveille : seule la
+        // diffusion peut constater que sa connexion est fermée
+        let _carol = attach(&server, "carol", "general");
+        server.connections.lock().unwrap()["carol"].stream.shutdown(std::net::Shutdown::Both).unwrap();
+
+        server.broadcast_to_room("general", MessageType::System { content: "quelqu'un ?".to_string() }, None);
+
+        assert_eq!(server.rooms.lock().unwrap()["general"], vec!["alice".to_string()]);
+        assert!(!server.connections.lock().unwrap().contains_key("carol"));
+        assert!(!server.users.lock().unwrap().contains_key("carol"));
+        assert_eq!(alice.recv_until(|m| matches!(m, MessageType::System { .. })), MessageType::System { content: "quelqu'un ?".to_string() });
+        assert_eq!(
+            alice.recv_until(|m| matches!(m, MessageType::UserLeft { .. })),
+            MessageType::UserLeft { room: "general".to_string(), username: "carol".to_string() }
+        );
+    }
+
+    #[test]
+    fn admin_announcement_reaches_every_room_and_others_are_refused() {
+        let mut server = test_server();
+        server.admin = Some("admin".to_string());
+        let addr = start_server(server);
+        let mut admin = TestClient::login(addr, "admin");
+        let mut alice = TestClient::login(addr, "alice");
+        let mut bob = TestClient::login(addr, "bob");
+        alice.join("a");
+        bob.join("b");
+
+        bob.send(MessageType::Announce { content: "faux".to_string() });
+        assert_eq!(
+            bob.recv_until(|m| matches!(m, MessageType::Error { .. })),
+            MessageType::Error { message: "Annonce réservée à l'administrateur".to_string() }
+        );
+
+        admin.send(MessageType::Announce { content: "maintenance à 18h".to_string() });
+        let expected = MessageType::System { content: format!("{} maintenance à 18h", ANNOUNCE_MARKER) };
+        for client in [&mut alice, &mut bob, &mut admin] {
+            assert_eq!(client.recv_until(|m| matches!(m, MessageType::System { .. })), expected);
+        }
+    }
+
+    #[test]
+    fn empty_idle_room_is_archived() {
+        let dir = test_path("archives");
+        let mut server = test_server();
+        server.set_archive(Some(ArchiveConfig { dir: dir.clone(), idle: Duration::from_millis(100) }));
+        let addr = start_server(server);
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("vieux");
+        alice.say("vieux", "dernier message", 1);
+        alice.join("actif");
+
+        // L'historique quitte la mémoire une fois l'archive écrite, verrous tenus
+        let deadline = Instant::now() + RECV_TIMEOUT;
+        while room_list(&mut alice, false) != vec!["actif"] {
+            assert!(Instant::now() < deadline, "salon jamais archivé");
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().map(|e| e.path()).collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].file_name().unwrap().to_string_lossy().starts_with("vieux-"));
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        let entry: HistoryEntry = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(entry.content, "dernier message");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn server_info_reports_version_and_uptime() {
+        let server = test_server();
+        let started_at = server.started_at;
+        let addr = start_server(server);
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("general");
+        thread::sleep(Duration::from_millis(50));
+
+        alice.send(MessageType::ServerInfo);
+        match alice.recv_until(|m| matches!(m, MessageType::ServerInfoReply { .. })) {
+            MessageType::ServerInfoReply { version, uptime_secs, users, rooms } => {
+                assert_eq!(version, env!("CARGO_PKG_VERSION"));
+                assert!(uptime_secs <= started_at.elapsed().as_secs());
+                assert_eq!((users, rooms), (1, 1));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn simultaneous_connects_with_the_same_name_admit_exactly_one() {
+        let addr = start_server(test_server());
+        for round in 0..20 {
+            let username = format!("double{}", round);
+            let barrier = Arc::new(std::sync::Barrier::new(2));
+            let attempts: Vec<_> = (0..2)
+                .map(|_| {
+                    let barrier = Arc::clone(&barrier);
+                    let username = username.clone();
+                    thread::spawn(move || {
+                        let mut client = TestClient::connect(addr);
+                        barrier.wait();
+                        let ack = client.connect_as(&username, "", None);
+                        // Garder la connexion ouverte jusqu'au décompte
+                        (matches!(ack, MessageType::ConnectAck { success: true, .. }), client)
+                    })
+                })
+                .collect();
+            let results: Vec<(bool, TestClient)> = attempts.into_iter().map(|t| t.join().unwrap()).collect();
+            assert_eq!(results.iter().filter(|(success, _)| *success).count(), 1, "tour {}", round);
+        }
+    }
+
+    #[test]
+    fn connect_requires_the_configured_token() {
+        let mut server = test_server();
+        server.set_token(Some("s3cret".to_string()));
+        let addr = start_server(server);
+
+        for (username, token) in [("alice", None), ("bob", Some("mauvais"))] {
+            let mut client = TestClient::connect(addr);
+            match client.connect_as(username, "", token) {
+                MessageType::ConnectAck { success, message, .. } => {
+                    assert!(!success);
+                    assert_eq!(message, "bad token");
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+
+        let mut client = TestClient::connect(addr);
+        let ack = client.connect_as("alice", "", Some("s3cret"));
+        assert!(matches!(ack, MessageType::ConnectAck { success: true, .. }), "{:?}", ack);
+    }
+
+    #[test]
+    fn transformers_apply_in_order_before_broadcast() {
+        let mut server = test_server();
+        server.set_transformers(parse_transformers("trim, max=10").unwrap());
+        let addr = start_server(server);
+        let mut alice = TestClient::login(addr, "alice");
+        let mut bob = TestClient::login(addr, "bob");
+        alice.join("general");
+        bob.join("general");
+
+        // Tronqué après le trim : les espaces de tête ne comptent pas
+        alice.say("general", "   salut tout le monde   ", 1);
+        assert_eq!(next_broadcast(&mut bob), "salut tout");
+
+        // Dans l'autre ordre, la troncature garde les espaces puis le trim les retire
+        let reversed = parse_transformers("max=10,trim").unwrap();
+        let content = reversed.iter().fold("   salut tout le monde".to_string(), |c, t| t.transform(&c));
+        assert_eq!(content, "salut t");
+        assert!(parse_transformers("inconnu").is_err());
+    }
+
+    /// Un exemple de chaque variante du protocole
+    fn all_variants() -> Vec<MessageType> {
+        let entry = HistoryEntry {
+            id: "1".to_string(),
+            reply_to: None,
+            username: "alice".to_string(),
+            content: "salut".to_string(),
+            timestamp: 1_700_000_000,
+        };
+        let room = || "general".to_string();
+        let user = || "alice".to_string();
+        vec![
+            MessageType::Connect { username: user(), password: "mdp".to_string(), token: Some("jeton".to_string()), codec: Some(Codec::MessagePack) },
+            MessageType::JoinRoom { room: room() },
+            MessageType::SendMessage { room: room(), content: "salut".to_string(), seq: 3, reply_to: Some("1".to_string()) },
+            MessageType::ListRooms { only_active: true },
+            MessageType::ListUsers { room: room() },
+            MessageType::GetHistory { room: room(), limit: 10 },
+            MessageType::GetThread { root_id: "1".to_string() },
+            MessageType::PrivateMessage { to: "bob".to_string(), content: "psst".to_string() },
+            MessageType::Echo { payload: "ping".to_string() },
+            MessageType::Subscribe,
+            MessageType::Announce { content: "annonce".to_string() },
+            MessageType::ServerInfo,
+            MessageType::Disconnect,
+            MessageType::ConnectAck { success: true, message: "ok".to_string(), codec: Codec::Json },
+            MessageType::JoinRoomAck { success: false, room: room(), message: "non".to_string() },
+            MessageType::MessageBroadcast { id: "2".to_string(), room: room(), username: user(), content: "🙂".to_string(), timestamp: 5, reply_to: None },
+            MessageType::RoomList { rooms: vec![room(), "autre".to_string()] },
+            MessageType::UserList { room: room(), users: vec![user()] },
+            MessageType::Error { message: "erreur".to_string() },
+            MessageType::UserJoined { room: room(), username: user() },
+            MessageType::UserLeft { room: room(), username: user() },
+            MessageType::History { room: room(), messages: vec![entry.clone()] },
+            MessageType::Thread { room: room(), root_id: "1".to_string(), messages: vec![entry] },
+            MessageType::PrivateMessageDelivered { from: user(), content: "psst".to_string(), timestamp: 6 },
+            MessageType::EchoReply { payload: "ping".to_string() },
+            MessageType::MessageAck { seq: 3 },
+            MessageType::UserOnline { username: user() },
+            MessageType::UserOffline { username: user() },
+            MessageType::System { content: "info".to_string() },
+            MessageType::ServerInfoReply { version: "0.1.0".to_string(), uptime_secs: 7, users: 2, rooms: 1 },
+        ]
+    }
+
+    #[test]
+    fn every_codec_round_trips_every_variant() {
+        for codec in [Codec::Bincode, Codec::Json, Codec::MessagePack] {
+            for message in all_variants() {
+                let original = ProtocolMessage::new(message);
+                let bytes = original.serialize(codec).unwrap();
+                let decoded = read_frame(&mut Cursor::new(bytes), &mut Vec::new(), codec).unwrap();
+                assert_eq!(decoded.message_type, original.message_type, "{:?}", codec);
+                assert_eq!(decoded.timestamp, original.timestamp);
+            }
+        }
+    }
+
+    #[test]
+    fn msgpack_is_negotiated_after_a_bincode_handshake() {
+        let addr = start_server(test_server());
+        let mut client = TestClient::connect(addr);
+        client.send(MessageType::Connect { username: "alice".to_string(), password: String::new(), token: None, codec: Some(Codec::MessagePack) });
+        let ack = client.recv();
+        assert!(matches!(ack, MessageType::ConnectAck { success: true, codec: Codec::MessagePack, .. }), "{:?}", ack);
+
+        client.codec = Codec::MessagePack;
+        client.send(MessageType::Echo { payload: "msgpack".to_string() });
+        assert_eq!(client.recv(), MessageType::EchoReply { payload: "msgpack".to_string() });
+    }
+
+    #[test]
+    fn thread_returns_root_and_replies_in_order() {
+        let addr = start_server(test_server());
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("general");
+        let mut bob = TestClient::login(addr, "bob");
+        bob.join("general");
+
+        alice.send(MessageType::SendMessage { room: "general".to_string(), content: "question".to_string(), seq: 1, reply_to: None });
+        let root_id = match bob.recv_until(|m| matches!(m, MessageType::MessageBroadcast { .. })) {
+            MessageType::MessageBroadcast { id, .. } => id,
+            _ => unreachable!(),
+        };
+        alice.say("general", "sans rapport", 2);
+        bob.send(MessageType::SendMessage { room: "general".to_string(), content: "réponse".to_string(), seq: 1, reply_to: Some(root_id.clone()) });
+        bob.recv_until(|m| matches!(m, MessageType::MessageAck { .. }));
+
+        bob.send(MessageType::GetThread { root_id: root_id.clone() });
+        match bob.recv_until(|m| matches!(m, MessageType::Thread { .. })) {
+            MessageType::Thread { room, root_id: thread_root, messages } => {
+                assert_eq!((room.as_str(), thread_root.as_str()), ("general", root_id.as_str()));
+                let contents: Vec<&str> = messages.iter().map(|entry| entry.content.as_str()).collect();
+                assert_eq!(contents, vec!["question", "réponse"]);
+                assert_eq!(messages[1].reply_to.as_deref(), Some(root_id.as_str()));
+            }
+            _ => unreachable!(),
+        }
+
+        // Réponse à un message inconnu : refusée
+        bob.send(MessageType::SendMessage { room: "general".to_string(), content: "?".to_string(), seq: 2, reply_to: Some("999".to_string()) });
+        assert!(matches!(bob.recv_until(|m| matches!(m, MessageType::Error { .. })), MessageType::Error { message } if message.contains("999")));
+    }
+
+    #[test]
+    fn snapshot_restores_rooms_and_history_in_a_fresh_server() {
+        let path = test_path("snapshot.json");
+        let server = test_server();
+        let addr = start_server(server.clone());
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("general");
+        alice.say("general", "un", 1);
+        alice.say("general", "deux", 2);
+        let mut carol = TestClient::login(addr, "carol");
+        carol.join("calme");
+        server.save_snapshot(&path).unwrap();
+
+        let restored = ChatServer::new(
+            DEFAULT_MAX_CONNECTIONS,
+            DEFAULT_MAX_ROOMS,
+            DEFAULT_MAX_ROOMS_PER_USER,
+            None,
+            DEFAULT_WRITE_TIMEOUT,
+            None,
+            Some(&path),
+        )
+        .unwrap();
+        let addr = start_server(restored);
+        let mut bob = TestClient::login(addr, "bob");
+        let mut rooms = room_list(&mut bob, false);
+        rooms.sort();
+        assert_eq!(rooms, vec!["calme", "general"]);
+
+        bob.join("general");
+        let old_ids = match bob.recv_until(|m| matches!(m, MessageType::History { .. })) {
+            MessageType::History { messages, .. } => {
+                let contents: Vec<&str> = messages.iter().map(|entry| entry.content.as_str()).collect();
+                assert_eq!(contents, vec!["un", "deux"]);
+                messages.into_iter().map(|entry| entry.id).collect::<Vec<_>>()
+            }
+            _ => unreachable!(),
+        };
+
+        // Les identifiants reprennent après ceux de la sauvegarde
+        bob.send(MessageType::SendMessage { room: "general".to_string(), content: "trois".to_string(), seq: 1, reply_to: None });
+        match bob.recv_until(|m| matches!(m, MessageType::MessageBroadcast { .. })) {
+            MessageType::MessageBroadcast { id, .. } => assert!(!old_ids.contains(&id)),
+            _ => unreachable!(),
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn restored_room_idle_for_too_long_is_archived_at_startup() {
+        let path = test_path("snapshot-ancien.json");
+        let dir = test_path("archives-snapshot");
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let entry = |id: &str, timestamp| HistoryEntry {
+            id: id.to_string(),
+            reply_to: None,
+            username: "alice".to_string(),
+            content: format!("message {}", id),
+            timestamp,
+        };
+        let snapshot = Snapshot {
+            rooms: HashMap::from([
+                ("ancien".to_string(), vec![entry("1", now - 7200)]),
+                ("recent".to_string(), vec![entry("2", now)]),
+            ]),
+            next_message_id: 3,
+        };
+        std::fs::write(&path, serde_json::to_vec(&snapshot).unwrap()).unwrap();
+
+        let mut server = ChatServer::new(
+            DEFAULT_MAX_CONNECTIONS,
+            DEFAULT_MAX_ROOMS,
+            DEFAULT_MAX_ROOMS_PER_USER,
+            None,
+            DEFAULT_WRITE_TIMEOUT,
+            None,
+            Some(&path),
+        )
+        .unwrap();
+        server.set_archive(Some(ArchiveConfig { dir: dir.clone(), idle: Duration::from_secs(3600) }));
+        let addr = start_server(server);
+        let mut bob = TestClient::login(addr, "bob");
+
+        // Inactif depuis deux heures pour une limite d'une heure : archivé sans attendre
+        let deadline = Instant::now() + RECV_TIMEOUT;
+        while room_list(&mut bob, false) != vec!["recent"] {
+            assert!(Instant::now() < deadline, "salon restauré jamais archivé");
+            thread::sleep(Duration::from_millis(20));
+        }
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().map(|e| e.path()).collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].file_name().unwrap().to_string_lossy().starts_with("ancien-"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn private_message_reaches_only_its_recipient() {
+        let addr = start_server(test_server());
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("general");
+        let mut bob = TestClient::login(addr, "bob");
+        bob.join("general");
+        let mut carol = TestClient::login(addr, "carol");
+        carol.join("general");
+
+        alice.send(MessageType::PrivateMessage { to: "bob".to_string(), content: "psst".to_string() });
+        match bob.recv_until(|m| matches!(m, MessageType::PrivateMessageDelivered { .. })) {
+            MessageType::PrivateMessageDelivered { from, content, .. } => assert_eq!((from.as_str(), content.as_str()), ("alice", "psst")),
+            _ => unreachable!(),
+        }
+        carol.assert_no(Duration::from_millis(200), |m| {
+            matches!(m, MessageType::PrivateMessageDelivered { .. } | MessageType::MessageBroadcast { .. })
+        });
+
+        alice.send(MessageType::PrivateMessage { to: "dave".to_string(), content: "?".to_string() });
+        assert!(matches!(alice.recv_until(|m| matches!(m, MessageType::Error { .. })), MessageType::Error { message } if message.contains("dave")));
+        // À soi-même : refusé, rien n'est livré
+        alice.send(MessageType::PrivateMessage { to: "alice".to_string(), content: "moi".to_string() });
+        assert!(matches!(alice.recv_until(|m| matches!(m, MessageType::Error { .. })), MessageType::Error { .. }));
+        alice.assert_no(Duration::from_millis(100), |m| matches!(m, MessageType::PrivateMessageDelivered { .. }));
+    }
+
+    #[test]
+    fn joining_replays_the_bounded_history_until_the_room_empties() {
+        let addr = start_server(test_server());
+        let mut alice = TestClient::login(addr, "alice");
+        alice.join("general");
+        for seq in 0..HISTORY_SIZE as u64 + 2 {
+            alice.say("general", &seq.to_string(), seq + 1);
+        }
+
+        let mut bob = TestClient::login(addr, "bob");
+        bob.join("general");
+        match bob.recv() {
+            MessageType::History { room, messages } => {
+                assert_eq!(room, "general");
+                assert_eq!(messages.len(), HISTORY_SIZE);
+                assert_eq!(messages.first().unwrap().content, "2");
+                assert_eq!(messages.last().unwrap().content, (HISTORY_SIZE + 1).to_string());
+            }
+            other => panic!("historique attendu juste après l'accusé, reçu {:?}", other),
+        }
+
+        // Salon vidé : l'historique est oublié
+        alice.join("ailleurs");
+        bob.join("ailleurs");
+        bob.join("general");
+        bob.assert_no(Duration::from_millis(200), |m| matches!(m, MessageType::History { .. }));
+    }
+
+    #[test]
+    fn script_runs_its_directives_against_the_server() {
+        let addr = start_server(test_server());
+        let mut bob = TestClient::login(addr, "bob");
+        bob.join("dev");
+
+        let script = "# démo\nlogin robot\njoin dev\nmsg bonjour\nsleep 10\nmsg au revoir\nquit\nmsg jamais envoyé\n";
+        let commands = parse_script(script).unwrap();
+        assert_eq!(commands.len(), 7);
+        let mut client = ChatClient::new();
+        client.connect(&addr.to_string()).unwrap();
+        run_script(&mut client, commands).unwrap();
+
+        assert_eq!(next_broadcast(&mut bob), "bonjour");
+        assert_eq!(next_broadcast(&mut bob), "au revoir");
+        bob.assert_no(Duration::from_millis(200), |m| matches!(m, MessageType::MessageBroadcast { .. }));
+
+        assert!(parse_script("login robot\ndanse\n").unwrap_err().contains("ligne 2"));
+        assert!(parse_script("join dev\n").is_err());
+    }
+
+    #[test]
+    fn passwords_register_new_users_and_reject_wrong_ones() {
+        let path = test_path("users.txt");
+        let mut server = test_server();
+        // Fichier absent : aucun compte, il sera créé au premier enregistrement
+        assert_eq!(server.set_accounts(&path).unwrap(), 0);
+        let addr = start_server(server);
+
+        let mut alice = TestClient::connect(addr);
+        assert!(matches!(alice.connect_as("alice", "secret", None), MessageType::ConnectAck { success: true, .. }));
+        drop(alice);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("alice:") && !content.contains("secret"), "{}", content);
+
+        let mut intruder = TestClient::connect(addr);
+        match intruder.connect_as("alice", "faux", None) {
+            MessageType::ConnectAck { success, message, .. } => {
+                assert!(!success);
+                assert!(message.contains("Mot de passe incorrect"), "{}", message);
+            }
+            other => panic!("{:?}", other),
+        }
+        let mut nobody = TestClient::connect(addr);
+        assert!(matches!(nobody.connect_as("bob", "", None), MessageType::ConnectAck { success: false, .. }));
+
+        // Un nouveau serveur relit le compte enregistré
+        assert_eq!(Accounts::load(&path).unwrap().len(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+}
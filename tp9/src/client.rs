@@ -1,4 +1,8 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs;
 use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 use futures_util::{SinkExt, StreamExt};
 use serde_json::json;
@@ -13,8 +17,76 @@ struct Args {
     url: String,
     
     /// Nom d'utilisateur
-    #[arg(short, long, default_value = "Anonymous")]
+    #[arg(short = 'n', long, default_value = "Anonymous")]
     username: String,
+
+    /// Spectateur : suivre le salon sans pouvoir y écrire
+    #[arg(long)]
+    readonly: bool,
+}
+
+/// Fichier des utilisateurs masqués (un nom par ligne), dans le dossier personnel
+const MUTE_FILE: &str = ".tp9_mutes";
+
+fn mute_file_path() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(MUTE_FILE)
+}
+
+// Fichier absent ou illisible : aucun utilisateur masqué
+fn load_mutes() -> HashSet<String> {
+    fs::read_to_string(mute_file_path())
+        .map(|content| content.lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn save_mutes(mutes: &HashSet<String>) -> io::Result<()> {
+    let mut names: Vec<&String> = mutes.iter().collect();
+    names.sort();
+    let content: String = names.iter().map(|name| format!("{}\n", name)).collect();
+    fs::write(mute_file_path(), content)
+}
+
+/// Message d'un utilisateur masqué ; les messages système le concernant
+/// (arrivée, départ) restent affichés
+fn is_muted(message: &serde_json::Value, mutes: &HashSet<String>) -> bool {
+    message
+        .get("username")
+        .and_then(|v| v.as_str())
+        .is_some_and(|username| mutes.contains(username))
+}
+
+/// Nombre d'identifiants de messages retenus pour écarter les doublons
+const SEEN_IDS_CAPACITY: usize = 1000;
+
+/// Identifiants des messages déjà affichés ; au-delà de la capacité, les plus
+/// anciens sont oubliés
+struct SeenIds {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl SeenIds {
+    fn new(capacity: usize) -> Self {
+        Self { ids: HashSet::new(), order: VecDeque::new(), capacity }
+    }
+
+    /// `false` si l'identifiant a déjà été vu
+    fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+        if self.order.len() >= self.capacity
+            && let Some(oldest) = self.order.pop_front() {
+            self.ids.remove(&oldest);
+        }
+        self.ids.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        true
+    }
 }
 
 #[tokio::main]
@@ -27,22 +99,124 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (ws_stream, _) = connect_async(&args.url).await?;
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
     
-    println!("Connexion établie! Tapez vos messages (tapez '/quit' pour quitter)");
+    println!("Connexion établie! Tapez vos messages (tapez '/quit' pour quitter, '/switch <salon>' pour changer de salon, '/rooms' pour les lister, '/users' pour les présents, '/history [n]' pour les derniers messages, '/away [note]' ou '/back' pour votre statut, '/react <emoji>' pour réagir au dernier message, '/mute <nom>' ou '/unmute <nom>' pour masquer un utilisateur)");
     
     // Envoyer le message de connexion
     let join_message = json!({
         "type": "join",
-        "username": args.username
+        "username": args.username,
+        "readonly": args.readonly
     });
     
     ws_sender.send(Message::Text(join_message.to_string())).await?;
     
+    // Salon courant, affiché dans l'invite
+    let current_room = Arc::new(Mutex::new("general".to_string()));
+    let room_for_receiver = Arc::clone(&current_room);
+    // Utilisateurs masqués localement (/mute), conservés d'une session à l'autre
+    let mutes = Arc::new(Mutex::new(load_mutes()));
+    let mutes_for_receiver = Arc::clone(&mutes);
+    // Dernier message de chat affiché, cible de /react
+    let last_message_id: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    let last_id_for_receiver = Arc::clone(&last_message_id);
+
     // Tâche pour lire les messages du serveur
     let receive_task = tokio::spawn(async move {
+        // Historique rejoué (changement de salon) : ne pas réafficher un message
+        let mut seen = SeenIds::new(SEEN_IDS_CAPACITY);
         while let Some(msg) = ws_receiver.next().await {
             match msg {
                 Ok(Message::Text(text)) => {
                     if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&text) {
+                        // Réponse à /rooms
+                        if parsed.get("type").and_then(|v| v.as_str()) == Some("roomlist") {
+                            println!("\rSalons actifs:");
+                            let rooms = parsed.get("rooms").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+                            for room in rooms {
+                                let name = room.get("name").and_then(|v| v.as_str()).unwrap_or("?");
+                                let users = room.get("users").and_then(|v| v.as_u64()).unwrap_or(0);
+                                match room.get("spectators").and_then(|v| v.as_u64()).unwrap_or(0) {
+                                    0 => println!("  - {} ({} utilisateur(s))", name, users),
+                                    spectators => println!("  - {} ({} utilisateur(s), {} spectateur(s))", name, users, spectators),
+                                }
+                            }
+                            print!("[{}]> ", room_for_receiver.lock().unwrap());
+                            io::stdout().flush().unwrap();
+                            continue;
+                        }
+
+                        // Réponse à /users
+                        if parsed.get("type").and_then(|v| v.as_str()) == Some("userlist") {
+                            let room = parsed.get("room").and_then(|v| v.as_str()).unwrap_or("?");
+                            println!("\rUtilisateurs de {}:", room);
+                            let users = parsed.get("users").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+                            for user in users {
+                                let name = user.get("username").and_then(|v| v.as_str()).unwrap_or("?");
+                                match user.get("away").and_then(|v| v.as_str()) {
+                                    Some("") => println!("  - {} (absent)", name),
+                                    Some(note) => println!("  - {} (absent : {})", name, note),
+                                    None => println!("  - {}", name),
+                                }
+                            }
+                            print!("[{}]> ", room_for_receiver.lock().unwrap());
+                            io::stdout().flush().unwrap();
+                            continue;
+                        }
+
+                        // Réponse à /history : affichée même si déjà vue
+                        if parsed.get("type").and_then(|v| v.as_str()) == Some("history") {
+                            let room = parsed.get("room").and_then(|v| v.as_str()).unwrap_or("?");
+                            let messages = parsed.get("messages").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+                            println!("\rHistorique de {} ({} message(s)):", room, messages.len());
+                            let mutes = mutes_for_receiver.lock().unwrap();
+                            for message in messages.iter().filter(|message| !is_muted(message, &mutes)) {
+                                let username = message.get("username").and_then(|v| v.as_str()).unwrap_or("Inconnu");
+                                let content = message.get("content").and_then(|v| v.as_str()).unwrap_or("");
+                                if message.get("message_type").and_then(|v| v.as_str()) == Some("Action") {
+                                    println!("  * {} {}", username, content);
+                                } else {
+                                    println!("  {}: {}", username, content);
+                                }
+                            }
+                            print!("[{}]> ", room_for_receiver.lock().unwrap());
+                            io::stdout().flush().unwrap();
+                            continue;
+                        }
+
+                        // Nouvelle réaction, ou totaux après la relecture de l'historique
+                        match parsed.get("type").and_then(|v| v.as_str()) {
+                            Some("reaction") => {
+                                let username = parsed.get("username").and_then(|v| v.as_str()).unwrap_or("?");
+                                let emoji = parsed.get("emoji").and_then(|v| v.as_str()).unwrap_or("?");
+                                let count = parsed.get("count").and_then(|v| v.as_u64()).unwrap_or(0);
+                                println!("\r  ↳ {} a réagi {} ({})", username, emoji, count);
+                                print!("[{}]> ", room_for_receiver.lock().unwrap());
+                                io::stdout().flush().unwrap();
+                                continue;
+                            }
+                            Some("reactions") => {
+                                let totals = parsed.get("totals").and_then(|v| v.as_object()).cloned().unwrap_or_default();
+                                let summary: Vec<String> = totals
+                                    .iter()
+                                    .map(|(emoji, count)| format!("{} {}", emoji, count.as_u64().unwrap_or(0)))
+                                    .collect();
+                                println!("\r  ↳ {}", summary.join("  "));
+                                print!("[{}]> ", room_for_receiver.lock().unwrap());
+                                io::stdout().flush().unwrap();
+                                continue;
+                            }
+                            _ => {}
+                        }
+
+                        if let Some(id) = parsed.get("id").and_then(|v| v.as_str())
+                            && !seen.insert(id) {
+                            continue;
+                        }
+
+                        if is_muted(&parsed, &mutes_for_receiver.lock().unwrap()) {
+                            continue;
+                        }
+
                         let username = parsed.get("username").and_then(|v| v.as_str()).unwrap_or("Inconnu");
                         let content = parsed.get("content").and_then(|v| v.as_str()).unwrap_or("");
                         let timestamp = parsed.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
@@ -51,13 +225,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         let datetime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(timestamp);
                         let formatted_time = format!("{:?}", datetime); // Simplification pour l'exemple
                         
-                        println!("\r[{}] {}: {}", formatted_time, username, content);
-                        print!("> ");
+                        let message_type = parsed.get("message_type").and_then(|v| v.as_str());
+                        if matches!(message_type, Some("Text" | "Action")) {
+                            *last_id_for_receiver.lock().unwrap() =
+                                parsed.get("id").and_then(|v| v.as_str()).map(String::from);
+                        }
+                        let is_action = message_type == Some("Action");
+                        if is_action {
+                            println!("\r[{}] * {} {}", formatted_time, username, content);
+                        } else {
+                            println!("\r[{}] {}: {}", formatted_time, username, content);
+                        }
+                        print!("[{}]> ", room_for_receiver.lock().unwrap());
                         io::stdout().flush().unwrap();
                     }
                 }
-                Ok(Message::Close(_)) => {
-                    println!("\nConnexion fermée par le serveur");
+                Ok(Message::Close(frame)) => {
+                    match frame {
+                        Some(frame) if !frame.reason.is_empty() => {
+                            println!("\nConnexion fermée par le serveur : {}", frame.reason);
+                        }
+                        _ => println!("\nConnexion fermée par le serveur"),
+                    }
                     break;
                 }
                 Err(e) => {
@@ -75,11 +264,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let mut input = String::new();
         
         loop {
-            print!("> ");
+            print!("[{}]> ", current_room.lock().unwrap());
             io::stdout().flush().unwrap();
             
             input.clear();
-            if stdin.read_line(&mut input).is_err() {
+            // Ok(0) : fin de l'entrée standard
+            if !matches!(stdin.read_line(&mut input), Ok(n) if n > 0) {
                 break;
             }
             
@@ -90,11 +280,92 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 break;
             }
             
+            // /mute <nom> et /unmute <nom> : filtrage local, rien n'est envoyé
+            let (command, target) = message.split_once(' ').unwrap_or((message, ""));
+            if command == "/mute" || command == "/unmute" {
+                let target = target.trim().to_string();
+                if target.is_empty() {
+                    println!("Usage: {} <nom>", command);
+                    continue;
+                }
+                let mut mutes = mutes.lock().unwrap();
+                let changed = if command == "/mute" {
+                    mutes.insert(target.clone())
+                } else {
+                    mutes.remove(&target)
+                };
+                match (command, changed) {
+                    ("/mute", true) => println!("Messages de {} masqués", target),
+                    ("/mute", false) => println!("{} est déjà masqué", target),
+                    (_, true) => println!("Messages de {} de nouveau affichés", target),
+                    (_, false) => println!("{} n'était pas masqué", target),
+                }
+                if changed && let Err(e) = save_mutes(&mutes) {
+                    eprintln!("Liste des masqués non enregistrée: {}", e);
+                }
+                continue;
+            }
+            
             if !message.is_empty() {
-                let chat_message = json!({
-                    "type": "message",
-                    "content": message
-                });
+                // /me <action> : message d'action style IRC
+                let chat_message = if let Some(action) = message.strip_prefix("/me ") {
+                    json!({
+                        "type": "action",
+                        "content": action.trim()
+                    })
+                } else if message == "/rooms" {
+                    json!({ "type": "rooms" })
+                } else if message == "/users" {
+                    json!({ "type": "users" })
+                } else if message == "/history" || message.starts_with("/history ") {
+                    // /history [n] : les n derniers messages du salon (tous ceux gardés sans n)
+                    match message["/history".len()..].trim() {
+                        "" => json!({ "type": "history" }),
+                        n => match n.parse::<u64>() {
+                            Ok(limit) => json!({ "type": "history", "limit": limit }),
+                            Err(_) => {
+                                println!("Usage: /history [n]");
+                                continue;
+                            }
+                        },
+                    }
+                } else if message == "/away" || message.starts_with("/away ") {
+                    // /away [note] : absent, /back : de nouveau disponible
+                    json!({
+                        "type": "status",
+                        "state": "away",
+                        "note": message["/away".len()..].trim()
+                    })
+                } else if let Some(emoji) = message.strip_prefix("/react ") {
+                    let Some(message_id) = last_message_id.lock().unwrap().clone() else {
+                        println!("Aucun message auquel réagir");
+                        continue;
+                    };
+                    json!({
+                        "type": "react",
+                        "message_id": message_id,
+                        "emoji": emoji.trim()
+                    })
+                } else if message == "/back" {
+                    json!({ "type": "status", "state": "available" })
+                } else if let Some(room) = message.strip_prefix("/switch ") {
+                    // /switch <salon> : changer de salon, l'historique est rejoué
+                    let room = room.trim();
+                    *current_room.lock().unwrap() = room.to_string();
+                    json!({
+                        "type": "switch",
+                        "room": room
+                    })
+                } else if message.starts_with('/') {
+                    // Commande inconnue ou incomplète : jamais envoyée comme message
+                    println!("Commande inconnue: {}", message);
+                    continue;
+                } else {
+                    json!({
+                        "type": "message",
+                        "content": message
+                    })
+                };
                 
                 if let Err(e) = ws_sender.send(Message::Text(chat_message.to_string())).await {
                     eprintln!("Erreur lors de l'envoi: {}", e);
@@ -112,4 +383,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     println!("Client fermé");
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn muted_users_are_filtered_from_the_stream() {
+        let mutes: HashSet<String> = ["bob".to_string()].into_iter().collect();
+        let stream = [
+            json!({ "username": "ana", "content": "salut", "message_type": "Text" }),
+            json!({ "username": "bob", "content": "spam", "message_type": "Text" }),
+            json!({ "username": "bob", "content": "danse", "message_type": "Action" }),
+            json!({ "username": "Système", "content": "bob a rejoint le chat", "message_type": "UserJoined" }),
+            json!({ "type": "userlist", "users": ["ana", "bob"] }),
+        ];
+
+        let shown: Vec<&str> = stream
+            .iter()
+            .filter(|message| !is_muted(message, &mutes))
+            .map(|message| message.get("content").and_then(|v| v.as_str()).unwrap_or("(liste)"))
+            .collect();
+        assert_eq!(shown, ["salut", "bob a rejoint le chat", "(liste)"]);
+        assert!(!stream.iter().any(|message| is_muted(message, &HashSet::new())));
+    }
+
+    #[test]
+    fn repeated_id_is_rendered_once() {
+        let mut seen = SeenIds::new(3);
+        let stream = ["a", "b", "a", "c", "b"];
+        let rendered: Vec<&str> = stream.into_iter().filter(|id| seen.insert(id)).collect();
+        assert_eq!(rendered, ["a", "b", "c"]);
+
+        // Capacité atteinte : le plus ancien est oublié, les autres restent connus
+        assert!(seen.insert("d"));
+        assert_eq!(seen.ids.len(), 3);
+        assert!(seen.insert("a"));
+        assert!(!seen.insert("d"));
+    }
+}
@@ -1,12 +1,88 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::{broadcast, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::{accept_async, tungstenite::Message};
+use tokio_tungstenite::accept_async_with_config;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(name = "WebSocket Server")]
+#[command(about = "Un serveur WebSocket simple pour le chat")]
+struct Args {
+    /// Adresse d'écoute (0.0.0.0:8080 pour le réseau local, port 0 pour un port libre)
+    #[arg(short, long, default_value = "127.0.0.1:8080")]
+    bind: String,
+
+    /// Désactiver la conversion des shortcodes (:smile:) en emoji
+    #[arg(long)]
+    no_emoji: bool,
+
+    /// Jeton des opérateurs (commandes d'administration comme le mode lent)
+    #[arg(long)]
+    admin_token: Option<String>,
+
+    /// Capacité du canal de diffusion (messages en attente par client)
+    #[arg(long, default_value_t = DEFAULT_CHANNEL_CAPACITY)]
+    channel_capacity: usize,
+
+    /// Taille maximale d'un message WebSocket en octets ; au-delà, la connexion est fermée
+    #[arg(long, default_value_t = DEFAULT_MAX_MESSAGE_SIZE)]
+    max_message_size: usize,
+
+    /// Déconnecter un client qui n'envoie aucun message pendant ce nombre de secondes (désactivé par défaut)
+    #[arg(long)]
+    idle_timeout: Option<u64>,
+
+    /// Longueur maximale du contenu d'un message en caractères ; au-delà, il est tronqué
+    #[arg(long, default_value_t = DEFAULT_MAX_CONTENT_CHARS)]
+    max_chars: usize,
+
+    /// Masquer les grossièretés courantes par des astérisques
+    #[arg(long)]
+    mask_profanity: bool,
+}
+
+/// Shortcodes courants et leur emoji
+static EMOJI_SHORTCODES: &[(&str, &str)] = &[
+    (":smile:", "😄"),
+    (":grin:", "😁"),
+    (":joy:", "😂"),
+    (":wink:", "😉"),
+    (":heart:", "❤️"),
+    (":thumbsup:", "👍"),
+    (":thumbsdown:", "👎"),
+    (":clap:", "👏"),
+    (":fire:", "🔥"),
+    (":rocket:", "🚀"),
+    (":tada:", "🎉"),
+    (":cry:", "😢"),
+    (":thinking:", "🤔"),
+    (":ok_hand:", "👌"),
+    (":wave:", "👋"),
+    (":crab:", "🦀"),
+];
+
+/// Remplace les shortcodes connus par leur emoji (idempotent)
+pub fn expand_shortcodes(content: &str) -> String {
+    let mut expanded = content.to_string();
+    for (code, emoji) in EMOJI_SHORTCODES {
+        if expanded.contains(code) {
+            expanded = expanded.replace(code, emoji);
+        }
+    }
+    expanded
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
@@ -15,37 +91,261 @@ pub struct ChatMessage {
     pub content: String,
     pub timestamp: u64,
     pub message_type: MessageType,
+    pub room: String,
+    /// Numéro d'ordre dans le salon, attribué à la diffusion (à partir de 1) ;
+    /// 0 pour un message privé, hors séquence
+    #[serde(default)]
+    pub seq: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessageType {
     Text,
+    /// Message d'action IRC (`/me`), affiché `* username contenu`
+    Action,
     UserJoined,
     UserLeft,
     System,
 }
 
+/// Salon rejoint par défaut
+pub const DEFAULT_ROOM: &str = "general";
+
+/// Nombre de messages conservés par salon pour la relecture
+pub const HISTORY_SIZE: usize = 50;
+
+/// Capacité par défaut du canal de diffusion
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1000;
+
+/// Taille maximale par défaut d'un message (et d'une trame) WebSocket reçu
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Longueur maximale par défaut du contenu d'un message, en caractères
+pub const DEFAULT_MAX_CONTENT_CHARS: usize = 2000;
+
+/// Mots masqués par `--mask-profanity`
+pub const PROFANITY: &[&str] = &["merde", "putain", "connard", "salaud"];
+
+/// Étape de nettoyage du contenu d'un message, avant sa diffusion
+pub type ContentStep = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+pub fn strip_control_step() -> ContentStep {
+    Box::new(|content| content.chars().filter(|c| !c.is_control()).collect())
+}
+
+pub fn trim_step() -> ContentStep {
+    Box::new(|content| content.trim().to_string())
+}
+
+pub fn truncate_step(max_chars: usize) -> ContentStep {
+    Box::new(move |content| content.chars().take(max_chars).collect())
+}
+
+/// Remplace les mots de `words` (sans tenir compte de la casse) par autant d'astérisques
+pub fn profanity_step(words: &[&str]) -> ContentStep {
+    let words: Vec<String> = words.iter().map(|word| word.to_lowercase()).collect();
+    Box::new(move |content| {
+        content
+            .split_inclusive(|c: char| !c.is_alphanumeric())
+            .map(|chunk| {
+                let word = chunk.trim_end_matches(|c: char| !c.is_alphanumeric());
+                if words.contains(&word.to_lowercase()) {
+                    "*".repeat(word.chars().count()) + &chunk[word.len()..]
+                } else {
+                    chunk.to_string()
+                }
+            })
+            .collect()
+    })
+}
+
+/// Chaîne par défaut : caractères de contrôle retirés, espaces de bord retirés,
+/// grossièretés masquées si demandé, puis troncature
+pub fn content_pipeline(max_chars: usize, mask_profanity: bool) -> Vec<ContentStep> {
+    let mut steps = vec![strip_control_step(), trim_step()];
+    if mask_profanity {
+        steps.push(profanity_step(PROFANITY));
+    }
+    steps.push(truncate_step(max_chars));
+    steps
+}
+
+/// Longueur maximale d'une réaction, en caractères (de quoi couvrir les
+/// emojis composés : teinte de peau, séquences ZWJ)
+pub const MAX_REACTION_CHARS: usize = 8;
+
+/// Une réaction est un seul symbole court, sans espace ni caractère de contrôle
+pub fn is_valid_reaction(emoji: &str) -> bool {
+    !emoji.is_empty()
+        && emoji.chars().count() <= MAX_REACTION_CHARS
+        && !emoji.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// Taille maximale des en-têtes HTTP examinés avant le handshake
+const MAX_REQUEST_HEAD: usize = 8192;
+
+/// Délai avant de lâcher une connexion fermée par le serveur
+const CLOSE_LINGER: Duration = Duration::from_millis(200);
+
+/// Délai laissé au client pour envoyer ses en-têtes HTTP
+const REQUEST_HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+impl ChatMessage {
+    pub fn new(username: &str, content: String, room: &str, message_type: MessageType) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            username: username.to_string(),
+            content,
+            timestamp: now_secs(),
+            message_type,
+            room: room.to_string(),
+            seq: 0,
+        }
+    }
+
+    pub fn system(content: String, room: &str, message_type: MessageType) -> Self {
+        Self::new("Système", content, room, message_type)
+    }
+}
+
+/// Salon actif, nombre d'utilisateurs présents et de spectateurs
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomInfo {
+    pub name: String,
+    pub users: usize,
+    pub spectators: usize,
+}
+
+/// Client présent dans un salon ; `away` contient la note d'absence
+#[derive(Debug, Clone, Serialize)]
+pub struct UserInfo {
+    pub username: String,
+    pub away: Option<String>,
+}
+
+/// Réponses privées qui ne sont pas des messages de chat
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ServerReply {
+    RoomList { rooms: Vec<RoomInfo> },
+    UserList { room: String, users: Vec<UserInfo> },
+    /// Nouvelle réaction, diffusée au salon ; `count` est le total pour cet emoji
+    Reaction { room: String, message_id: String, username: String, emoji: String, count: usize },
+    /// Totaux des réactions d'un message, envoyés après la relecture de l'historique
+    Reactions { message_id: String, totals: BTreeMap<String, usize> },
+    /// Derniers messages du salon demandés par le client, du plus ancien au plus récent
+    History { room: String, messages: Vec<ChatMessage> },
+}
+
+/// Message envoyé sur le canal direct d'un client : sérialisé tel quel,
+/// sans enveloppe supplémentaire
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum DirectMessage {
+    Chat(ChatMessage),
+    Reply(ServerReply),
+    // Ferme la connexion avec ce code au lieu d'envoyer du texte
+    #[serde(skip)]
+    Close(CloseFrame<'static>),
+}
+
+impl From<ChatMessage> for DirectMessage {
+    fn from(message: ChatMessage) -> Self {
+        DirectMessage::Chat(message)
+    }
+}
+
 #[derive(Debug)]
 pub struct Client {
     pub id: String,
     pub username: String,
     pub addr: SocketAddr,
+    pub room: String,
+    // Canal direct vers ce client (réponses privées, notices)
+    pub sender: mpsc::UnboundedSender<DirectMessage>,
+    // Note d'absence (None : disponible)
+    pub away: Option<String>,
+    // Spectateur : reçoit les messages du salon mais ne peut pas en envoyer
+    pub readonly: bool,
 }
 
 pub struct ServerState {
     pub clients: RwLock<HashMap<String, Client>>,
     pub broadcast_tx: broadcast::Sender<ChatMessage>,
+    pub expand_emoji: bool,
+    pub admin_token: Option<String>,
+    pub max_message_size: usize,
+    // Inactivité maximale (aucun message texte reçu) avant déconnexion
+    pub idle_timeout: Option<Duration>,
+    // Étapes appliquées dans l'ordre au contenu des messages et actions
+    pub content_pipeline: Vec<ContentStep>,
+    // Mode lent : salon -> délai minimal (secondes) entre deux messages d'un même utilisateur
+    pub slow_mode: RwLock<HashMap<String, u64>>,
+    // Dernier envoi par (salon, connexion) : un changement de nom ou une
+    // seconde connexion ne remet pas le délai à zéro pour cette connexion
+    pub last_sent: RwLock<HashMap<(String, String), Instant>>,
+    // Derniers messages de chaque salon, rejoués lors d'un changement de salon
+    pub history: RwLock<HashMap<String, VecDeque<ChatMessage>>>,
+    // Réactions aux messages encore dans l'historique : id -> emoji -> utilisateurs
+    pub reactions: RwLock<HashMap<String, BTreeMap<String, BTreeSet<String>>>>,
+    // Dernier numéro d'ordre attribué par salon
+    pub room_seq: RwLock<HashMap<String, u64>>,
+    // Démarrage du serveur, pour l'uptime de /health
+    pub started_at: Instant,
+    // Messages de chat (texte et actions) diffusés depuis le démarrage, pour /metrics
+    pub messages_total: AtomicU64,
+}
+
+impl Default for ServerState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ServerState {
     pub fn new() -> Self {
-        let (broadcast_tx, _) = broadcast::channel(1000);
+        Self::with_capacity(DEFAULT_CHANNEL_CAPACITY)
+    }
+
+    /// Crée l'état avec un canal de diffusion de `capacity` messages.
+    /// Plus grand : les clients lents ont plus de marge, mais chaque message en
+    /// attente reste en mémoire. Plus petit : moins de mémoire, mais un client
+    /// qui ne suit pas perd plus vite des messages (il est alors prévenu).
+    pub fn with_capacity(capacity: usize) -> Self {
+        // broadcast::channel panique avec une capacité nulle
+        let (broadcast_tx, _) = broadcast::channel(capacity.max(1));
         Self {
             clients: RwLock::new(HashMap::new()),
             broadcast_tx,
+            expand_emoji: true,
+            admin_token: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            idle_timeout: None,
+            content_pipeline: content_pipeline(DEFAULT_MAX_CONTENT_CHARS, false),
+            slow_mode: RwLock::new(HashMap::new()),
+            last_sent: RwLock::new(HashMap::new()),
+            history: RwLock::new(HashMap::new()),
+            reactions: RwLock::new(HashMap::new()),
+            room_seq: RwLock::new(HashMap::new()),
+            started_at: Instant::now(),
+            messages_total: AtomicU64::new(0),
         }
     }
 
+    /// Contenu après toutes les étapes de `content_pipeline`
+    pub fn sanitize_content(&self, content: &str) -> String {
+        self.content_pipeline
+            .iter()
+            .fold(content.to_string(), |content, step| step(&content))
+    }
+
     pub async fn add_client(&self, client: Client) {
         let mut clients = self.clients.write().await;
         clients.insert(client.id.clone(), client);
@@ -56,25 +356,230 @@ impl ServerState {
         clients.remove(client_id)
     }
 
+    /// Retire tous les clients portant ce nom et les retourne ; leur canal
+    /// direct permet ensuite de fermer leur connexion
+    pub async fn remove_by_username(&self, username: &str) -> Vec<Client> {
+        let mut clients = self.clients.write().await;
+        let ids: Vec<String> = clients
+            .values()
+            .filter(|client| client.username == username)
+            .map(|client| client.id.clone())
+            .collect();
+        ids.iter().filter_map(|id| clients.remove(id)).collect()
+    }
+
+    /// Clients ayant rejoint le chat, hors spectateurs
     pub async fn get_client_count(&self) -> usize {
         let clients = self.clients.read().await;
-        clients.len()
+        clients.values().filter(|client| !client.readonly).count()
+    }
+
+    pub async fn spectator_count(&self) -> usize {
+        let clients = self.clients.read().await;
+        clients.values().filter(|client| client.readonly).count()
     }
 
-    pub async fn broadcast_message(&self, message: ChatMessage) {
+    /// Salon courant d'un client (salon par défaut tant qu'il n'a pas rejoint)
+    pub async fn client_room(&self, client_id: &str) -> String {
+        let clients = self.clients.read().await;
+        clients
+            .get(client_id)
+            .map(|client| client.room.clone())
+            .unwrap_or_else(|| DEFAULT_ROOM.to_string())
+    }
+
+    pub async fn broadcast_message(&self, mut message: ChatMessage) {
+        // Verrou gardé jusqu'à l'envoi : les messages d'un salon partent dans
+        // l'ordre de leurs numéros, même depuis plusieurs connexions à la fois
+        let mut room_seq = self.room_seq.write().await;
+        let seq = room_seq.entry(message.room.clone()).or_default();
+        *seq += 1;
+        message.seq = *seq;
+
+        if matches!(message.message_type, MessageType::Text | MessageType::Action) {
+            self.messages_total.fetch_add(1, Ordering::Relaxed);
+            let mut history = self.history.write().await;
+            let room_history = history.entry(message.room.clone()).or_default();
+            // Les réactions suivent le message : la table reste bornée par l'historique
+            if room_history.len() >= HISTORY_SIZE
+                && let Some(evicted) = room_history.pop_front() {
+                self.reactions.write().await.remove(&evicted.id);
+            }
+            room_history.push_back(message.clone());
+        }
+
         if let Err(e) = self.broadcast_tx.send(message) {
             eprintln!("Erreur lors de la diffusion du message: {}", e);
         }
     }
+
+    /// Déplace un client dans `room` et retourne son ancien salon
+    /// (None si le client n'a pas encore rejoint le chat)
+    pub async fn switch_room(&self, client_id: &str, room: &str) -> Option<String> {
+        let mut clients = self.clients.write().await;
+        let client = clients.get_mut(client_id)?;
+        Some(std::mem::replace(&mut client.room, room.to_string()))
+    }
+
+    /// Salons ayant au moins un client (spectateurs compris), triés par nom
+    pub async fn room_list(&self) -> Vec<RoomInfo> {
+        let clients = self.clients.read().await;
+        let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+        for client in clients.values() {
+            let (users, spectators) = counts.entry(client.room.as_str()).or_default();
+            if client.readonly {
+                *spectators += 1;
+            } else {
+                *users += 1;
+            }
+        }
+        let mut rooms: Vec<RoomInfo> = counts
+            .into_iter()
+            .map(|(name, (users, spectators))| RoomInfo { name: name.to_string(), users, spectators })
+            .collect();
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+        rooms
+    }
+
+    /// Clients d'un salon (hors spectateurs) avec leur statut, triés par nom
+    pub async fn user_list(&self, room: &str) -> Vec<UserInfo> {
+        let clients = self.clients.read().await;
+        let mut users: Vec<UserInfo> = clients
+            .values()
+            .filter(|client| client.room == room && !client.readonly)
+            .map(|client| UserInfo { username: client.username.clone(), away: client.away.clone() })
+            .collect();
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        users
+    }
+
+    /// Change le statut d'un client ; false s'il n'a pas encore rejoint le chat
+    pub async fn set_away(&self, client_id: &str, away: Option<String>) -> bool {
+        let mut clients = self.clients.write().await;
+        match clients.get_mut(client_id) {
+            Some(client) => {
+                client.away = away;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Notes d'absence des utilisateurs du salon mentionnés par `@nom` dans `content`
+    pub async fn away_mentions(&self, room: &str, content: &str) -> Vec<(String, String)> {
+        let mentioned: Vec<&str> = content
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix('@'))
+            .map(|name| name.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_' && c != '-'))
+            .collect();
+        if mentioned.is_empty() {
+            return Vec::new();
+        }
+
+        let clients = self.clients.read().await;
+        let mut notes: Vec<(String, String)> = clients
+            .values()
+            .filter(|client| client.room == room && mentioned.contains(&client.username.as_str()))
+            .filter_map(|client| Some((client.username.clone(), client.away.clone()?)))
+            .collect();
+        notes.sort();
+        notes.dedup();
+        notes
+    }
+
+    /// Messages récents d'un salon, du plus ancien au plus récent
+    pub async fn room_history(&self, room: &str) -> Vec<ChatMessage> {
+        let history = self.history.read().await;
+        history
+            .get(room)
+            .map(|messages| messages.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Enregistre la réaction de `username` à un message encore dans l'historique
+    /// du salon et retourne le total pour cet emoji (None : message introuvable).
+    /// Réagir deux fois avec le même emoji ne compte qu'une fois.
+    pub async fn add_reaction(&self, room: &str, message_id: &str, username: &str, emoji: &str) -> Option<usize> {
+        let history = self.history.read().await;
+        history.get(room)?.iter().find(|message| message.id == message_id)?;
+        let mut reactions = self.reactions.write().await;
+        let users = reactions
+            .entry(message_id.to_string())
+            .or_default()
+            .entry(emoji.to_string())
+            .or_default();
+        users.insert(username.to_string());
+        Some(users.len())
+    }
+
+    /// Nombre de réactions par emoji pour un message (None : aucune réaction)
+    pub async fn reaction_totals(&self, message_id: &str) -> Option<BTreeMap<String, usize>> {
+        let reactions = self.reactions.read().await;
+        let emojis = reactions.get(message_id)?;
+        Some(emojis.iter().map(|(emoji, users)| (emoji.clone(), users.len())).collect())
+    }
+
+    /// Envoie un message sur le canal direct de chaque client du salon
+    pub async fn send_to_room(&self, room: &str, message: DirectMessage) {
+        let clients = self.clients.read().await;
+        for client in clients.values().filter(|client| client.room == room) {
+            let _ = client.sender.send(message.clone());
+        }
+    }
+
+    /// Vérifie le jeton d'un opérateur (toujours refusé si aucun jeton n'est configuré)
+    pub fn is_operator(&self, token: Option<&str>) -> bool {
+        matches!((&self.admin_token, token), (Some(expected), Some(token)) if expected == token)
+    }
+
+    /// Active le mode lent sur un salon (0 pour le désactiver)
+    pub async fn set_slow_mode(&self, room: &str, seconds: u64) {
+        let mut slow_mode = self.slow_mode.write().await;
+        if seconds == 0 {
+            slow_mode.remove(room);
+        } else {
+            slow_mode.insert(room.to_string(), seconds);
+        }
+    }
+
+    /// Retourne le temps d'attente restant (secondes) si la connexion a parlé
+    /// trop récemment dans ce salon, sinon enregistre l'envoi et retourne None
+    pub async fn check_slow_mode(&self, room: &str, client_id: &str) -> Option<u64> {
+        let delay = self.slow_mode.read().await.get(room).copied()?;
+        let mut last_sent = self.last_sent.write().await;
+        let key = (room.to_string(), client_id.to_string());
+
+        if let Some(last) = last_sent.get(&key) {
+            let elapsed = last.elapsed().as_secs();
+            if elapsed < delay {
+                return Some(delay - elapsed);
+            }
+        }
+
+        last_sent.insert(key, Instant::now());
+        None
+    }
+
+    /// Oublie les envois d'une connexion fermée
+    pub async fn forget_slow_mode(&self, client_id: &str) {
+        self.last_sent.write().await.retain(|(_, id), _| id != client_id);
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "127.0.0.1:8080";
-    let listener = TcpListener::bind(&addr).await?;
-    println!("Serveur WebSocket démarré sur ws://{}", addr);
+    let args = Args::parse();
 
-    let state = Arc::new(ServerState::new());
+    let listener = TcpListener::bind(&args.bind).await?;
+    println!("Serveur WebSocket démarré sur ws://{}", listener.local_addr()?);
+
+    let mut state = ServerState::with_capacity(args.channel_capacity);
+    state.expand_emoji = !args.no_emoji;
+    state.admin_token = args.admin_token;
+    state.max_message_size = args.max_message_size;
+    state.idle_timeout = args.idle_timeout.map(Duration::from_secs);
+    state.content_pipeline = content_pipeline(args.max_chars, args.mask_profanity);
+    let state = Arc::new(state);
 
     while let Ok((stream, addr)) = listener.accept().await {
         let state_clone = Arc::clone(&state);
@@ -84,85 +589,478 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Lit sans les consommer les en-têtes de la requête initiale : `accept_async`
+/// refait ensuite le handshake depuis le début
+async fn peek_request_head(stream: &TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; MAX_REQUEST_HEAD];
+    loop {
+        let n = stream.peek(&mut buf).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        if n == buf.len() || buf[..n].windows(4).any(|w| w == b"\r\n\r\n") {
+            buf.truncate(n);
+            return Ok(buf);
+        }
+        // En-têtes incomplets : peek rend aussitôt ce qui est déjà arrivé
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+fn is_websocket_upgrade(head: &[u8]) -> bool {
+    String::from_utf8_lossy(head).lines().skip(1).any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("upgrade") && value.trim().eq_ignore_ascii_case("websocket")
+        })
+    })
+}
+
+/// Répond à `GET /health` (nombre de clients et uptime), 400 pour le reste
+async fn handle_http_request(
+    mut stream: TcpStream,
+    head: &[u8],
+    state: &ServerState,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Consommer la requête déjà examinée : fermer avec des données non lues
+    // provoquerait un RST qui peut faire perdre la réponse au client
+    stream.read_exact(&mut vec![0u8; head.len()]).await?;
+
+    let request = String::from_utf8_lossy(head);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (status, content_type, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/health")) => {
+            let body = serde_json::json!({
+                "status": "ok",
+                "clients": state.get_client_count().await,
+                "spectators": state.spectator_count().await,
+                "uptime_secs": state.started_at.elapsed().as_secs(),
+            });
+            ("200 OK", "application/json", body.to_string())
+        }
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4", metrics_text(state).await),
+        _ => {
+            let body = serde_json::json!({ "error": "requête WebSocket, GET /health ou GET /metrics attendue" });
+            ("400 Bad Request", "application/json", body.to_string())
+        }
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Compteurs au format texte de Prometheus
+async fn metrics_text(state: &ServerState) -> String {
+    let metrics = [
+        ("chat_connected_clients", "gauge", "Clients ayant rejoint le chat", state.get_client_count().await as u64),
+        ("chat_spectators", "gauge", "Spectateurs en lecture seule", state.spectator_count().await as u64),
+        ("chat_messages_total", "counter", "Messages de chat diffusés", state.messages_total.load(Ordering::Relaxed)),
+        ("chat_uptime_seconds", "gauge", "Temps écoulé depuis le démarrage", state.started_at.elapsed().as_secs()),
+    ];
+    metrics
+        .iter()
+        .map(|(name, kind, help, value)| format!("# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"))
+        .collect()
+}
+
 async fn handle_connection(
     stream: TcpStream,
     addr: SocketAddr,
     state: Arc<ServerState>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Les requêtes HTTP simples (sondes /health et /metrics) sont traitées avant le handshake
+    let head = tokio::time::timeout(REQUEST_HEAD_TIMEOUT, peek_request_head(&stream)).await??;
+    if !is_websocket_upgrade(&head) {
+        return handle_http_request(stream, &head, &state).await;
+    }
+
     println!("Nouvelle connexion depuis: {}", addr);
 
-    // Effectuer le handshake WebSocket
-    let ws_stream = accept_async(stream).await?;
+    // Effectuer le handshake WebSocket ; une trame annoncée plus grande que la
+    // limite est rejetée dès son en-tête, sans être mise en mémoire
+    let config = WebSocketConfig {
+        max_message_size: Some(state.max_message_size),
+        max_frame_size: Some(state.max_message_size),
+        ..WebSocketConfig::default()
+    };
+    let ws_stream = accept_async_with_config(stream, Some(config)).await?;
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
     // Générer un ID unique pour le client
     let client_id = Uuid::new_v4().to_string();
     let mut username = format!("User_{}", &client_id[..8]);
+    // Fixé au join : un spectateur ne peut ni écrire ni réagir
+    let mut readonly = false;
 
     // Créer un récepteur pour les messages broadcast
     let mut broadcast_rx = state.broadcast_tx.subscribe();
+    // Canal pour les messages destinés uniquement à ce client
+    let (direct_tx, mut direct_rx) = mpsc::unbounded_channel::<DirectMessage>();
+
+    // Dernier message applicatif reçu ; les ping/pong WebSocket n'y comptent pas
+    let last_activity = Arc::new(std::sync::Mutex::new(Instant::now()));
+
+    // Surveillance de l'inactivité : avis puis fermeture par la tâche de diffusion
+    let idle_watchdog = state.idle_timeout.map(|timeout| {
+        let last_activity = Arc::clone(&last_activity);
+        let direct_tx = direct_tx.clone();
+        let state = Arc::clone(&state);
+        let client_id = client_id.clone();
+        tokio::spawn(async move {
+            loop {
+                let idle = last_activity.lock().unwrap().elapsed();
+                if idle < timeout {
+                    tokio::time::sleep(timeout - idle).await;
+                    continue;
+                }
+                println!("Client {} inactif depuis {} s, déconnexion", client_id, idle.as_secs());
+                let room = state.client_room(&client_id).await;
+                let notice = ChatMessage::system("idle timeout : déconnexion pour inactivité".to_string(), &room, MessageType::System);
+                let _ = direct_tx.send(notice.into());
+                let _ = direct_tx.send(DirectMessage::Close(CloseFrame {
+                    code: CloseCode::Away,
+                    reason: "idle timeout".into(),
+                }));
+                break;
+            }
+        })
+    });
 
     // Tâche pour recevoir les messages du client
     let state_for_receiver = Arc::clone(&state);
     let client_id_for_receiver = client_id.clone();
-    let username_for_receiver = username.clone();
     
-    let receive_task = tokio::spawn(async move {
+    let mut receive_task = tokio::spawn(async move {
         while let Some(msg) = ws_receiver.next().await {
             match msg {
                 Ok(Message::Text(text)) => {
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&text) {
-                        // Gérer différents types de messages
-                        if let Some(msg_type) = parsed.get("type").and_then(|v| v.as_str()) {
-                            match msg_type {
-                                "join" => {
-                                    if let Some(new_username) = parsed.get("username").and_then(|v| v.as_str()) {
-                                        username = new_username.to_string();
-                                        
-                                        let client = Client {
-                                            id: client_id_for_receiver.clone(),
-                                            username: username.clone(),
-                                            addr,
-                                        };
-                                        
-                                        state_for_receiver.add_client(client).await;
-                                        
-                                        let join_message = ChatMessage {
-                                            id: Uuid::new_v4().to_string(),
-                                            username: "Système".to_string(),
-                                            content: format!("{} a rejoint le chat", username),
-                                            timestamp: std::time::SystemTime::now()
-                                                .duration_since(std::time::UNIX_EPOCH)
-                                                .unwrap()
-                                                .as_secs(),
-                                            message_type: MessageType::UserJoined,
-                                        };
-                                        
-                                        state_for_receiver.broadcast_message(join_message).await;
-                                        
-                                        println!("Client {} ({}) a rejoint le chat", username, client_id_for_receiver);
+                    *last_activity.lock().unwrap() = Instant::now();
+                    // Une trame mal formée est signalée au client sans couper la connexion
+                    let parsed = match serde_json::from_str::<serde_json::Value>(&text) {
+                        Ok(parsed) => parsed,
+                        Err(e) => {
+                            eprintln!("JSON invalide de {}: {}", client_id_for_receiver, e);
+                            let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                            let notice = ChatMessage::system(
+                                format!("Message ignoré : JSON invalide ({})", e),
+                                &room,
+                                MessageType::System,
+                            );
+                            let _ = direct_tx.send(notice.into());
+                            continue;
+                        }
+                    };
+
+                    // Gérer différents types de messages
+                    if let Some(msg_type) = parsed.get("type").and_then(|v| v.as_str()) {
+                        match msg_type {
+                            "join" => {
+                                if let Some(new_username) = parsed.get("username").and_then(|v| v.as_str()) {
+                                    username = new_username.to_string();
+                                    readonly = parsed.get("readonly").and_then(|v| v.as_bool()).unwrap_or(false);
+                                    let room = parsed
+                                        .get("room")
+                                        .and_then(|v| v.as_str())
+                                        .unwrap_or(DEFAULT_ROOM)
+                                        .to_string();
+                                    
+                                    let client = Client {
+                                        id: client_id_for_receiver.clone(),
+                                        username: username.clone(),
+                                        addr,
+                                        room: room.clone(),
+                                        sender: direct_tx.clone(),
+                                        away: None,
+                                        readonly,
+                                    };
+                                    
+                                    state_for_receiver.add_client(client).await;
+                                    
+                                    // Arrivée d'un spectateur non annoncée au salon
+                                    if readonly {
+                                        let notice = ChatMessage::system(
+                                            format!("Vous observez {} en lecture seule", room),
+                                            &room,
+                                            MessageType::System,
+                                        );
+                                        let _ = direct_tx.send(notice.into());
+                                        println!("Spectateur {} ({}) observe {}", username, client_id_for_receiver, room);
+                                        continue;
+                                    }
+                                    
+                                    let join_message = ChatMessage::system(
+                                        format!("{} a rejoint le chat", username),
+                                        &room,
+                                        MessageType::UserJoined,
+                                    );
+                                    
+                                    state_for_receiver.broadcast_message(join_message).await;
+                                    
+                                    println!("Client {} ({}) a rejoint le chat", username, client_id_for_receiver);
+                                }
+                            }
+                            "rooms" => {
+                                let rooms = state_for_receiver.room_list().await;
+                                let _ = direct_tx.send(DirectMessage::Reply(ServerReply::RoomList { rooms }));
+                            }
+                            // {"type":"history","limit":20} : au plus HISTORY_SIZE, tous par défaut
+                            "history" => {
+                                let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                                let limit = parsed
+                                    .get("limit")
+                                    .and_then(|v| v.as_u64())
+                                    .map_or(HISTORY_SIZE, |limit| (limit as usize).min(HISTORY_SIZE));
+                                let mut messages = state_for_receiver.room_history(&room).await;
+                                messages.drain(..messages.len().saturating_sub(limit));
+                                let _ = direct_tx.send(DirectMessage::Reply(ServerReply::History { room, messages }));
+                            }
+                            "users" => {
+                                let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                                let users = state_for_receiver.user_list(&room).await;
+                                let _ = direct_tx.send(DirectMessage::Reply(ServerReply::UserList { room, users }));
+                            }
+                            // {"type":"status","state":"away","note":"..."} ; tout autre état : de retour
+                            "status" => {
+                                let away = match parsed.get("state").and_then(|v| v.as_str()) {
+                                    Some("away") => Some(
+                                        parsed.get("note").and_then(|v| v.as_str()).unwrap_or("").trim().to_string(),
+                                    ),
+                                    _ => None,
+                                };
+                                let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                                let content = if !state_for_receiver.set_away(&client_id_for_receiver, away.clone()).await {
+                                    "Rejoignez d'abord le chat".to_string()
+                                } else {
+                                    match away {
+                                        Some(note) if !note.is_empty() => format!("Vous êtes absent : {}", note),
+                                        Some(_) => "Vous êtes absent".to_string(),
+                                        None => "Vous êtes de nouveau disponible".to_string(),
+                                    }
+                                };
+                                let notice = ChatMessage::system(content, &room, MessageType::System);
+                                let _ = direct_tx.send(notice.into());
+                            }
+                            "switch" => {
+                                let Some(new_room) = parsed.get("room").and_then(|v| v.as_str()) else {
+                                    continue;
+                                };
+                                let Some(old_room) = state_for_receiver.switch_room(&client_id_for_receiver, new_room).await else {
+                                    let notice = ChatMessage::system(
+                                        "Rejoignez d'abord le chat".to_string(),
+                                        DEFAULT_ROOM,
+                                        MessageType::System,
+                                    );
+                                    let _ = direct_tx.send(notice.into());
+                                    continue;
+                                };
+                                if old_room == new_room {
+                                    continue;
+                                }
+
+                                // Les déplacements d'un spectateur ne sont pas annoncés
+                                if !readonly {
+                                    let leave_message = ChatMessage::system(
+                                        format!("{} a quitté le salon", username),
+                                        &old_room,
+                                        MessageType::UserLeft,
+                                    );
+                                    state_for_receiver.broadcast_message(leave_message).await;
+                                }
+
+                                // Relecture de l'historique, envoyée uniquement à ce client
+                                for message in state_for_receiver.room_history(new_room).await {
+                                    let totals = state_for_receiver.reaction_totals(&message.id).await;
+                                    let message_id = message.id.clone();
+                                    let _ = direct_tx.send(message.into());
+                                    if let Some(totals) = totals {
+                                        let _ = direct_tx.send(DirectMessage::Reply(ServerReply::Reactions { message_id, totals }));
                                     }
                                 }
-                                "message" => {
-                                    if let Some(content) = parsed.get("content").and_then(|v| v.as_str()) {
-                                        let chat_message = ChatMessage {
-                                            id: Uuid::new_v4().to_string(),
-                                            username: username_for_receiver.clone(),
-                                            content: content.to_string(),
-                                            timestamp: std::time::SystemTime::now()
-                                                .duration_since(std::time::UNIX_EPOCH)
-                                                .unwrap()
-                                                .as_secs(),
-                                            message_type: MessageType::Text,
+
+                                if !readonly {
+                                    let join_message = ChatMessage::system(
+                                        format!("{} a rejoint le salon", username),
+                                        new_room,
+                                        MessageType::UserJoined,
+                                    );
+                                    state_for_receiver.broadcast_message(join_message).await;
+                                }
+
+                                println!("Client {} est passé de {} à {}", username, old_room, new_room);
+                            }
+                            // "action" (/me) suit le même chemin que "message", seul le type change
+                            "message" | "action" | "react" if readonly => {
+                                let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                                let notice = ChatMessage::system(
+                                    "Mode spectateur : envoi impossible en lecture seule".to_string(),
+                                    &room,
+                                    MessageType::System,
+                                );
+                                let _ = direct_tx.send(notice.into());
+                            }
+                            kind @ ("message" | "action") => {
+                                if let Some(content) = parsed.get("content").and_then(|v| v.as_str()) {
+                                    // Rien à diffuser une fois nettoyé (espaces, caractères de contrôle)
+                                    let content = state_for_receiver.sanitize_content(content);
+                                    if content.is_empty() {
+                                        continue;
+                                    }
+                                    let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                                    
+                                    // Mode lent : refuser en privé si l'utilisateur parle trop vite
+                                    if let Some(remaining) = state_for_receiver.check_slow_mode(&room, &client_id_for_receiver).await {
+                                        let notice = ChatMessage::system(
+                                            format!("Mode lent actif dans {} : réessayez dans {} s", room, remaining),
+                                            &room,
+                                            MessageType::System,
+                                        );
+                                        let _ = direct_tx.send(notice.into());
+                                        continue;
+                                    }
+                                    
+                                    let content = if state_for_receiver.expand_emoji {
+                                        expand_shortcodes(&content)
+                                    } else {
+                                        content
+                                    };
+
+                                    let message_type = if kind == "action" { MessageType::Action } else { MessageType::Text };
+                                    let away_notes = state_for_receiver.away_mentions(&room, &content).await;
+                                    let chat_message = ChatMessage::new(&username, content, &room, message_type);
+                                    
+                                    state_for_receiver.broadcast_message(chat_message).await;
+
+                                    // Réponse automatique pour chaque utilisateur absent mentionné
+                                    for (away_user, note) in away_notes {
+                                        let content = if note.is_empty() {
+                                            format!("{} est absent", away_user)
+                                        } else {
+                                            format!("{} est absent : {}", away_user, note)
                                         };
-                                        
-                                        state_for_receiver.broadcast_message(chat_message).await;
+                                        let notice = ChatMessage::system(content, &room, MessageType::System);
+                                        let _ = direct_tx.send(notice.into());
                                     }
                                 }
-                                _ => {
-                                    println!("Type de message non reconnu: {}", msg_type);
+                            }
+                            // {"type":"react","message_id":"...","emoji":"👍"}
+                            "react" => {
+                                let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                                let (Some(message_id), Some(emoji)) = (
+                                    parsed.get("message_id").and_then(|v| v.as_str()),
+                                    parsed.get("emoji").and_then(|v| v.as_str()),
+                                ) else {
+                                    continue;
+                                };
+                                let emoji = if state_for_receiver.expand_emoji {
+                                    expand_shortcodes(emoji.trim())
+                                } else {
+                                    emoji.trim().to_string()
+                                };
+
+                                if !is_valid_reaction(&emoji) {
+                                    let notice = ChatMessage::system(
+                                        format!("Réaction invalide : un seul emoji de {} caractères au plus", MAX_REACTION_CHARS),
+                                        &room,
+                                        MessageType::System,
+                                    );
+                                    let _ = direct_tx.send(notice.into());
+                                    continue;
+                                }
+                                let Some(count) = state_for_receiver.add_reaction(&room, message_id, &username, &emoji).await else {
+                                    let notice = ChatMessage::system(
+                                        format!("Message {} introuvable dans {}", message_id, room),
+                                        &room,
+                                        MessageType::System,
+                                    );
+                                    let _ = direct_tx.send(notice.into());
+                                    continue;
+                                };
+
+                                let reaction = ServerReply::Reaction {
+                                    room: room.clone(),
+                                    message_id: message_id.to_string(),
+                                    username: username.clone(),
+                                    emoji,
+                                    count,
+                                };
+                                state_for_receiver.send_to_room(&room, DirectMessage::Reply(reaction)).await;
+                            }
+                            "slowmode" => {
+                                let token = parsed.get("token").and_then(|v| v.as_str());
+                                let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                                
+                                if !state_for_receiver.is_operator(token) {
+                                    let notice = ChatMessage::system(
+                                        "Commande réservée aux opérateurs".to_string(),
+                                        &room,
+                                        MessageType::System,
+                                    );
+                                    let _ = direct_tx.send(notice.into());
+                                } else if let Some(seconds) = parsed.get("seconds").and_then(|v| v.as_u64()) {
+                                    state_for_receiver.set_slow_mode(&room, seconds).await;
+                                    
+                                    let content = if seconds == 0 {
+                                        "Mode lent désactivé".to_string()
+                                    } else {
+                                        format!("Mode lent activé : un message toutes les {} s", seconds)
+                                    };
+                                    let notice = ChatMessage::system(content, &room, MessageType::System);
+                                    state_for_receiver.broadcast_message(notice).await;
                                 }
                             }
+                            "kick" => {
+                                let token = parsed.get("token").and_then(|v| v.as_str());
+                                let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                                let Some(target) = parsed.get("target").and_then(|v| v.as_str()) else {
+                                    continue;
+                                };
+
+                                if !state_for_receiver.is_operator(token) {
+                                    let notice = ChatMessage::system(
+                                        "Commande réservée aux opérateurs".to_string(),
+                                        &room,
+                                        MessageType::System,
+                                    );
+                                    let _ = direct_tx.send(notice.into());
+                                    continue;
+                                }
+
+                                // Retiré de l'état avant la fermeture : sa déconnexion
+                                // n'annoncera pas un départ ordinaire
+                                let kicked = state_for_receiver.remove_by_username(target).await;
+                                if kicked.is_empty() {
+                                    let notice = ChatMessage::system(
+                                        format!("Aucun utilisateur nommé {}", target),
+                                        &room,
+                                        MessageType::System,
+                                    );
+                                    let _ = direct_tx.send(notice.into());
+                                    continue;
+                                }
+                                for client in kicked {
+                                    let _ = client.sender.send(DirectMessage::Close(CloseFrame {
+                                        code: CloseCode::Policy,
+                                        reason: "expulsé par un opérateur".into(),
+                                    }));
+                                    let notice = ChatMessage::system(
+                                        format!("{} a été expulsé", client.username),
+                                        &client.room,
+                                        MessageType::System,
+                                    );
+                                    state_for_receiver.broadcast_message(notice).await;
+                                    println!("Client {} ({}) expulsé par {}", client.username, client.id, username);
+                                }
+                            }
+                            _ => {
+                                println!("Type de message non reconnu: {}", msg_type);
+                            }
                         }
                     }
                 }
@@ -170,6 +1068,25 @@ async fn handle_connection(
                     println!("Client {} a fermé la connexion", client_id_for_receiver);
                     break;
                 }
+                // Texte non UTF-8 : la trame est consommée, la connexion reste utilisable
+                Err(WsError::Utf8) => {
+                    eprintln!("Trame non UTF-8 de {}", client_id_for_receiver);
+                    let room = state_for_receiver.client_room(&client_id_for_receiver).await;
+                    let notice = ChatMessage::system(
+                        "Message ignoré : texte non UTF-8".to_string(),
+                        &room,
+                        MessageType::System,
+                    );
+                    let _ = direct_tx.send(notice.into());
+                }
+                Err(WsError::Capacity(e)) => {
+                    eprintln!("Message trop grand de {}: {}", client_id_for_receiver, e);
+                    let _ = direct_tx.send(DirectMessage::Close(CloseFrame {
+                        code: CloseCode::Size,
+                        reason: "message trop grand".into(),
+                    }));
+                    break;
+                }
                 Err(e) => {
                     eprintln!("Erreur WebSocket: {}", e);
                     break;
@@ -180,8 +1097,45 @@ async fn handle_connection(
     });
 
     // Tâche pour diffuser les messages aux clients
-    let broadcast_task = tokio::spawn(async move {
-        while let Ok(message) = broadcast_rx.recv().await {
+    let state_for_broadcast = Arc::clone(&state);
+    let client_id_for_broadcast = client_id.clone();
+    let receive_abort = receive_task.abort_handle();
+    let mut broadcast_task = tokio::spawn(async move {
+        loop {
+            let message = tokio::select! {
+                result = broadcast_rx.recv() => match result {
+                    Ok(message) => {
+                        // Ne transmettre que les messages du salon courant
+                        if message.room != state_for_broadcast.client_room(&client_id_for_broadcast).await {
+                            continue;
+                        }
+                        message.into()
+                    }
+                    // Client trop lent : des messages ont été écrasés dans le canal
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("Client {} en retard de {} messages", client_id_for_broadcast, skipped);
+                        let room = state_for_broadcast.client_room(&client_id_for_broadcast).await;
+                        ChatMessage::system(
+                            format!("{} message(s) manqué(s), connexion trop lente", skipped),
+                            &room,
+                            MessageType::System,
+                        )
+                        .into()
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                Some(message) = direct_rx.recv() => message,
+            };
+
+            if let DirectMessage::Close(frame) = message {
+                // Plus rien n'est accepté du client pendant l'attente qui suit
+                receive_abort.abort();
+                let _ = ws_sender.send(Message::Close(Some(frame))).await;
+                // Des données non lues restent côté serveur : fermer aussitôt
+                // enverrait un RST qui ferait perdre la trame Close au client
+                tokio::time::sleep(CLOSE_LINGER).await;
+                break;
+            }
             let json_message = serde_json::to_string(&message).unwrap();
             if let Err(e) = ws_sender.send(Message::Text(json_message)).await {
                 eprintln!("Erreur lors de l'envoi du message: {}", e);
@@ -190,28 +1144,558 @@ async fn handle_connection(
         }
     });
 
-    // Attendre qu'une des tâches se termine
+    // Attendre qu'une des tâches se termine, puis arrêter l'autre : après une
+    // expulsion ou un idle timeout, la réception ne doit plus rien accepter
     tokio::select! {
-        _ = receive_task => {},
-        _ = broadcast_task => {},
+        _ = &mut receive_task => {},
+        _ = &mut broadcast_task => {},
+    }
+    receive_task.abort();
+    broadcast_task.abort();
+    state.forget_slow_mode(&client_id).await;
+    if let Some(watchdog) = idle_watchdog {
+        watchdog.abort();
     }
 
-    // Nettoyer le client déconnecté
-    if let Some(client) = state.remove_client(&client_id).await {
-        let leave_message = ChatMessage {
-            id: Uuid::new_v4().to_string(),
-            username: "Système".to_string(),
-            content: format!("{} a quitté le chat", client.username),
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            message_type: MessageType::UserLeft,
-        };
+    // Nettoyer le client déconnecté (départ d'un spectateur non annoncé)
+    if let Some(client) = state.remove_client(&client_id).await
+        && !client.readonly {
+        let leave_message = ChatMessage::system(
+            format!("{} a quitté le chat", client.username),
+            &client.room,
+            MessageType::UserLeft,
+        );
         
         state.broadcast_message(leave_message).await;
         println!("Client {} déconnecté", client.username);
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+    type TestSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn start_server(state: ServerState) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(state);
+        tokio::spawn(async move {
+            while let Ok((stream, addr)) = listener.accept().await {
+                tokio::spawn(handle_connection(stream, addr, Arc::clone(&state)));
+            }
+        });
+        addr
+    }
+
+    async fn join(addr: SocketAddr, username: &str) -> TestSocket {
+        let (mut ws, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+        let join = serde_json::json!({ "type": "join", "username": username });
+        ws.send(Message::Text(join.to_string())).await.unwrap();
+        ws
+    }
+
+    /// Textes reçus jusqu'à `wait` sans nouvelle trame
+    async fn drain(ws: &mut TestSocket, wait: Duration) -> Vec<String> {
+        let mut texts = Vec::new();
+        while let Ok(Some(Ok(message))) = tokio::time::timeout(wait, ws.next()).await {
+            if let Message::Text(text) = message {
+                texts.push(text);
+            }
+        }
+        texts
+    }
+
+    /// Attend la trame Close du serveur
+    async fn wait_close(ws: &mut TestSocket) {
+        loop {
+            match tokio::time::timeout(Duration::from_secs(5), ws.next()).await {
+                Ok(Some(Ok(Message::Close(_)))) => return,
+                Ok(Some(Ok(_))) => continue,
+                other => panic!("trame Close attendue, reçu {:?}", other),
+            }
+        }
+    }
+
+    /// Écrit une trame texte directement sur la socket : tungstenite refuse
+    /// d'envoyer après une trame Close, un client malveillant non
+    async fn send_raw_text(ws: &mut TestSocket, text: &str) {
+        // Trame masquée (obligatoire côté client) avec une clé nulle
+        let mut frame = vec![0x81, 0x80 | text.len() as u8, 0, 0, 0, 0];
+        frame.extend_from_slice(text.as_bytes());
+        let _ = ws.get_mut().write_all(&frame).await;
+    }
+
+    #[tokio::test]
+    async fn kicked_client_cannot_post() {
+        let mut state = ServerState::new();
+        state.admin_token = Some("secret".to_string());
+        let addr = start_server(state).await;
+
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+
+        let kick = serde_json::json!({ "type": "kick", "target": "bob", "token": "secret" });
+        ana.send(Message::Text(kick.to_string())).await.unwrap();
+        wait_close(&mut bob).await;
+
+        send_raw_text(&mut bob, r#"{"type":"message","content":"fantome"}"#).await;
+        let received = drain(&mut ana, Duration::from_millis(500)).await;
+        assert!(received.iter().any(|text| text.contains("bob a été expulsé")));
+        assert!(!received.iter().any(|text| text.contains("fantome")));
+    }
+
+    #[tokio::test]
+    async fn slow_mode_defers_second_message_even_after_rename() {
+        let mut state = ServerState::new();
+        state.admin_token = Some("secret".to_string());
+        let addr = start_server(state).await;
+
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        let slowmode = serde_json::json!({ "type": "slowmode", "seconds": 60, "token": "secret" });
+        ana.send(Message::Text(slowmode.to_string())).await.unwrap();
+        drain(&mut ana, Duration::from_millis(200)).await;
+        drain(&mut bob, Duration::from_millis(200)).await;
+
+        for content in ["premier", "deuxième"] {
+            let message = serde_json::json!({ "type": "message", "content": content });
+            bob.send(Message::Text(message.to_string())).await.unwrap();
+        }
+        let to_bob = drain(&mut bob, Duration::from_millis(200)).await;
+        assert!(to_bob.iter().any(|text| text.contains("Mode lent actif dans general")));
+        let to_ana = drain(&mut ana, Duration::from_millis(200)).await;
+        assert!(to_ana.iter().any(|text| text.contains("premier")));
+        assert!(!to_ana.iter().any(|text| text.contains("deuxième")));
+
+        // Un nouveau nom sur la même connexion garde le même délai
+        let rejoin = serde_json::json!({ "type": "join", "username": "bob2" });
+        bob.send(Message::Text(rejoin.to_string())).await.unwrap();
+        let message = serde_json::json!({ "type": "message", "content": "troisième" });
+        bob.send(Message::Text(message.to_string())).await.unwrap();
+        let to_bob = drain(&mut bob, Duration::from_millis(200)).await;
+        assert!(to_bob.iter().any(|text| text.contains("Mode lent actif")));
+        let to_ana = drain(&mut ana, Duration::from_millis(200)).await;
+        assert!(!to_ana.iter().any(|text| text.contains("troisième")));
+    }
+
+    #[tokio::test]
+    async fn idle_client_cannot_post_after_timeout() {
+        let mut state = ServerState::new();
+        state.idle_timeout = Some(Duration::from_millis(300));
+        let addr = start_server(state).await;
+
+        let mut bob = join(addr, "bob").await;
+        wait_close(&mut bob).await;
+
+        // Rejoint après la déconnexion de bob : ne voit que ce qui suit
+        let mut ana = join(addr, "ana").await;
+        drain(&mut ana, Duration::from_millis(100)).await;
+        send_raw_text(&mut bob, r#"{"type":"message","content":"fantome"}"#).await;
+        let received = drain(&mut ana, Duration::from_millis(200)).await;
+        assert!(!received.iter().any(|text| text.contains("fantome")));
+    }
+
+    async fn send_json(ws: &mut TestSocket, value: serde_json::Value) {
+        ws.send(Message::Text(value.to_string())).await.unwrap();
+    }
+
+    /// Messages de chat reçus (texte et actions), décodés
+    fn chat_messages(texts: &[String]) -> Vec<ChatMessage> {
+        texts
+            .iter()
+            .filter_map(|text| serde_json::from_str::<ChatMessage>(text).ok())
+            .filter(|message| matches!(message.message_type, MessageType::Text | MessageType::Action))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn shortcode_is_expanded_in_the_broadcast() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+
+        send_json(&mut bob, serde_json::json!({ "type": "message", "content": "salut :smile:" })).await;
+        let received = chat_messages(&drain(&mut ana, Duration::from_millis(200)).await);
+        assert_eq!(received[0].content, "salut 😄");
+        assert_eq!(expand_shortcodes(&received[0].content), received[0].content);
+
+        // --no-emoji : le shortcode passe tel quel
+        let mut state = ServerState::new();
+        state.expand_emoji = false;
+        let addr = start_server(state).await;
+        let mut ana = join(addr, "ana").await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+        send_json(&mut ana, serde_json::json!({ "type": "message", "content": ":smile:" })).await;
+        let received = chat_messages(&drain(&mut ana, Duration::from_millis(200)).await);
+        assert_eq!(received[0].content, ":smile:");
+    }
+
+    #[tokio::test]
+    async fn tiny_channel_makes_a_slow_receiver_lag() {
+        let state = ServerState::with_capacity(2);
+        let mut rx = state.broadcast_tx.subscribe();
+        for content in ["un", "deux", "trois", "quatre"] {
+            state.broadcast_message(ChatMessage::new("ana", content.to_string(), DEFAULT_ROOM, MessageType::Text)).await;
+        }
+
+        // Les deux plus anciens ont été écrasés : c'est le cas signalé au client
+        assert!(matches!(rx.recv().await, Err(RecvError::Lagged(2))));
+        assert_eq!(rx.recv().await.unwrap().content, "trois");
+        assert_eq!(rx.recv().await.unwrap().content, "quatre");
+
+        // Une capacité nulle est ramenée à 1 au lieu de paniquer
+        let state = ServerState::with_capacity(0);
+        let mut rx = state.broadcast_tx.subscribe();
+        state.broadcast_message(ChatMessage::new("ana", "seul".to_string(), DEFAULT_ROOM, MessageType::Text)).await;
+        assert_eq!(rx.recv().await.unwrap().content, "seul");
+    }
+
+    #[tokio::test]
+    async fn action_round_trips_with_the_action_type() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        drain(&mut bob, Duration::from_millis(200)).await;
+
+        send_json(&mut ana, serde_json::json!({ "type": "action", "content": "salue tout le monde" })).await;
+        let texts = drain(&mut bob, Duration::from_millis(200)).await;
+        assert!(texts.iter().any(|text| text.contains(r#""message_type":"Action""#)), "{:?}", texts);
+        let received = chat_messages(&texts);
+        assert_eq!(received.len(), 1);
+        assert!(matches!(received[0].message_type, MessageType::Action));
+        assert_eq!((received[0].username.as_str(), received[0].content.as_str()), ("ana", "salue tout le monde"));
+    }
+
+    #[tokio::test]
+    async fn garbage_frame_does_not_drop_the_connection() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+        drain(&mut bob, Duration::from_millis(200)).await;
+
+        bob.send(Message::Text("{pas du json".to_string())).await.unwrap();
+        send_json(&mut bob, serde_json::json!({ "type": "message", "content": "toujours là" })).await;
+
+        let to_bob = drain(&mut bob, Duration::from_millis(200)).await;
+        assert!(to_bob.iter().any(|text| text.contains("JSON invalide")));
+        let to_ana = drain(&mut ana, Duration::from_millis(200)).await;
+        assert_eq!(chat_messages(&to_ana)[0].content, "toujours là");
+        assert!(!to_ana.iter().any(|text| text.contains("bob a quitté")));
+    }
+
+    #[tokio::test]
+    async fn switching_rooms_replays_the_new_backlog() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        send_json(&mut ana, serde_json::json!({ "type": "switch", "room": "dev" })).await;
+        for content in ["backlog 1", "backlog 2"] {
+            send_json(&mut ana, serde_json::json!({ "type": "message", "content": content })).await;
+        }
+        let mut carol = join(addr, "carol").await;
+        let mut bob = join(addr, "bob").await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+        drain(&mut carol, Duration::from_millis(200)).await;
+        drain(&mut bob, Duration::from_millis(200)).await;
+
+        send_json(&mut bob, serde_json::json!({ "type": "switch", "room": "dev" })).await;
+        let to_bob = drain(&mut bob, Duration::from_millis(200)).await;
+        let backlog: Vec<String> = chat_messages(&to_bob).into_iter().map(|message| message.content).collect();
+        assert_eq!(backlog, ["backlog 1", "backlog 2"]);
+        assert!(drain(&mut ana, Duration::from_millis(200)).await.iter().any(|text| text.contains("bob a rejoint le salon")));
+        assert!(drain(&mut carol, Duration::from_millis(200)).await.iter().any(|text| text.contains("bob a quitté le salon")));
+
+        // Plus rien de general une fois parti
+        send_json(&mut carol, serde_json::json!({ "type": "message", "content": "ici general" })).await;
+        assert!(!drain(&mut bob, Duration::from_millis(200)).await.iter().any(|text| text.contains("ici general")));
+    }
+
+    #[tokio::test]
+    async fn room_list_counts_members_and_skips_empty_rooms() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        let mut carol = join(addr, "carol").await;
+        send_json(&mut bob, serde_json::json!({ "type": "switch", "room": "dev" })).await;
+        send_json(&mut carol, serde_json::json!({ "type": "switch", "room": "vide" })).await;
+        send_json(&mut carol, serde_json::json!({ "type": "switch", "room": "dev" })).await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+
+        send_json(&mut ana, serde_json::json!({ "type": "rooms" })).await;
+        let reply = drain(&mut ana, Duration::from_millis(200)).await
+            .into_iter()
+            .filter_map(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .find(|value| value["type"] == "roomlist")
+            .unwrap();
+        assert_eq!(reply["rooms"], serde_json::json!([
+            { "name": "dev", "users": 2, "spectators": 0 },
+            { "name": "general", "users": 1, "spectators": 0 },
+        ]));
+    }
+
+    #[test]
+    fn args_parse_from_a_fake_argv() {
+        let args = Args::try_parse_from(["server"]).unwrap();
+        assert_eq!(args.bind, "127.0.0.1:8080");
+        assert_eq!(args.channel_capacity, DEFAULT_CHANNEL_CAPACITY);
+        assert!(!args.no_emoji && args.idle_timeout.is_none());
+
+        let args = Args::try_parse_from(["server", "--bind", "0.0.0.0:9000", "--no-emoji", "--idle-timeout", "30"]).unwrap();
+        assert_eq!(args.bind, "0.0.0.0:9000");
+        assert!(args.no_emoji);
+        assert_eq!(args.idle_timeout, Some(30));
+        assert_eq!(Args::try_parse_from(["server", "-b", "[::1]:0"]).unwrap().bind, "[::1]:0");
+        assert!(Args::try_parse_from(["server", "--channel-capacity", "beaucoup"]).is_err());
+    }
+
+    /// Requête HTTP brute ; retourne la ligne de statut et le corps
+    async fn http_get(addr: SocketAddr, path: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    #[tokio::test]
+    async fn health_endpoint_reports_clients_as_json() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+
+        let (status, body) = http_get(addr, "/health").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let health: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(health["status"], "ok");
+        assert_eq!(health["clients"], 1);
+        assert!(health["uptime_secs"].is_u64());
+
+        let (status, _) = http_get(addr, "/ailleurs").await;
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        // Le chat continue de fonctionner à côté
+        send_json(&mut ana, serde_json::json!({ "type": "message", "content": "encore là" })).await;
+        assert_eq!(chat_messages(&drain(&mut ana, Duration::from_millis(200)).await)[0].content, "encore là");
+    }
+
+    #[tokio::test]
+    async fn oversized_frame_closes_the_connection() {
+        let mut state = ServerState::new();
+        state.max_message_size = 1024;
+        let addr = start_server(state).await;
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+
+        let content = "x".repeat(4096);
+        send_json(&mut bob, serde_json::json!({ "type": "message", "content": content })).await;
+        let frame = loop {
+            match tokio::time::timeout(Duration::from_secs(5), bob.next()).await {
+                Ok(Some(Ok(Message::Close(frame)))) => break frame.unwrap(),
+                Ok(Some(Ok(_))) => continue,
+                other => panic!("trame Close attendue, reçu {:?}", other),
+            }
+        };
+        assert_eq!(frame.code, CloseCode::Size);
+
+        let to_ana = drain(&mut ana, Duration::from_millis(500)).await;
+        assert!(to_ana.iter().any(|text| text.contains("bob a quitté le chat")));
+        assert!(!to_ana.iter().any(|text| text.contains("xxxx")));
+    }
+
+    #[tokio::test]
+    async fn away_status_is_listed_and_answers_mentions() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        send_json(&mut bob, serde_json::json!({ "type": "status", "state": "away", "note": "déjeuner" })).await;
+        assert!(drain(&mut bob, Duration::from_millis(200)).await.iter().any(|text| text.contains("Vous êtes absent : déjeuner")));
+        drain(&mut ana, Duration::from_millis(200)).await;
+
+        send_json(&mut ana, serde_json::json!({ "type": "users" })).await;
+        let reply = drain(&mut ana, Duration::from_millis(200)).await
+            .into_iter()
+            .filter_map(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .find(|value| value["type"] == "userlist")
+            .unwrap();
+        assert_eq!(reply["users"], serde_json::json!([
+            { "username": "ana", "away": null },
+            { "username": "bob", "away": "déjeuner" },
+        ]));
+
+        send_json(&mut ana, serde_json::json!({ "type": "message", "content": "@bob, tu es là ?" })).await;
+        assert!(drain(&mut ana, Duration::from_millis(200)).await.iter().any(|text| text.contains("bob est absent : déjeuner")));
+
+        // De retour : plus de réponse automatique
+        send_json(&mut bob, serde_json::json!({ "type": "status", "state": "available" })).await;
+        drain(&mut bob, Duration::from_millis(200)).await;
+        send_json(&mut ana, serde_json::json!({ "type": "message", "content": "@bob re" })).await;
+        assert!(!drain(&mut ana, Duration::from_millis(200)).await.iter().any(|text| text.contains("est absent")));
+    }
+
+    #[tokio::test]
+    async fn metrics_endpoint_lists_the_counters() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        send_json(&mut ana, serde_json::json!({ "type": "message", "content": "un" })).await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+
+        let (status, body) = http_get(addr, "/metrics").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        for name in ["chat_connected_clients", "chat_messages_total", "chat_uptime_seconds"] {
+            assert!(body.contains(&format!("# TYPE {} ", name)), "{} absent de\n{}", name, body);
+        }
+        assert!(body.lines().any(|line| line == "chat_connected_clients 1"));
+        assert!(body.lines().any(|line| line == "chat_messages_total 1"));
+
+        let (status, _) = http_get(addr, "/metrics/extra").await;
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    }
+
+    #[tokio::test]
+    async fn messages_in_a_room_get_consecutive_seq_numbers() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+        for content in ["un", "deux"] {
+            send_json(&mut ana, serde_json::json!({ "type": "message", "content": content })).await;
+        }
+        let seqs: Vec<u64> = chat_messages(&drain(&mut ana, Duration::from_millis(200)).await)
+            .iter()
+            .map(|message| message.seq)
+            .collect();
+        assert_eq!(seqs.len(), 2);
+        assert_eq!(seqs[1], seqs[0] + 1);
+
+        // Envois concurrents : diffusés dans l'ordre de leurs numéros, sans trou
+        let state = Arc::new(ServerState::new());
+        let mut rx = state.broadcast_tx.subscribe();
+        let senders: Vec<_> = (0..20)
+            .map(|i| {
+                let state = Arc::clone(&state);
+                tokio::spawn(async move {
+                    state.broadcast_message(ChatMessage::new("ana", i.to_string(), "dev", MessageType::Text)).await;
+                })
+            })
+            .collect();
+        for sender in senders {
+            sender.await.unwrap();
+        }
+        for expected in 1..=20 {
+            assert_eq!(rx.recv().await.unwrap().seq, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn reaction_is_broadcast_with_message_id_and_emoji() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        drain(&mut ana, Duration::from_millis(200)).await;
+        send_json(&mut ana, serde_json::json!({ "type": "message", "content": "bonne nouvelle" })).await;
+        let message_id = chat_messages(&drain(&mut bob, Duration::from_millis(200)).await)[0].id.clone();
+        drain(&mut ana, Duration::from_millis(200)).await;
+
+        send_json(&mut bob, serde_json::json!({ "type": "react", "message_id": message_id, "emoji": ":thumbsup:" })).await;
+        let reaction = drain(&mut ana, Duration::from_millis(200)).await
+            .into_iter()
+            .filter_map(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .find(|value| value["type"] == "reaction")
+            .unwrap();
+        assert_eq!(reaction["message_id"], message_id.as_str());
+        assert_eq!(reaction["emoji"], "👍");
+        assert_eq!(reaction["username"], "bob");
+        assert_eq!(reaction["count"], 1);
+        drain(&mut bob, Duration::from_millis(200)).await;
+
+        send_json(&mut bob, serde_json::json!({ "type": "react", "message_id": message_id, "emoji": "pas un emoji" })).await;
+        assert!(drain(&mut bob, Duration::from_millis(200)).await.iter().any(|text| text.contains("Réaction invalide")));
+        send_json(&mut bob, serde_json::json!({ "type": "react", "message_id": "inconnu", "emoji": "👍" })).await;
+        assert!(drain(&mut bob, Duration::from_millis(200)).await.iter().any(|text| text.contains("Message inconnu introuvable")));
+    }
+
+    #[tokio::test]
+    async fn spectator_is_refused_but_still_receives() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        let (mut watcher, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+        send_json(&mut watcher, serde_json::json!({ "type": "join", "username": "tableau", "readonly": true })).await;
+        assert!(drain(&mut watcher, Duration::from_millis(200)).await.iter().any(|text| text.contains("lecture seule")));
+        // Arrivée non annoncée au salon
+        assert!(!drain(&mut ana, Duration::from_millis(200)).await.iter().any(|text| text.contains("tableau")));
+
+        send_json(&mut watcher, serde_json::json!({ "type": "message", "content": "je peux ?" })).await;
+        assert!(drain(&mut watcher, Duration::from_millis(200)).await.iter().any(|text| text.contains("Mode spectateur")));
+        assert!(!drain(&mut ana, Duration::from_millis(200)).await.iter().any(|text| text.contains("je peux ?")));
+
+        send_json(&mut ana, serde_json::json!({ "type": "message", "content": "bienvenue" })).await;
+        assert_eq!(chat_messages(&drain(&mut watcher, Duration::from_millis(200)).await)[0].content, "bienvenue");
+
+        let (_, body) = http_get(addr, "/health").await;
+        let health: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!((health["clients"].as_u64(), health["spectators"].as_u64()), (Some(1), Some(1)));
+    }
+
+    #[tokio::test]
+    async fn padded_long_message_is_cleaned_before_delivery() {
+        let mut state = ServerState::new();
+        state.content_pipeline = vec![trim_step(), truncate_step(10)];
+        let addr = start_server(state).await;
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        drain(&mut bob, Duration::from_millis(200)).await;
+
+        send_json(&mut ana, serde_json::json!({ "type": "message", "content": "   abcdefghijklmnop   " })).await;
+        // Un message vide une fois nettoyé n'est pas diffusé
+        send_json(&mut ana, serde_json::json!({ "type": "message", "content": "    " })).await;
+        let received = chat_messages(&drain(&mut bob, Duration::from_millis(200)).await);
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].content, "abcdefghij");
+
+        // Chaîne par défaut : contrôles retirés, grossièretés masquées, puis troncature
+        let state = ServerState { content_pipeline: content_pipeline(12, true), ..ServerState::new() };
+        assert_eq!(state.sanitize_content(" \u{7}Merde, alors !  "), "*****, alors");
+    }
+
+    #[tokio::test]
+    async fn history_request_returns_the_last_messages_in_order() {
+        let addr = start_server(ServerState::new()).await;
+        let mut ana = join(addr, "ana").await;
+        let mut bob = join(addr, "bob").await;
+        for content in ["un", "deux", "trois", "quatre"] {
+            send_json(&mut ana, serde_json::json!({ "type": "message", "content": content })).await;
+        }
+        drain(&mut ana, Duration::from_millis(200)).await;
+        drain(&mut bob, Duration::from_millis(200)).await;
+
+        send_json(&mut bob, serde_json::json!({ "type": "history", "limit": 3 })).await;
+        let to_bob = drain(&mut bob, Duration::from_millis(200)).await;
+        let reply = to_bob
+            .iter()
+            .filter_map(|text| serde_json::from_str::<serde_json::Value>(text).ok())
+            .find(|value| value["type"] == "history")
+            .unwrap();
+        assert_eq!(reply["room"], DEFAULT_ROOM);
+        let contents: Vec<&str> = reply["messages"].as_array().unwrap().iter().map(|m| m["content"].as_str().unwrap()).collect();
+        assert_eq!(contents, ["deux", "trois", "quatre"]);
+        // Réponse réservée au demandeur
+        assert!(!drain(&mut ana, Duration::from_millis(200)).await.iter().any(|text| text.contains(r#""type":"history""#)));
+
+        // Sans limite : tout ce qui est gardé (messages de chat seulement)
+        send_json(&mut bob, serde_json::json!({ "type": "history" })).await;
+        let reply = drain(&mut bob, Duration::from_millis(200)).await
+            .into_iter()
+            .filter_map(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .find(|value| value["type"] == "history")
+            .unwrap();
+        assert_eq!(reply["messages"].as_array().unwrap().len(), 4);
+    }
+}